@@ -103,6 +103,8 @@ const PrinterCard: React.FC<PrinterCardProps> = ({
     switch (status) {
       case 'idle':
         return <Wifi {...iconProps} />;
+      case 'preparing':
+        return <Loader2 {...iconProps} className="w-3 h-3 mr-1 animate-spin" />;
       case 'printing':
         return <Play {...iconProps} />;
       case 'paused':
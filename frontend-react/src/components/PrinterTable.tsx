@@ -143,6 +143,8 @@ const PrinterTable: React.FC<PrinterTableProps> = ({
     switch (status) {
       case 'idle':
         return <Wifi {...iconProps} />;
+      case 'preparing':
+        return <Loader2 {...iconProps} className="w-4 h-4 animate-spin" />;
       case 'printing':
         return <Play {...iconProps} />;
       case 'paused':
@@ -162,6 +164,8 @@ const PrinterTable: React.FC<PrinterTableProps> = ({
     switch (status) {
       case 'idle':
         return 'bg-blue-100 text-blue-800 dark:bg-blue-900 dark:text-blue-200';
+      case 'preparing':
+        return 'bg-teal-100 text-teal-800 dark:bg-teal-900 dark:text-teal-200';
       case 'printing':
         return 'bg-green-100 text-green-800 dark:bg-green-900 dark:text-green-200';
       case 'paused':
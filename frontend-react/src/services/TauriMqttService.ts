@@ -171,17 +171,41 @@ export class TauriMqttService {
       lastUpdate: new Date(
         backendPrinter.last_update || backendPrinter.lastUpdate || Date.now()
       ),
+      calibrationStage: backendPrinter.calibration_stage ?? undefined,
+      bedType: backendPrinter.bed_type ?? undefined,
+      activeTray: backendPrinter.active_tray ?? undefined,
+      externalSpoolActive: backendPrinter.external_spool_active ?? false,
+      detection: backendPrinter.detection ?? undefined,
+      aiDetectionEnabled: backendPrinter.ai_detection_enabled ?? undefined,
+      deviceLinks: backendPrinter.device_links
+        ? {
+            ahb: backendPrinter.device_links.ahb ?? undefined,
+            rfid: backendPrinter.device_links.rfid ?? undefined,
+          }
+        : undefined,
+      tags: backendPrinter.tags ?? [],
+      location: backendPrinter.location ?? undefined,
+      printStart: backendPrinter.print_start
+        ? new Date(backendPrinter.print_start)
+        : undefined,
     };
 
     // Convert print object field names from snake_case to camelCase
     if (backendPrinter.print) {
       converted.print = {
         progress: backendPrinter.print.progress,
+        progressSource: backendPrinter.print.progress_source,
         fileName: backendPrinter.print.file_name,
+        gcodeFile: backendPrinter.print.gcode_file ?? undefined,
         layerCurrent: backendPrinter.print.layer_current,
         layerTotal: backendPrinter.print.layer_total,
         timeRemaining: backendPrinter.print.time_remaining,
         estimatedTotalTime: backendPrinter.print.estimated_total_time,
+        speedMagnitude: backendPrinter.print.speed_magnitude ?? undefined,
+        printStage: backendPrinter.print.print_stage ?? undefined,
+        subStage: backendPrinter.print.sub_stage ?? undefined,
+        elapsedSeconds: backendPrinter.print.elapsed_seconds ?? undefined,
+        pauseReason: backendPrinter.print.pause_reason ?? undefined,
       };
     }
 
@@ -190,10 +214,23 @@ export class TauriMqttService {
       converted.filament = {
         type: backendPrinter.filament.type || backendPrinter.filament['r#type'],
         color: backendPrinter.filament.color,
+        textOnColor: backendPrinter.filament.text_on_color,
         remaining: backendPrinter.filament.remaining,
       };
     }
 
+    if (Array.isArray(backendPrinter.filament_slots)) {
+      converted.filamentSlots = backendPrinter.filament_slots.map((slot: any) => ({
+        index: slot.index,
+        amsId: slot.ams_id ?? undefined,
+        trayId: slot.tray_id,
+        filamentType: slot.filament_type ?? undefined,
+        color: slot.color ?? undefined,
+        textOnColor: slot.text_on_color ?? undefined,
+        remaining: slot.remaining ?? undefined,
+      }));
+    }
+
     // Convert error object field names if needed
     if (backendPrinter.error) {
       converted.error = {
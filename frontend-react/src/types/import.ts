@@ -1,3 +1,5 @@
+import { ConnectionState } from './printer';
+
 export interface ImportablePrinter {
   name: string;
   model: string;
@@ -101,7 +103,7 @@ export interface TauriPrinterData {
   serial: string;
   status?: string;
   online?: boolean;
-  connection_state?: string;
+  connection_state?: ConnectionState;
   temperatures?: {
     nozzle: number;
     bed: number;
@@ -1,24 +1,73 @@
+export type ConnectionState =
+  | 'connecting'
+  | 'connected'
+  | 'failed'
+  | 'stale'
+  | 'auth_failed'
+  | 'reconnecting'
+  | 'paused'
+  | 'unreachable';
+
+export type HeatingState = 'heating' | 'cooling' | 'stable' | 'off';
+
 export interface PrinterTemperatures {
   nozzle: number;
   bed: number;
   chamber: number;
+  nozzle_target?: number;
+  bed_target?: number;
+  nozzle_state?: HeatingState;
+  bed_state?: HeatingState;
 }
 
 export interface PrintJob {
   progress: number;
+  progressSource?: 'mc_percent' | 'layers' | 'unavailable';
   fileName: string;
+  gcodeFile?: string;
   layerCurrent: number;
   layerTotal: number;
   timeRemaining: number;
   estimatedTotalTime: number;
+  speedMagnitude?: number;
+  /** `mc_print_stage`: 1 idle, 2 running the job (including the post-print cooldown) */
+  printStage?: number;
+  subStage?: number;
+  elapsedSeconds?: number;
+  pauseReason?: string;
+}
+
+export interface PrinterCapabilities {
+  has_ams: boolean;
+  has_chamber_fan: boolean;
+  has_chamber_temp: boolean;
+  has_camera: boolean;
+  max_nozzle_temp: number;
+}
+
+export interface CameraStatus {
+  recording: boolean;
+  timelapse: boolean;
+  resolution: string | null;
 }
 
 export interface FilamentInfo {
   type: string;
   color: string;
+  textOnColor?: string;
   remaining: number;
 }
 
+export interface FilamentSlot {
+  index: number;
+  amsId?: number;
+  trayId: number;
+  filamentType?: string;
+  color?: string;
+  textOnColor?: string;
+  remaining?: number;
+}
+
 export interface PrinterError {
   printError: number;
   errorCode: number;
@@ -30,11 +79,13 @@ export interface PrinterError {
 
 export type PrinterStatus =
   | 'idle'
+  | 'preparing'
   | 'printing'
   | 'paused'
   | 'error'
   | 'offline'
-  | 'connecting';
+  | 'connecting'
+  | 'disabled';
 
 export interface Printer {
   id: string;
@@ -47,7 +98,20 @@ export interface Printer {
   temperatures: PrinterTemperatures;
   print: PrintJob | null;
   filament: FilamentInfo | null;
+  filamentSlots?: FilamentSlot[];
   error: PrinterError | null;
+  capabilities?: PrinterCapabilities;
+  camera?: CameraStatus | null;
+  bedType?: string;
+  activeTray?: number;
+  externalSpoolActive?: boolean;
+  detection?: 'first_layer_ok' | 'first_layer_defect' | 'spaghetti_detected';
+  aiDetectionEnabled?: boolean;
+  deviceLinks?: { ahb?: boolean; rfid?: boolean };
+  tags?: string[];
+  location?: string;
+  calibrationStage?: string;
+  printStart?: Date;
   lastUpdate: Date;
 }
 
@@ -98,8 +162,17 @@ export interface TauriPrintJobData {
   layer_total: number;
   speed_level?: number;
   fan_speed?: number;
+  fan_speeds?: TauriFanSpeedsData;
   stage?: number;
   lifecycle?: string;
+  filament_weight?: number;
+  filament_length?: number;
+}
+
+export interface TauriFanSpeedsData {
+  part: number;
+  aux: number;
+  chamber: number;
 }
 
 export interface TauriFilamentData {
@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// One MQTT message as written to a capture file, one per line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureRecord {
+	pub timestamp: DateTime<Utc>,
+	pub topic: String,
+	/// The payload as JSON, or as a string when the printer sent something
+	/// that doesn't parse, so malformed messages are kept too
+	pub payload: serde_json::Value,
+}
+
+impl CaptureRecord {
+	fn new(topic: &str, payload: &[u8]) -> Self {
+		Self {
+			timestamp: Utc::now(),
+			topic: topic.to_string(),
+			payload: serde_json::from_slice(payload).unwrap_or_else(|_| {
+				serde_json::Value::String(String::from_utf8_lossy(payload).into_owned())
+			}),
+		}
+	}
+
+	/// The payload as bytes to parse again, equivalent to what the printer sent.
+	pub fn payload_bytes(&self) -> Vec<u8> {
+		match &self.payload {
+			serde_json::Value::String(raw) => raw.clone().into_bytes(),
+			payload => payload.to_string().into_bytes(),
+		}
+	}
+}
+
+/// Printers whose raw MQTT messages are being appended to a capture file,
+/// as newline-delimited `CaptureRecord`s.
+#[derive(Clone, Default)]
+pub struct Captures {
+	files: Arc<Mutex<HashMap<String, LineWriter<File>>>>,
+	// Checked before taking the lock, so messages cost nothing extra while
+	// nothing is being captured
+	active: Arc<AtomicUsize>,
+}
+
+impl Captures {
+	/// Starts appending `printer_id`'s messages to `path`, replacing any
+	/// capture already running for it.
+	pub fn start(&self, printer_id: &str, path: &Path) -> Result<()> {
+		let file = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(path)
+			.map_err(|e| anyhow!("Failed to open capture file {}: {e}", path.display()))?;
+
+		let mut files = self.files.lock().unwrap();
+		files.insert(printer_id.to_string(), LineWriter::new(file));
+		self.active.store(files.len(), Ordering::Relaxed);
+		info!(
+			"Capturing MQTT messages for {printer_id} to {}",
+			path.display()
+		);
+		Ok(())
+	}
+
+	/// Stops the capture for `printer_id`, returning whether one was running.
+	pub fn stop(&self, printer_id: &str) -> bool {
+		let mut files = self.files.lock().unwrap();
+		let stopped = files.remove(printer_id);
+		self.active.store(files.len(), Ordering::Relaxed);
+		if let Some(mut file) = stopped {
+			if let Err(e) = file.flush() {
+				error!("Failed to flush capture for {printer_id}: {e}");
+			}
+			return true;
+		}
+		false
+	}
+
+	/// Appends a message if `printer_id` is being captured. A write error
+	/// ends the capture rather than failing every message after it.
+	pub fn record(&self, printer_id: &str, topic: &str, payload: &[u8]) {
+		if self.active.load(Ordering::Relaxed) == 0 {
+			return;
+		}
+
+		let mut files = self.files.lock().unwrap();
+		let Some(file) = files.get_mut(printer_id) else {
+			return;
+		};
+		let record = CaptureRecord::new(topic, payload);
+		let written = serde_json::to_writer(&mut *file, &record)
+			.map_err(std::io::Error::from)
+			.and_then(|_| file.write_all(b"\n"));
+		if let Err(e) = written {
+			error!("Failed to write capture for {printer_id}, stopping it: {e}");
+			files.remove(printer_id);
+			self.active.store(files.len(), Ordering::Relaxed);
+		}
+	}
+
+	/// Reads back a capture file written by `record`. Blank lines are
+	/// skipped; anything else that doesn't parse is an error.
+	pub fn read(path: &Path) -> Result<Vec<CaptureRecord>> {
+		let contents = std::fs::read_to_string(path)
+			.map_err(|e| anyhow!("Failed to read capture file {}: {e}", path.display()))?;
+		contents
+			.lines()
+			.enumerate()
+			.filter(|(_, line)| !line.trim().is_empty())
+			.map(|(index, line)| {
+				serde_json::from_str(line).map_err(|e| {
+					anyhow!(
+						"Bad record on line {} of {}: {e}",
+						index + 1,
+						path.display()
+					)
+				})
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_capture_round_trip() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("printer.ndjson");
+		let captures = Captures::default();
+
+		// Nothing is written before the capture starts
+		captures.record("p1", "device/SERIAL/report", br#"{"print":{}}"#);
+		captures.start("p1", &path).unwrap();
+		captures.record(
+			"p1",
+			"device/SERIAL/report",
+			br#"{"print":{"mc_percent":5}}"#,
+		);
+		captures.record("p2", "device/OTHER/report", br#"{"print":{}}"#);
+		captures.record("p1", "device/SERIAL/report", b"{not json");
+		assert!(captures.stop("p1"));
+		assert!(!captures.stop("p1"));
+		captures.record("p1", "device/SERIAL/report", br#"{"print":{}}"#);
+
+		let records = Captures::read(&path).unwrap();
+		assert_eq!(records.len(), 2);
+		assert_eq!(records[0].topic, "device/SERIAL/report");
+		assert_eq!(
+			records[0].payload,
+			serde_json::json!({ "print": { "mc_percent": 5 } })
+		);
+		assert_eq!(records[1].payload_bytes(), b"{not json");
+	}
+
+	#[test]
+	fn test_read_rejects_bad_lines() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("broken.ndjson");
+		std::fs::write(&path, "\n{\"oops\": true}\n").unwrap();
+
+		let error = Captures::read(&path).unwrap_err();
+		assert!(error.to_string().contains("line 2"));
+	}
+}
@@ -1,13 +1,35 @@
-use crate::mqtt::{MqttService, PrintCommand, Printer, PrinterConfig};
+use crate::database::{CommandHistory, CommandHistoryEntry, PrinterStateStore};
+use crate::ftps::PrinterFile;
+use crate::hms::ErrorInfo;
+use crate::logging;
+use crate::mqtt::{
+	BatchCommandResult, CalibrationOptions, ConnectionMetrics, Diagnostics, MirrorConfig,
+	MqttService, PrintCommand, Printer, PrinterConfig, PrinterFilter, PrinterImportResult,
+	PrinterStatus, ProgressEstimate, ServiceSummary, StartPrintOptions,
+};
+use std::path::Path;
+use std::time::Duration;
 use tauri::State;
 
 #[tauri::command]
 pub async fn add_printer(
 	mqtt_service: State<'_, MqttService>,
 	config: PrinterConfig,
+	require_connection: Option<bool>,
 ) -> Result<(), String> {
 	mqtt_service
-		.add_printer(config)
+		.add_printer(config, require_connection.unwrap_or(false))
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_printer(
+	mqtt_service: State<'_, MqttService>,
+	config: PrinterConfig,
+) -> Result<(), String> {
+	mqtt_service
+		.update_printer(config)
 		.await
 		.map_err(|e| e.to_string())
 }
@@ -23,6 +45,74 @@ pub async fn remove_printer(
 		.map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn rename_printer(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	name: String,
+) -> Result<(), String> {
+	mqtt_service
+		.rename_printer(&printer_id, &name)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn prune_offline_printers(
+	mqtt_service: State<'_, MqttService>,
+	older_than_secs: u64,
+) -> Result<Vec<String>, String> {
+	mqtt_service
+		.prune_offline_printers(older_than_secs)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_printers(
+	mqtt_service: State<'_, MqttService>,
+	include_access_codes: bool,
+) -> Result<String, String> {
+	mqtt_service
+		.export_printers(include_access_codes)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn import_printers(
+	mqtt_service: State<'_, MqttService>,
+	json: String,
+) -> Result<Vec<PrinterImportResult>, String> {
+	mqtt_service
+		.import_printers(&json)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_monitoring_enabled(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	enabled: bool,
+) -> Result<(), String> {
+	mqtt_service
+		.set_monitoring_enabled(&printer_id, enabled)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn reconnect_printer(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<(), String> {
+	mqtt_service
+		.reconnect_printer(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn get_all_printers(
 	mqtt_service: State<'_, MqttService>,
@@ -31,46 +121,485 @@ pub async fn get_all_printers(
 }
 
 #[tauri::command]
-pub async fn send_printer_command(
+pub async fn get_printers_since(
+	mqtt_service: State<'_, MqttService>,
+	version: u64,
+) -> Result<(u64, Vec<Printer>), String> {
+	Ok(mqtt_service.get_printers_since(version).await)
+}
+
+#[tauri::command]
+pub async fn query_printers(
+	mqtt_service: State<'_, MqttService>,
+	filter: PrinterFilter,
+) -> Result<Vec<Printer>, String> {
+	Ok(mqtt_service.query_printers(&filter).await)
+}
+
+#[tauri::command]
+pub async fn get_service_summary(
+	mqtt_service: State<'_, MqttService>,
+) -> Result<ServiceSummary, String> {
+	Ok(mqtt_service.get_service_summary().await)
+}
+
+#[tauri::command]
+pub async fn get_diagnostics(mqtt_service: State<'_, MqttService>) -> Result<Diagnostics, String> {
+	Ok(mqtt_service.get_diagnostics().await)
+}
+
+#[tauri::command]
+pub async fn get_print_thumbnail(
 	mqtt_service: State<'_, MqttService>,
 	printer_id: String,
-	command: PrintCommand,
+) -> Result<Vec<u8>, String> {
+	mqtt_service
+		.get_print_thumbnail(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_printer_files(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<Vec<PrinterFile>, String> {
+	mqtt_service
+		.list_printer_files(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn start_capture(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	path: String,
 ) -> Result<(), String> {
 	mqtt_service
-		.send_command(&printer_id, command)
+		.start_capture(&printer_id, Path::new(&path))
 		.await
 		.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn pause_printer(
+pub async fn stop_capture(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<(), String> {
+	mqtt_service
+		.stop_capture(&printer_id)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn replay_capture(
+	mqtt_service: State<'_, MqttService>,
+	path: String,
+	printer_id: Option<String>,
+) -> Result<usize, String> {
+	mqtt_service
+		.replay_capture(Path::new(&path), printer_id.as_deref())
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn force_refresh(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<(), String> {
+	mqtt_service
+		.force_refresh(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_print_progress_estimate(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<ProgressEstimate, String> {
+	mqtt_service
+		.get_print_progress_estimate(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_debug_bundle(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<String, String> {
+	mqtt_service
+		.export_debug_bundle(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_raw_state(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<serde_json::Value, String> {
+	mqtt_service
+		.get_raw_state(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_connection_metrics(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<ConnectionMetrics, String> {
+	mqtt_service
+		.get_connection_metrics(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn send_printer_command(
 	mqtt_service: State<'_, MqttService>,
 	printer_id: String,
+	command: PrintCommand,
+	dry_run: Option<bool>,
 ) -> Result<(), String> {
-	let command = PrintCommand {
-		action: "pause".to_string(),
+	let result = if dry_run.unwrap_or(false) {
+		mqtt_service.validate_command(&printer_id, &command).await
+	} else {
+		mqtt_service.send_command(&printer_id, command).await
 	};
-	send_printer_command(mqtt_service, printer_id, command).await
+	result.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_pending_commands(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<Vec<String>, String> {
+	Ok(mqtt_service.get_pending_commands(&printer_id))
+}
+
+#[tauri::command]
+pub async fn cancel_pending_commands(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<usize, String> {
+	Ok(mqtt_service.cancel_pending_commands(&printer_id))
+}
+
+#[tauri::command]
+pub async fn pause_printer(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	dry_run: Option<bool>,
+) -> Result<(), String> {
+	let command = PrintCommand::new("pause");
+	send_printer_command(mqtt_service, printer_id, command, dry_run).await
 }
 
 #[tauri::command]
 pub async fn resume_printer(
 	mqtt_service: State<'_, MqttService>,
 	printer_id: String,
+	dry_run: Option<bool>,
 ) -> Result<(), String> {
-	let command = PrintCommand {
-		action: "resume".to_string(),
-	};
-	send_printer_command(mqtt_service, printer_id, command).await
+	let command = PrintCommand::new("resume");
+	send_printer_command(mqtt_service, printer_id, command, dry_run).await
 }
 
 #[tauri::command]
 pub async fn stop_printer(
 	mqtt_service: State<'_, MqttService>,
 	printer_id: String,
+	dry_run: Option<bool>,
 ) -> Result<(), String> {
-	let command = PrintCommand {
-		action: "stop".to_string(),
-	};
-	send_printer_command(mqtt_service, printer_id, command).await
+	let command = PrintCommand::new("stop");
+	send_printer_command(mqtt_service, printer_id, command, dry_run).await
+}
+
+#[tauri::command]
+pub async fn pause_all_printers(
+	mqtt_service: State<'_, MqttService>,
+) -> Result<Vec<BatchCommandResult>, String> {
+	Ok(
+		mqtt_service
+			.send_command_to_all("pause", &[PrinterStatus::Printing])
+			.await,
+	)
+}
+
+#[tauri::command]
+pub async fn resume_all_printers(
+	mqtt_service: State<'_, MqttService>,
+) -> Result<Vec<BatchCommandResult>, String> {
+	Ok(
+		mqtt_service
+			.send_command_to_all("resume", &[PrinterStatus::Paused])
+			.await,
+	)
+}
+
+#[tauri::command]
+pub async fn stop_all_printers(
+	mqtt_service: State<'_, MqttService>,
+) -> Result<Vec<BatchCommandResult>, String> {
+	Ok(
+		mqtt_service
+			.send_command_to_all(
+				"stop",
+				&[
+					PrinterStatus::Preparing,
+					PrinterStatus::Printing,
+					PrinterStatus::Paused,
+				],
+			)
+			.await,
+	)
+}
+
+#[tauri::command]
+pub async fn set_fan_speed(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	fan: String,
+	percent: u8,
+) -> Result<(), String> {
+	mqtt_service
+		.set_fan_speed(&printer_id, &fan, percent)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_chamber_fan(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	percent: u8,
+) -> Result<(), String> {
+	mqtt_service
+		.set_chamber_fan(&printer_id, percent)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_timelapse(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	enabled: bool,
+) -> Result<(), String> {
+	mqtt_service
+		.set_timelapse(&printer_id, enabled)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_temperature(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	target: String,
+	value: i32,
+) -> Result<(), String> {
+	mqtt_service
+		.set_temperature(&printer_id, &target, value)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_temperature_and_wait(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	target: String,
+	value: i32,
+	timeout_secs: u64,
+) -> Result<(), String> {
+	mqtt_service
+		.set_temperature_and_wait(&printer_id, &target, value, timeout_secs)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_ai_detection(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	enabled: bool,
+) -> Result<(), String> {
+	mqtt_service
+		.set_ai_detection(&printer_id, enabled)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn adjust_z_offset(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	delta_mm: f64,
+) -> Result<(), String> {
+	mqtt_service
+		.adjust_z_offset(&printer_id, delta_mm)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn home_printer(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<(), String> {
+	mqtt_service
+		.home_printer(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn run_calibration(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	options: CalibrationOptions,
+) -> Result<(), String> {
+	mqtt_service
+		.run_calibration(&printer_id, options)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn start_print(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	file: String,
+	options: Option<StartPrintOptions>,
+) -> Result<(), String> {
+	mqtt_service
+		.start_print(&printer_id, &file, options.unwrap_or_default())
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_print_speed(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	level: u8,
+	optimistic: Option<bool>,
+) -> Result<(), String> {
+	mqtt_service
+		.set_print_speed(&printer_id, level, optimistic.unwrap_or(false))
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_command_history(
+	command_history: State<'_, CommandHistory>,
+	printer_id: String,
+	limit: u32,
+) -> Result<Vec<CommandHistoryEntry>, String> {
+	command_history
+		.recent(&printer_id, limit)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_state_flush_interval(
+	state_store: State<'_, PrinterStateStore>,
+	interval_secs: u64,
+) -> Result<(), String> {
+	state_store
+		.set_flush_interval(Duration::from_secs(interval_secs))
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn change_filament(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	slot: u8,
+	target_temp: i32,
+) -> Result<(), String> {
+	mqtt_service
+		.change_filament(&printer_id, slot, target_temp)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn unload_filament(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<(), String> {
+	mqtt_service
+		.unload_filament(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn skip_objects(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+	object_ids: Vec<i64>,
+) -> Result<(), String> {
+	mqtt_service
+		.skip_objects(&printer_id, object_ids)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_error(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<(), String> {
+	mqtt_service
+		.clear_error(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn resume_after_error(
+	mqtt_service: State<'_, MqttService>,
+	printer_id: String,
+) -> Result<(), String> {
+	mqtt_service
+		.resume_after_error(&printer_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_mirror_broker(
+	mqtt_service: State<'_, MqttService>,
+	config: Option<MirrorConfig>,
+) -> Result<(), String> {
+	mqtt_service
+		.set_mirror_broker(config)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn lookup_error_code(code: String) -> Result<Option<ErrorInfo>, String> {
+	Ok(ErrorInfo::lookup(&code))
+}
+
+#[tauri::command]
+pub async fn set_log_level(level: String) -> Result<(), String> {
+	logging::set_level(&level).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_mqtt_file_logging(enabled: bool) -> Result<(), String> {
+	logging::set_mqtt_file_logging(enabled);
+	Ok(())
 }
@@ -1,4 +1,11 @@
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{Sqlite, SqliteArguments};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PrinterConfig {
@@ -41,5 +48,369 @@ pub struct UserPreferences {
 	pub updated_at: String,
 }
 
-// Database operations will be handled directly from the frontend using the SQL plugin
-// The Rust side will focus on printer communication and state management
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommandHistoryEntry {
+	pub id: i64,
+	pub printer_id: String,
+	pub action: String,
+	pub success: bool,
+	pub error: Option<String>,
+	pub created_at: String,
+}
+
+pub const COMMAND_HISTORY_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS command_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                printer_id TEXT NOT NULL,
+                action TEXT NOT NULL,
+                success INTEGER NOT NULL,
+                error TEXT,
+                created_at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_command_history_printer
+                ON command_history (printer_id, id);";
+
+pub const PRINTER_STATE_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS printer_states (
+                printer_id TEXT PRIMARY KEY,
+                status TEXT NOT NULL,
+                nozzle_temp REAL NOT NULL,
+                bed_temp REAL NOT NULL,
+                chamber_temp REAL NOT NULL,
+                print_progress REAL,
+                print_filename TEXT,
+                layer_current INTEGER,
+                layer_total INTEGER,
+                time_remaining INTEGER,
+                filament_type TEXT,
+                filament_color TEXT,
+                error_message TEXT,
+                error_code INTEGER,
+                last_seen TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );";
+
+/// How often changed printer states are written out, unless configured
+/// otherwise. Reports arrive several times a second, so saving each would
+/// keep SQLite busy for nothing.
+pub const DEFAULT_STATE_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+// Most database operations are handled directly from the frontend using the SQL plugin.
+// The Rust side only writes data it produces itself, such as the command log below.
+
+/// Log of commands dispatched to printers, written by the MQTT command handler.
+#[derive(Clone)]
+pub struct CommandHistory {
+	pool: SqlitePool,
+}
+
+impl CommandHistory {
+	pub fn new(pool: SqlitePool) -> Self {
+		Self { pool }
+	}
+
+	pub async fn record(&self, printer_id: &str, action: &str, error: Option<&str>) -> Result<()> {
+		sqlx::query(
+			"INSERT INTO command_history (printer_id, action, success, error, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+		)
+		.bind(printer_id)
+		.bind(action)
+		.bind(error.is_none())
+		.bind(error)
+		.bind(chrono::Utc::now().to_rfc3339())
+		.execute(&self.pool)
+		.await?;
+
+		Ok(())
+	}
+
+	/// Most recent commands for a printer, newest first.
+	pub async fn recent(&self, printer_id: &str, limit: u32) -> Result<Vec<CommandHistoryEntry>> {
+		let rows = sqlx::query(
+			"SELECT id, printer_id, action, success, error, created_at
+             FROM command_history
+             WHERE printer_id = ?1
+             ORDER BY id DESC
+             LIMIT ?2",
+		)
+		.bind(printer_id)
+		.bind(limit)
+		.fetch_all(&self.pool)
+		.await?;
+
+		rows
+			.iter()
+			.map(|row| {
+				Ok(CommandHistoryEntry {
+					id: row.try_get("id")?,
+					printer_id: row.try_get("printer_id")?,
+					action: row.try_get("action")?,
+					success: row.try_get("success")?,
+					error: row.try_get("error")?,
+					created_at: row.try_get("created_at")?,
+				})
+			})
+			.collect()
+	}
+}
+
+/// Last-known state of each printer, so after a restart the UI has something
+/// to show before the first report comes in.
+///
+/// Reports only mark a printer dirty; its state is written with the next
+/// flush, so any number of reports in between cost one write.
+#[derive(Clone)]
+pub struct PrinterStateStore {
+	pool: SqlitePool,
+	// Printers whose state changed since the last flush
+	dirty: Arc<Mutex<HashSet<String>>>,
+	flush_interval_ms: Arc<AtomicU64>,
+}
+
+impl PrinterStateStore {
+	pub fn new(pool: SqlitePool) -> Self {
+		Self {
+			pool,
+			dirty: Arc::new(Mutex::new(HashSet::new())),
+			flush_interval_ms: Arc::new(AtomicU64::new(
+				DEFAULT_STATE_FLUSH_INTERVAL.as_millis() as u64
+			)),
+		}
+	}
+
+	pub fn mark_dirty(&self, printer_id: &str) {
+		let mut dirty = self.dirty.lock().unwrap_or_else(|e| e.into_inner());
+		if !dirty.contains(printer_id) {
+			dirty.insert(printer_id.to_string());
+		}
+	}
+
+	/// Printers marked dirty since the last call, clearing the set.
+	pub fn take_dirty(&self) -> Vec<String> {
+		let mut dirty = self.dirty.lock().unwrap_or_else(|e| e.into_inner());
+		dirty.drain().collect()
+	}
+
+	pub fn flush_interval(&self) -> Duration {
+		Duration::from_millis(self.flush_interval_ms.load(Ordering::Relaxed))
+	}
+
+	/// Takes effect from the next flush on.
+	pub fn set_flush_interval(&self, interval: Duration) -> Result<()> {
+		if interval.is_zero() {
+			return Err(anyhow!("The flush interval must be greater than zero"));
+		}
+		self
+			.flush_interval_ms
+			.store(interval.as_millis() as u64, Ordering::Relaxed);
+		Ok(())
+	}
+
+	/// Writes several states in one transaction.
+	pub async fn save_all(&self, states: &[PrinterState]) -> Result<()> {
+		let mut transaction = self.pool.begin().await?;
+		for state in states {
+			Self::upsert(state).execute(&mut *transaction).await?;
+		}
+		transaction.commit().await?;
+		Ok(())
+	}
+
+	fn upsert(state: &PrinterState) -> sqlx::query::Query<'_, Sqlite, SqliteArguments<'_>> {
+		sqlx::query(
+			"INSERT OR REPLACE INTO printer_states (
+                printer_id, status, nozzle_temp, bed_temp, chamber_temp, print_progress,
+                print_filename, layer_current, layer_total, time_remaining, filament_type,
+                filament_color, error_message, error_code, last_seen, updated_at
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+		)
+		.bind(&state.printer_id)
+		.bind(&state.status)
+		.bind(state.nozzle_temp)
+		.bind(state.bed_temp)
+		.bind(state.chamber_temp)
+		.bind(state.print_progress)
+		.bind(&state.print_filename)
+		.bind(state.layer_current)
+		.bind(state.layer_total)
+		.bind(state.time_remaining)
+		.bind(&state.filament_type)
+		.bind(&state.filament_color)
+		.bind(&state.error_message)
+		.bind(state.error_code)
+		.bind(&state.last_seen)
+		.bind(&state.updated_at)
+	}
+
+	pub async fn load_all(&self) -> Result<Vec<PrinterState>> {
+		let rows = sqlx::query("SELECT * FROM printer_states")
+			.fetch_all(&self.pool)
+			.await?;
+
+		rows
+			.iter()
+			.map(|row| {
+				Ok(PrinterState {
+					printer_id: row.try_get("printer_id")?,
+					status: row.try_get("status")?,
+					nozzle_temp: row.try_get("nozzle_temp")?,
+					bed_temp: row.try_get("bed_temp")?,
+					chamber_temp: row.try_get("chamber_temp")?,
+					print_progress: row.try_get("print_progress")?,
+					print_filename: row.try_get("print_filename")?,
+					layer_current: row.try_get("layer_current")?,
+					layer_total: row.try_get("layer_total")?,
+					time_remaining: row.try_get("time_remaining")?,
+					filament_type: row.try_get("filament_type")?,
+					filament_color: row.try_get("filament_color")?,
+					error_message: row.try_get("error_message")?,
+					error_code: row.try_get("error_code")?,
+					last_seen: row.try_get("last_seen")?,
+					updated_at: row.try_get("updated_at")?,
+				})
+			})
+			.collect()
+	}
+
+	pub async fn delete(&self, printer_id: &str) -> Result<()> {
+		sqlx::query("DELETE FROM printer_states WHERE printer_id = ?1")
+			.bind(printer_id)
+			.execute(&self.pool)
+			.await?;
+		self
+			.dirty
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.remove(printer_id);
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sqlx::sqlite::SqlitePoolOptions;
+
+	async fn memory_history() -> CommandHistory {
+		// A single connection, since every `:memory:` connection is its own database
+		let pool = SqlitePoolOptions::new()
+			.max_connections(1)
+			.connect("sqlite::memory:")
+			.await
+			.unwrap();
+		sqlx::raw_sql(COMMAND_HISTORY_SCHEMA)
+			.execute(&pool)
+			.await
+			.unwrap();
+		CommandHistory::new(pool)
+	}
+
+	fn printer_state(printer_id: &str, status: &str) -> PrinterState {
+		PrinterState {
+			printer_id: printer_id.to_string(),
+			status: status.to_string(),
+			nozzle_temp: 220.0,
+			bed_temp: 55.0,
+			chamber_temp: 30.0,
+			print_progress: Some(42.0),
+			print_filename: Some("benchy".to_string()),
+			layer_current: Some(84),
+			layer_total: Some(200),
+			time_remaining: Some(4380),
+			filament_type: None,
+			filament_color: None,
+			error_message: None,
+			error_code: None,
+			last_seen: "2024-05-01T12:00:00+00:00".to_string(),
+			updated_at: "2024-05-01T12:00:00+00:00".to_string(),
+		}
+	}
+
+	#[tokio::test]
+	async fn printer_state_store_keeps_latest_state_per_printer() {
+		let pool = SqlitePoolOptions::new()
+			.max_connections(1)
+			.connect("sqlite::memory:")
+			.await
+			.unwrap();
+		sqlx::raw_sql(PRINTER_STATE_SCHEMA)
+			.execute(&pool)
+			.await
+			.unwrap();
+		let store = PrinterStateStore::new(pool);
+
+		store
+			.save_all(&[
+				printer_state("p1", "printing"),
+				printer_state("p1", "paused"),
+			])
+			.await
+			.unwrap();
+		store
+			.save_all(&[printer_state("p2", "idle")])
+			.await
+			.unwrap();
+		store.delete("p2").await.unwrap();
+
+		let states = store.load_all().await.unwrap();
+		assert_eq!(states.len(), 1);
+		assert_eq!(states[0].status, "paused");
+		assert_eq!(states[0].layer_current, Some(84));
+		assert_eq!(states[0].print_filename.as_deref(), Some("benchy"));
+
+		// Repeated updates coalesce into one pending write
+		store.mark_dirty("p1");
+		store.mark_dirty("p1");
+		store.mark_dirty("p3");
+		let mut dirty = store.take_dirty();
+		dirty.sort();
+		assert_eq!(dirty, ["p1", "p3"]);
+		assert!(store.take_dirty().is_empty());
+
+		store
+			.save_all(&[printer_state("p1", "idle"), printer_state("p3", "printing")])
+			.await
+			.unwrap();
+		let mut states = store.load_all().await.unwrap();
+		states.sort_by(|a, b| a.printer_id.cmp(&b.printer_id));
+		assert_eq!(states.len(), 2);
+		assert_eq!(states[0].status, "idle");
+		assert_eq!(states[1].status, "printing");
+
+		assert!(store.set_flush_interval(Duration::ZERO).is_err());
+		store.set_flush_interval(Duration::from_secs(2)).unwrap();
+		assert_eq!(store.flush_interval(), Duration::from_secs(2));
+	}
+
+	#[tokio::test]
+	async fn command_history_records_and_reads_back_newest_first() {
+		let history = memory_history().await;
+		history.record("p1", "pause", None).await.unwrap();
+		history
+			.record("p1", "resume", Some("MQTT publish failed"))
+			.await
+			.unwrap();
+		history.record("p2", "stop", None).await.unwrap();
+
+		let entries = history.recent("p1", 10).await.unwrap();
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].action, "resume");
+		assert!(!entries[0].success);
+		assert_eq!(entries[0].error.as_deref(), Some("MQTT publish failed"));
+		assert_eq!(entries[1].action, "pause");
+		assert!(entries[1].success);
+		assert_eq!(entries[1].error, None);
+	}
+
+	#[tokio::test]
+	async fn command_history_respects_limit() {
+		let history = memory_history().await;
+		for action in ["pause", "resume", "stop"] {
+			history.record("p1", action, None).await.unwrap();
+		}
+
+		let entries = history.recent("p1", 2).await.unwrap();
+		let actions: Vec<_> = entries.iter().map(|e| e.action.as_str()).collect();
+		assert_eq!(actions, ["stop", "resume"]);
+	}
+}
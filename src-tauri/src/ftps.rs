@@ -0,0 +1,298 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Utc};
+use log::debug;
+use rustls::pki_types::ServerName;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// Bambu printers serve their storage over implicit FTPS on this port
+pub const FTPS_PORT: u16 = 990;
+
+/// User name for LAN-mode FTPS; the password is the printer's access code
+const FTPS_USER: &str = "bblp";
+
+/// A file on the printer's storage, as listed by `FtpsClient::list`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrinterFile {
+	/// Full path on the printer, e.g. `"/cache/benchy.gcode.3mf"`
+	pub path: String,
+	pub name: String,
+	pub size: u64,
+	/// `None` when the listing's date couldn't be read
+	pub modified: Option<DateTime<Utc>>,
+}
+
+/// Just enough of an implicit-FTPS client to download files from a printer:
+/// passive mode, binary transfers, TLS on both connections.
+pub struct FtpsClient {
+	control: BufReader<TlsStream<TcpStream>>,
+	connector: TlsConnector,
+	host: String,
+}
+
+impl FtpsClient {
+	/// Connects and logs in. `tls_config` is reused for data connections, so
+	/// the server sees the TLS session resumption it requires.
+	pub async fn connect(
+		host: &str,
+		access_code: &str,
+		tls_config: Arc<rustls::ClientConfig>,
+	) -> Result<Self> {
+		let connector = TlsConnector::from(tls_config);
+		let stream = Self::open_tls(&connector, host, FTPS_PORT).await?;
+		let mut client = Self {
+			control: BufReader::new(stream),
+			connector,
+			host: host.to_string(),
+		};
+
+		client.expect(&[220]).await?;
+		client.command(&format!("USER {FTPS_USER}"), &[331]).await?;
+		client
+			.command(&format!("PASS {access_code}"), &[230])
+			.await
+			.map_err(|e| anyhow!("FTPS login failed: {e}"))?;
+		client.command("PBSZ 0", &[200]).await?;
+		client.command("PROT P", &[200]).await?;
+		client.command("TYPE I", &[200]).await?;
+
+		Ok(client)
+	}
+
+	/// Downloads a file by its path on the printer, e.g. `"/cache/benchy.3mf"`.
+	pub async fn retrieve(&mut self, path: &str) -> Result<Vec<u8>> {
+		self.transfer(&format!("RETR {path}")).await
+	}
+
+	/// Lists the files in `dir`, leaving out subdirectories and links.
+	pub async fn list(&mut self, dir: &str) -> Result<Vec<PrinterFile>> {
+		let listing = self.transfer(&format!("LIST {dir}")).await?;
+		let now = Utc::now();
+		Ok(
+			String::from_utf8_lossy(&listing)
+				.lines()
+				.filter_map(|line| parse_list_line(dir, line, now))
+				.collect(),
+		)
+	}
+
+	pub async fn quit(mut self) {
+		// The session is over either way, so a failed goodbye doesn't matter
+		let _ = self.command("QUIT", &[221]).await;
+	}
+
+	async fn transfer(&mut self, command: &str) -> Result<Vec<u8>> {
+		let (_, reply) = self.command("PASV", &[227]).await?;
+		let port = parse_pasv_port(&reply)?;
+		// Connect to the host we already know; the address in the reply may not be reachable
+		let mut data = Self::open_tls(&self.connector, &self.host, port).await?;
+
+		self.command(command, &[125, 150]).await?;
+
+		let mut contents = Vec::new();
+		match data.read_to_end(&mut contents).await {
+			Ok(_) => {}
+			// The printer closes data connections without a TLS close_notify
+			Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {}
+			Err(e) => return Err(anyhow!("FTPS transfer failed: {e}")),
+		}
+		drop(data);
+
+		self.expect(&[226, 250]).await?;
+		Ok(contents)
+	}
+
+	async fn open_tls(
+		connector: &TlsConnector,
+		host: &str,
+		port: u16,
+	) -> Result<TlsStream<TcpStream>> {
+		let server_name = ServerName::try_from(host.to_string())
+			.map_err(|e| anyhow!("Invalid FTPS host '{host}': {e}"))?;
+		let tcp = TcpStream::connect((host, port))
+			.await
+			.map_err(|e| anyhow!("Failed to connect to {host}:{port}: {e}"))?;
+		connector
+			.connect(server_name, tcp)
+			.await
+			.map_err(|e| anyhow!("TLS handshake with {host}:{port} failed: {e}"))
+	}
+
+	async fn command(&mut self, command: &str, expected: &[u16]) -> Result<(u16, String)> {
+		let shown = if command.starts_with("PASS ") {
+			"PASS ****"
+		} else {
+			command
+		};
+		debug!("FTPS > {shown}");
+
+		let stream = self.control.get_mut();
+		stream
+			.write_all(format!("{command}\r\n").as_bytes())
+			.await?;
+		stream.flush().await?;
+		self
+			.expect(expected)
+			.await
+			.map_err(|e| anyhow!("'{shown}': {e}"))
+	}
+
+	/// Reads one (possibly multi-line) reply and checks its code.
+	async fn expect(&mut self, expected: &[u16]) -> Result<(u16, String)> {
+		let mut line = String::new();
+		if self.control.read_line(&mut line).await? == 0 {
+			return Err(anyhow!("FTPS server closed the connection"));
+		}
+		let code = parse_reply_code(&line)?;
+
+		// A multi-line reply ends with the same code followed by a space
+		let mut reply = line.clone();
+		if line.as_bytes().get(3) == Some(&b'-') {
+			let end = format!("{code} ");
+			loop {
+				line.clear();
+				if self.control.read_line(&mut line).await? == 0 {
+					return Err(anyhow!("FTPS server closed the connection"));
+				}
+				reply.push_str(&line);
+				if line.starts_with(&end) {
+					break;
+				}
+			}
+		}
+
+		let reply = reply.trim_end().to_string();
+		debug!("FTPS < {reply}");
+		if !expected.contains(&code) {
+			return Err(anyhow!("unexpected FTPS reply: {reply}"));
+		}
+		Ok((code, reply))
+	}
+}
+
+fn parse_reply_code(line: &str) -> Result<u16> {
+	line
+		.get(..3)
+		.and_then(|code| code.parse().ok())
+		.ok_or_else(|| anyhow!("Malformed FTPS reply: {}", line.trim_end()))
+}
+
+/// Extracts the data port from `227 Entering Passive Mode (h1,h2,h3,h4,p1,p2)`.
+fn parse_pasv_port(reply: &str) -> Result<u16> {
+	let malformed = || anyhow!("Malformed PASV reply: {reply}");
+	let start = reply.find('(').ok_or_else(malformed)?;
+	let end = reply[start..].find(')').ok_or_else(malformed)? + start;
+	let numbers = reply[start + 1..end]
+		.split(',')
+		.map(|n| n.trim().parse::<u8>())
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|_| malformed())?;
+
+	match numbers.as_slice() {
+		[_, _, _, _, high, low] => Ok(u16::from(*high) << 8 | u16::from(*low)),
+		_ => Err(malformed()),
+	}
+}
+
+/// Parses one line of a Unix-style `LIST` reply, such as
+/// `-rw-r--r-- 1 root root 1048576 Mar 14 09:26 benchy.gcode.3mf`. Entries
+/// from the last six months give a time instead of a year, so `now` decides
+/// which year they belong to.
+fn parse_list_line(dir: &str, line: &str, now: DateTime<Utc>) -> Option<PrinterFile> {
+	let mut fields = [""; 8];
+	let mut rest = line.trim_end();
+	for field in &mut fields {
+		let trimmed = rest.trim_start();
+		let end = trimmed.find(char::is_whitespace)?;
+		*field = &trimmed[..end];
+		rest = &trimmed[end..];
+	}
+	let [permissions, _, _, _, size, month, day, time_or_year] = fields;
+	let name = rest.trim_start();
+	if !permissions.starts_with('-') || name.is_empty() {
+		return None;
+	}
+
+	let modified = if time_or_year.contains(':') {
+		let in_year = |year: i32| {
+			NaiveDateTime::parse_from_str(
+				&format!("{month} {day} {year} {time_or_year}"),
+				"%b %d %Y %H:%M",
+			)
+			.ok()
+			.map(|at| at.and_utc())
+		};
+		in_year(now.year()).and_then(|at| {
+			if at > now + chrono::Duration::days(1) {
+				in_year(now.year() - 1)
+			} else {
+				Some(at)
+			}
+		})
+	} else {
+		NaiveDate::parse_from_str(&format!("{month} {day} {time_or_year}"), "%b %d %Y")
+			.ok()
+			.and_then(|date| date.and_hms_opt(0, 0, 0))
+			.map(|at| at.and_utc())
+	};
+
+	Some(PrinterFile {
+		path: format!("{}/{name}", dir.trim_end_matches('/')),
+		name: name.to_string(),
+		size: size.parse().ok()?,
+		modified,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_pasv_port() {
+		assert_eq!(
+			parse_pasv_port("227 Entering Passive Mode (192,168,1,50,195,80).").unwrap(),
+			50000
+		);
+		assert!(parse_pasv_port("227 Entering Passive Mode").is_err());
+		assert!(parse_pasv_port("227 (192,168,1,50,195)").is_err());
+		assert!(parse_pasv_port("227 (192,168,1,50,300,1)").is_err());
+	}
+
+	#[test]
+	fn test_parse_list_line() {
+		let now = "2024-03-20T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+		let file = parse_list_line(
+			"/cache",
+			"-rw-r--r--    1 root     root      1048576 Mar 14 09:26 my benchy.gcode.3mf",
+			now,
+		)
+		.unwrap();
+		assert_eq!(file.path, "/cache/my benchy.gcode.3mf");
+		assert_eq!(file.name, "my benchy.gcode.3mf");
+		assert_eq!(file.size, 1_048_576);
+		assert_eq!(file.modified, "2024-03-14T09:26:00Z".parse().ok());
+
+		// A time in the "future" is from last year; older files give the year
+		let file = parse_list_line("/", "-rw-r--r-- 1 root root 10 Dec 01 08:00 a.gcode", now).unwrap();
+		assert_eq!(file.path, "/a.gcode");
+		assert_eq!(file.modified, "2023-12-01T08:00:00Z".parse().ok());
+		let file = parse_list_line("/", "-rw-r--r-- 1 root root 10 Jun 30  2022 b.3mf", now).unwrap();
+		assert_eq!(file.modified, "2022-06-30T00:00:00Z".parse().ok());
+
+		assert!(parse_list_line("/", "drwxr-xr-x 2 root root 4096 Mar 14 09:26 cache", now).is_none());
+		assert!(parse_list_line("/", "total 12", now).is_none());
+	}
+
+	#[test]
+	fn test_parse_reply_code() {
+		assert_eq!(parse_reply_code("230 Login successful.\r\n").unwrap(), 230);
+		assert_eq!(parse_reply_code("211-Features:\r\n").unwrap(), 211);
+		assert!(parse_reply_code("OK\r\n").is_err());
+	}
+}
@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+
+const WIKI_BASE_URL: &str = "https://wiki.bambulab.com/en/x1/troubleshooting/hmscode";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HmsSeverity {
+	Fatal,
+	Serious,
+	Common,
+	Info,
+	Unknown,
+}
+
+/// One entry from the printer's `print.hms` list (Health Management System).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HmsCode {
+	pub attr: u32,
+	pub code: u32,
+	/// Code as Bambu's app and wiki show it, e.g. `"0700_2000_0002_0001"`
+	pub id: String,
+	/// Reporting module, e.g. `"ams"` or `"mc"` (motion controller)
+	pub module: String,
+	pub severity: HmsSeverity,
+	/// Human-readable description, if the code is catalogued below
+	pub message: Option<String>,
+	pub wiki_url: String,
+}
+
+impl HmsCode {
+	pub fn new(attr: u32, code: u32) -> Self {
+		let id = format!(
+			"{:04X}_{:04X}_{:04X}_{:04X}",
+			attr >> 16,
+			attr & 0xFFFF,
+			code >> 16,
+			code & 0xFFFF
+		);
+
+		Self {
+			attr,
+			code,
+			wiki_url: format!("{WIKI_BASE_URL}/{id}"),
+			id,
+			module: module_name(attr).to_string(),
+			severity: severity(code),
+			message: describe(attr, code),
+		}
+	}
+
+	/// Parses `print.hms`; entries missing `attr` or `code` are skipped.
+	pub fn from_print_data(print_data: &serde_json::Value) -> Vec<Self> {
+		print_data
+			.get("hms")
+			.and_then(|hms| hms.as_array())
+			.map(|entries| {
+				entries
+					.iter()
+					.filter_map(|entry| {
+						let attr = entry.get("attr")?.as_u64()?;
+						let code = entry.get("code")?.as_u64()?;
+						Some(Self::new(
+							u32::try_from(attr).ok()?,
+							u32::try_from(code).ok()?,
+						))
+					})
+					.collect()
+			})
+			.unwrap_or_default()
+	}
+}
+
+/// What the app knows about a code the user typed in, looked up without
+/// any printer connected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorInfo {
+	/// Code in the `0700_2000_0002_0001` form
+	pub id: String,
+	/// `None` for codes not catalogued here; the wiki link still works
+	pub description: Option<String>,
+	pub severity: HmsSeverity,
+	pub wiki_url: String,
+}
+
+impl ErrorInfo {
+	/// Accepts the code as the printer screen and wiki show it
+	/// (`HMS_0700_2000_0002_0001`, with `_`, `-`, `.` or no separators), as
+	/// one 64-bit integer in decimal or `0x` hex, or as the `attr` and `code`
+	/// integers from an MQTT report separated by a comma or space. Returns
+	/// `None` when the input is none of these.
+	pub fn lookup(input: &str) -> Option<Self> {
+		let (attr, code) = parse_code(input)?;
+		let hms = HmsCode::new(attr, code);
+		Some(Self {
+			id: hms.id,
+			description: hms.message,
+			severity: hms.severity,
+			wiki_url: hms.wiki_url,
+		})
+	}
+}
+
+fn parse_code(input: &str) -> Option<(u32, u32)> {
+	let input = input.trim();
+	let input = match input.get(..4) {
+		Some(prefix) if prefix.eq_ignore_ascii_case("HMS_") => &input[4..],
+		_ => input,
+	};
+
+	let numbers: Vec<&str> = input
+		.split(|c: char| c == ',' || c.is_whitespace())
+		.filter(|part| !part.is_empty())
+		.collect();
+	if let [attr, code] = numbers[..] {
+		return Some((attr.parse().ok()?, code.parse().ok()?));
+	}
+
+	let hex: String = input
+		.chars()
+		.filter(|c| !matches!(c, '_' | '-' | '.'))
+		.collect();
+	let combined = if let Some(hex) = hex.strip_prefix("0x").or_else(|| hex.strip_prefix("0X")) {
+		u64::from_str_radix(hex, 16).ok()?
+	} else if hex.len() == 16 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+		u64::from_str_radix(&hex, 16).ok()?
+	} else if hex.len() == input.len() {
+		// No separators, so a plain decimal integer
+		input.parse().ok()?
+	} else {
+		return None;
+	};
+	Some(((combined >> 32) as u32, combined as u32))
+}
+
+fn module_name(attr: u32) -> &'static str {
+	match attr >> 24 {
+		0x03 => "mc",
+		0x05 => "mainboard",
+		0x07 => "ams",
+		0x08 => "toolhead",
+		0x0C => "xcam",
+		_ => "unknown",
+	}
+}
+
+fn severity(code: u32) -> HmsSeverity {
+	match code >> 16 {
+		1 => HmsSeverity::Fatal,
+		2 => HmsSeverity::Serious,
+		3 => HmsSeverity::Common,
+		4 => HmsSeverity::Info,
+		_ => HmsSeverity::Unknown,
+	}
+}
+
+/// Descriptions for the codes users run into most. Anything else still
+/// carries its `id` and wiki link, so it can be looked up by hand.
+fn describe(attr: u32, code: u32) -> Option<String> {
+	let message = match (u64::from(attr) << 32) | u64::from(code) {
+		0x0300_0100_0001_0001 => {
+			"The heatbed temperature is abnormal; the heater may be short-circuited"
+		}
+		0x0300_0300_0001_0001 => "The hotend cooling fan speed is too slow or stopped",
+		0x0C00_0300_0003_0008 => "Possible spaghetti failure detected",
+		_ => return describe_ams_runout(attr, code),
+	};
+
+	Some(message.to_string())
+}
+
+/// AMS runout codes follow a pattern: `07UU_2S00_0002_0001`, where `UU` is the
+/// unit (A, B, ...) and `S` the slot.
+fn describe_ams_runout(attr: u32, code: u32) -> Option<String> {
+	if attr >> 24 != 0x07 || attr & 0xF0FF != 0x2000 || code != 0x0002_0001 {
+		return None;
+	}
+
+	let unit = (attr >> 16) & 0xFF;
+	let slot = (attr >> 8) & 0x0F;
+	if unit > 3 || slot > 3 {
+		return None;
+	}
+
+	let unit = char::from(b'A' + unit as u8);
+	Some(format!(
+		"AMS {unit} slot {} has run out of filament",
+		slot + 1
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_hms_code_formats_id_and_link() {
+		let hms = HmsCode::new(0x0700_0100, 0x0002_0002);
+		assert_eq!(hms.id, "0700_0100_0002_0002");
+		assert_eq!(hms.module, "ams");
+		assert_eq!(hms.severity, HmsSeverity::Serious);
+		assert_eq!(
+			hms.wiki_url,
+			"https://wiki.bambulab.com/en/x1/troubleshooting/hmscode/0700_0100_0002_0002"
+		);
+		// Not catalogued, but still identifiable
+		assert_eq!(hms.message, None);
+	}
+
+	#[test]
+	fn test_hms_code_describes_catalogued_codes() {
+		let hms = HmsCode::new(0x0300_0100, 0x0001_0001);
+		assert_eq!(hms.severity, HmsSeverity::Fatal);
+		assert_eq!(
+			hms.message.as_deref(),
+			Some("The heatbed temperature is abnormal; the heater may be short-circuited")
+		);
+
+		assert_eq!(
+			HmsCode::new(0x0700_2000, 0x0002_0001).message.as_deref(),
+			Some("AMS A slot 1 has run out of filament")
+		);
+		assert_eq!(
+			HmsCode::new(0x0701_2300, 0x0002_0001).message.as_deref(),
+			Some("AMS B slot 4 has run out of filament")
+		);
+		assert_eq!(HmsCode::new(0x0700_2000, 0x0002_0002).message, None);
+	}
+
+	#[test]
+	fn test_error_info_lookup_accepts_every_form() {
+		let heatbed = ErrorInfo::lookup("HMS_0300_0100_0001_0001").unwrap();
+		assert_eq!(heatbed.id, "0300_0100_0001_0001");
+		assert_eq!(heatbed.severity, HmsSeverity::Fatal);
+		assert!(heatbed.description.is_some());
+
+		for input in [
+			"hms_0300-0100-0001-0001",
+			"0300.0100.0001.0001",
+			"0300010000010001",
+			"0x0300010000010001",
+			"216173881625477121",
+			"50331904, 65537",
+			" 50331904 65537 ",
+		] {
+			assert_eq!(ErrorInfo::lookup(input).as_ref(), Some(&heatbed), "{input}");
+		}
+
+		// Not catalogued, but still linked
+		let unknown = ErrorInfo::lookup("0700_0100_0002_0002").unwrap();
+		assert_eq!(unknown.description, None);
+		assert!(unknown.wiki_url.ends_with("0700_0100_0002_0002"));
+
+		for input in ["", "HMS_", "0300_0100", "not a code", "1, 2, 3", "0xZZ"] {
+			assert_eq!(ErrorInfo::lookup(input), None, "{input}");
+		}
+	}
+
+	#[test]
+	fn test_hms_from_print_data_skips_malformed_entries() {
+		let print_data = serde_json::json!({
+			"hms": [
+				{ "attr": 117440768, "code": 131074 },
+				{ "attr": "0700", "code": 1 },
+				{ "code": 1 }
+			]
+		});
+
+		let hms = HmsCode::from_print_data(&print_data);
+		assert_eq!(hms.len(), 1);
+		assert_eq!(hms[0].id, "0700_0100_0002_0002");
+
+		assert!(HmsCode::from_print_data(&serde_json::json!({})).is_empty());
+	}
+}
@@ -1,43 +1,172 @@
+mod capture;
 mod commands;
 mod database;
+mod ftps;
+mod hms;
+mod logging;
+mod mock;
 mod mqtt;
+mod report;
+#[cfg(test)]
+mod test_broker;
+mod thumbnail;
 
+use database::{CommandHistory, PrinterStateStore};
+use log::warn;
 use mqtt::MqttService;
 use tauri::Manager;
-use tauri_plugin_sql::{Migration, MigrationKind};
+use tauri_plugin_log::{RotationStrategy, Target, TargetKind};
+use tauri_plugin_sql::{DbInstances, DbPool, Migration, MigrationKind};
+
+const DB_URL: &str = "sqlite:pulseprint.db";
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-	// Define migrations for user preferences
-	let migrations = vec![Migration {
-		version: 1,
-		description: "create_user_preferences_table",
-		sql: "CREATE TABLE IF NOT EXISTS user_preferences (
+	// Define migrations for user preferences, command history and printer state
+	let migrations = vec![
+		Migration {
+			version: 1,
+			description: "create_user_preferences_table",
+			sql: "CREATE TABLE IF NOT EXISTS user_preferences (
                 key TEXT PRIMARY KEY,
                 value TEXT NOT NULL
             );",
-		kind: MigrationKind::Up,
-	}];
+			kind: MigrationKind::Up,
+		},
+		Migration {
+			version: 2,
+			description: "create_command_history_table",
+			sql: database::COMMAND_HISTORY_SCHEMA,
+			kind: MigrationKind::Up,
+		},
+		Migration {
+			version: 3,
+			description: "create_printer_states_table",
+			sql: database::PRINTER_STATE_SCHEMA,
+			kind: MigrationKind::Up,
+		},
+	];
 
 	tauri::Builder::default()
+		.plugin(
+			// Let everything through here; `logging::set_level` does the filtering at runtime
+			tauri_plugin_log::Builder::new()
+				.clear_targets()
+				.target(Target::new(TargetKind::Stdout))
+				.target(
+					Target::new(TargetKind::LogDir {
+						file_name: Some(logging::MQTT_LOG_FILE.to_string()),
+					})
+					.filter(logging::is_mqtt_file_record),
+				)
+				.level(log::LevelFilter::Trace)
+				.max_file_size(logging::MQTT_LOG_MAX_BYTES)
+				.rotation_strategy(RotationStrategy::KeepOne)
+				.build(),
+		)
 		.plugin(
 			tauri_plugin_sql::Builder::new()
-				.add_migrations("sqlite:pulseprint.db", migrations)
+				.add_migrations(DB_URL, migrations)
 				.build(),
 		)
 		.setup(|app| {
-			app.manage(MqttService::new(app.handle().clone()));
+			log::set_max_level(logging::DEFAULT_LEVEL);
+
+			// The SQL plugin preloads and migrates the database before setup runs
+			let pool = tauri::async_runtime::block_on(async {
+				let instances = app.state::<DbInstances>();
+				let instances = instances.0.read().await;
+				match instances.get(DB_URL) {
+					Some(DbPool::Sqlite(pool)) => Some(pool.clone()),
+					_ => None,
+				}
+			});
+			let mqtt_service = MqttService::new(app.handle().clone());
+			match pool {
+				Some(pool) => {
+					app.manage(CommandHistory::new(pool.clone()));
+
+					// Printers are added by the frontend; each picks up its saved state then
+					let store = PrinterStateStore::new(pool);
+					match tauri::async_runtime::block_on(store.load_all()) {
+						Ok(states) => tauri::async_runtime::block_on(mqtt_service.restore_last_known(states)),
+						Err(e) => warn!("Failed to load saved printer states: {e}"),
+					}
+					mqtt_service.start_state_flusher(store.clone());
+					app.manage(store);
+				}
+				None => warn!(
+					"Database {DB_URL} not loaded, command history and saved printer state are disabled"
+				),
+			}
+
+			app.manage(mqtt_service);
 			Ok(())
 		})
 		.invoke_handler(tauri::generate_handler![
 			commands::add_printer,
+			commands::update_printer,
+			commands::rename_printer,
 			commands::remove_printer,
+			commands::prune_offline_printers,
+			commands::export_printers,
+			commands::import_printers,
+			commands::set_monitoring_enabled,
+			commands::reconnect_printer,
 			commands::get_all_printers,
+			commands::get_printers_since,
+			commands::query_printers,
+			commands::get_service_summary,
+			commands::get_diagnostics,
+			commands::get_print_thumbnail,
+			commands::list_printer_files,
+			commands::start_capture,
+			commands::stop_capture,
+			commands::replay_capture,
+			commands::force_refresh,
+			commands::get_print_progress_estimate,
+			commands::get_raw_state,
+			commands::export_debug_bundle,
+			commands::get_connection_metrics,
 			commands::send_printer_command,
+			commands::get_pending_commands,
+			commands::cancel_pending_commands,
 			commands::pause_printer,
 			commands::resume_printer,
 			commands::stop_printer,
+			commands::pause_all_printers,
+			commands::resume_all_printers,
+			commands::stop_all_printers,
+			commands::set_fan_speed,
+			commands::set_chamber_fan,
+			commands::set_timelapse,
+			commands::set_temperature,
+			commands::set_temperature_and_wait,
+			commands::set_ai_detection,
+			commands::adjust_z_offset,
+			commands::home_printer,
+			commands::run_calibration,
+			commands::start_print,
+			commands::set_print_speed,
+			commands::get_command_history,
+			commands::set_state_flush_interval,
+			commands::change_filament,
+			commands::unload_filament,
+			commands::skip_objects,
+			commands::clear_error,
+			commands::resume_after_error,
+			commands::lookup_error_code,
+			commands::set_mirror_broker,
+			commands::set_log_level,
+			commands::set_mqtt_file_logging,
 		])
-		.run(tauri::generate_context!())
-		.expect("error while running tauri application");
+		.build(tauri::generate_context!())
+		.expect("error while building tauri application")
+		.run(|app_handle, event| {
+			if let tauri::RunEvent::Exit = event {
+				// Close MQTT sessions cleanly rather than leaving the printers to time them out
+				let mqtt_service = app_handle.state::<MqttService>();
+				tauri::async_runtime::block_on(mqtt_service.shutdown());
+			}
+		});
 }
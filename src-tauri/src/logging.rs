@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Result};
+use log::{LevelFilter, Metadata};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Level in effect until the user picks another with `set_log_level`
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+/// Size at which the MQTT log file is rotated
+pub const MQTT_LOG_MAX_BYTES: u128 = 10 * 1024 * 1024;
+
+/// File name (without extension) of the MQTT log in the app's log directory
+pub const MQTT_LOG_FILE: &str = "mqtt";
+
+static MQTT_FILE_LOGGING: AtomicBool = AtomicBool::new(false);
+
+/// Changes the level at runtime, e.g. `"debug"` while chasing a problem.
+pub fn set_level(level: &str) -> Result<()> {
+	let filter: LevelFilter = level.trim().parse().map_err(|_| {
+		anyhow!("Unknown log level '{level}', expected off, error, warn, info, debug or trace")
+	})?;
+	log::set_max_level(filter);
+	Ok(())
+}
+
+pub fn set_mqtt_file_logging(enabled: bool) {
+	MQTT_FILE_LOGGING.store(enabled, Ordering::Relaxed);
+}
+
+/// Filter for the MQTT log file target: records from the MQTT service, and
+/// only while file logging is switched on.
+pub fn is_mqtt_file_record(metadata: &Metadata) -> bool {
+	MQTT_FILE_LOGGING.load(Ordering::Relaxed) && is_mqtt_target(metadata.target())
+}
+
+fn is_mqtt_target(target: &str) -> bool {
+	target == crate::mqtt::LOG_TARGET
+		|| target
+			.strip_prefix(crate::mqtt::LOG_TARGET)
+			.is_some_and(|rest| rest.starts_with("::"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_set_level_rejects_unknown_levels() {
+		assert!(set_level("verbose").is_err());
+		assert!(set_level(" Debug ").is_ok());
+		assert_eq!(log::max_level(), LevelFilter::Debug);
+	}
+
+	#[test]
+	fn test_is_mqtt_target() {
+		let target = crate::mqtt::LOG_TARGET;
+		assert!(is_mqtt_target(target));
+		assert!(is_mqtt_target(&format!("{target}::connection")));
+		assert!(!is_mqtt_target(&format!("{target}_other")));
+		assert!(!is_mqtt_target("rumqttc::state"));
+	}
+}
@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const AMBIENT_TEMP: f64 = 25.0;
+const NOZZLE_TARGET: f64 = 220.0;
+const BED_TARGET: f64 = 55.0;
+const TOTAL_LAYERS: i64 = 200;
+/// Share of the run spent heating up before the first layer
+const PREPARE_FRACTION: f64 = 0.1;
+/// Simulated job name, so the UI has something to show
+pub const JOB_NAME: &str = "mock_benchy";
+
+/// How a mock printer behaves.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MockOptions {
+	/// Real time from heating up to a finished print
+	#[serde(default = "MockOptions::default_duration_secs")]
+	pub duration_secs: u64,
+	/// Time between simulated reports
+	#[serde(default = "MockOptions::default_report_interval_ms")]
+	pub report_interval_ms: u64,
+	/// Fail the print with a filament runout once it reaches this percentage
+	#[serde(default)]
+	pub fail_at_percent: Option<u8>,
+}
+
+impl Default for MockOptions {
+	fn default() -> Self {
+		Self {
+			duration_secs: Self::default_duration_secs(),
+			report_interval_ms: Self::default_report_interval_ms(),
+			fail_at_percent: None,
+		}
+	}
+}
+
+impl MockOptions {
+	fn default_duration_secs() -> u64 {
+		120
+	}
+
+	fn default_report_interval_ms() -> u64 {
+		1000
+	}
+
+	pub fn report_interval(&self) -> Duration {
+		Duration::from_millis(self.report_interval_ms)
+	}
+
+	pub fn duration(&self) -> Duration {
+		Duration::from_secs(self.duration_secs)
+	}
+
+	/// Whether the simulation has nothing left to change once `elapsed` has passed
+	pub fn is_done(&self, elapsed: Duration) -> bool {
+		elapsed >= self.duration() || self.failed(Self::percent(self.progress(elapsed)))
+	}
+
+	/// Share of the run completed, from 0 to 1
+	fn progress(&self, elapsed: Duration) -> f64 {
+		(elapsed.as_secs_f64() / self.duration().as_secs_f64()).clamp(0.0, 1.0)
+	}
+
+	/// Print progress for a run `progress` of the way through; the heating
+	/// phase counts as 0%
+	fn percent(progress: f64) -> f64 {
+		((progress - PREPARE_FRACTION) / (1.0 - PREPARE_FRACTION) * 100.0).clamp(0.0, 100.0)
+	}
+
+	fn failed(&self, percent: f64) -> bool {
+		self
+			.fail_at_percent
+			.is_some_and(|fail_at| percent >= f64::from(fail_at))
+	}
+
+	/// The `report` payload a printer would send `elapsed` into the run.
+	pub fn report(&self, elapsed: Duration) -> serde_json::Value {
+		let progress = self.progress(elapsed);
+		let heat = (progress / PREPARE_FRACTION).min(1.0);
+		let nozzle = AMBIENT_TEMP + (NOZZLE_TARGET - AMBIENT_TEMP) * heat;
+		let bed = AMBIENT_TEMP + (BED_TARGET - AMBIENT_TEMP) * heat;
+
+		let percent = Self::percent(progress);
+		let failed = self.failed(percent);
+		let percent = match self.fail_at_percent {
+			Some(fail_at) if failed => f64::from(fail_at),
+			_ => percent,
+		};
+		let layer = (percent / 100.0 * TOTAL_LAYERS as f64).round() as i64;
+		let remaining_minutes = (self.duration().as_secs_f64() * (1.0 - progress) / 60.0).ceil() as i64;
+
+		let (gcode_state, print_error, error_code) = if failed {
+			// Same codes a P1P sends for a filament runout
+			("PAUSE", 117_473_287, 1203)
+		} else if progress >= 1.0 {
+			("FINISH", 0, 0)
+		} else if progress < PREPARE_FRACTION {
+			("PREPARE", 0, 0)
+		} else {
+			("RUNNING", 0, 0)
+		};
+		let running = gcode_state == "RUNNING";
+
+		serde_json::json!({
+			"print": {
+				"command": "push_status",
+				"gcode_state": gcode_state,
+				"subtask_name": JOB_NAME,
+				"gcode_file": format!("/sdcard/{JOB_NAME}.gcode.3mf"),
+				"print_type": "local",
+				"mc_percent": percent.round() as i64,
+				"mc_remaining_time": if gcode_state == "FINISH" { 0 } else { remaining_minutes },
+				"layer_num": layer,
+				"total_layer_num": TOTAL_LAYERS,
+				"print_real": i64::from(running),
+				"nozzle_temper": if gcode_state == "FINISH" { AMBIENT_TEMP } else { nozzle },
+				"nozzle_target_temper": if gcode_state == "FINISH" { 0.0 } else { NOZZLE_TARGET },
+				"bed_temper": if gcode_state == "FINISH" { AMBIENT_TEMP } else { bed },
+				"bed_target_temper": if gcode_state == "FINISH" { 0.0 } else { BED_TARGET },
+				"chamber_temper": AMBIENT_TEMP,
+				"cooling_fan_speed": if running { "15" } else { "0" },
+				"fan_gear": if running { 15 } else { 0 },
+				"spd_lvl": 2,
+				"stg_cur": if failed { 6 } else { 0 },
+				"print_error": print_error,
+				"mc_print_error_code": error_code,
+				"lifecycle": "product",
+				"wifi_signal": "-50dBm",
+				"sdcard": true,
+				"hms": []
+			}
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_report_progresses_through_a_print() {
+		let options = MockOptions {
+			duration_secs: 100,
+			..Default::default()
+		};
+		let at = |secs: u64| options.report(Duration::from_secs(secs))["print"].clone();
+
+		let heating = at(5);
+		assert_eq!(heating["gcode_state"], "PREPARE");
+		assert_eq!(heating["mc_percent"], 0);
+		assert!(heating["nozzle_temper"].as_f64().unwrap() < NOZZLE_TARGET);
+
+		let printing = at(55);
+		assert_eq!(printing["gcode_state"], "RUNNING");
+		assert_eq!(printing["mc_percent"], 50);
+		assert_eq!(printing["layer_num"], 100);
+		assert_eq!(printing["nozzle_temper"], NOZZLE_TARGET);
+
+		let finished = at(100);
+		assert_eq!(finished["gcode_state"], "FINISH");
+		assert_eq!(finished["mc_percent"], 100);
+		assert!(options.is_done(Duration::from_secs(100)));
+		assert!(!options.is_done(Duration::from_secs(99)));
+	}
+
+	#[test]
+	fn test_report_injects_failure() {
+		let options = MockOptions {
+			duration_secs: 100,
+			fail_at_percent: Some(30),
+			..Default::default()
+		};
+
+		let report = options.report(Duration::from_secs(80));
+		assert_eq!(report["print"]["gcode_state"], "PAUSE");
+		assert_eq!(report["print"]["mc_percent"], 30);
+		assert_eq!(report["print"]["mc_print_error_code"], 1203);
+		assert!(options.is_done(Duration::from_secs(80)));
+	}
+}
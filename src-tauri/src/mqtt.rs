@@ -1,24 +1,185 @@
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
-use log::{debug, error, info};
-use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS, TlsConfiguration, Transport};
+use log::{debug, error, info, log_enabled, warn, Level};
+use rumqttc::{
+	AsyncClient, Event, EventLoop, MqttOptions, Outgoing, Packet, QoS, TlsConfiguration, Transport,
+};
 use rustls::{
 	client::danger::{ServerCertVerified, ServerCertVerifier},
+	crypto::CryptoProvider,
 	pki_types::ServerName,
 	Error as TlsError,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
-use tauri::{AppHandle, Emitter};
-use tokio::sync::{mpsc, RwLock};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{broadcast, oneshot, watch, Notify, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+use crate::capture::Captures;
+use crate::database::{CommandHistory, CommandHistoryEntry, PrinterState, PrinterStateStore};
+use crate::ftps::{FtpsClient, PrinterFile};
+use crate::hms::{HmsCode, HmsSeverity};
+use crate::mock::MockOptions;
+#[cfg(test)]
+use crate::report::parse_wifi_signal;
+use crate::report::{value_as_f64, value_as_i64, BambuReport};
+use crate::thumbnail;
+
+/// Target of every log record from this module, for routing them to the MQTT log file.
+pub const LOG_TARGET: &str = module_path!();
+
+/// Remaining time reported for the last minute of a print, which the printer
+/// rounds down to 0 minutes: the midpoint of what's actually left.
+const SUB_MINUTE_REMAINING_SECS: i64 = 30;
+
+/// How long to wait for the printer to answer a command before giving up.
+const COMMAND_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a command waits for a printer that is still connecting, e.g. one
+/// added a moment ago, before it fails instead of being sent.
+const COMMAND_CONNECTION_WAIT: Duration = Duration::from_secs(10);
+
+/// How long after resuming a print to watch for the error coming straight back.
+const RESUME_ERROR_WINDOW: Duration = Duration::from_secs(5);
+
+/// Upper bound on downloading a job's project file for its thumbnail.
+const THUMBNAIL_FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Upper bound on listing the files on a printer's storage.
+const FILE_LIST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Where printable files live: the storage root, and `/cache` for jobs sent
+/// from Bambu Studio.
+const PRINTER_FILE_DIRS: [&str; 2] = ["/", "/cache"];
+
+/// Upper bound on the per-printer delay before status requests, which keeps
+/// printers connecting together from all asking at the same instant.
+const MAX_STATUS_JITTER: Duration = Duration::from_millis(1500);
+
+/// How long `add_printer` waits for the first `ConnAck` when the caller
+/// requires a working connection.
+const ADD_PRINTER_CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Minimum gap between `force_refresh` calls for the same printer.
+const FORCE_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long to wait after a connection error before retrying.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Minimum gap between `connection-error` events for the same error, so a
+/// tight reconnect loop doesn't flood the webview.
+const CONNECTION_ERROR_EVENT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Upper bound on how long `shutdown` waits for printers to disconnect, so an
+/// unreachable printer can't hold up app exit.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Largest Z-offset change `adjust_z_offset` makes in one call, in mm.
+const MAX_Z_OFFSET_STEP_MM: f64 = 0.5;
+
+/// How close, in °C, `set_temperature_and_wait` needs a heater to get to its
+/// target before it counts as reached.
+const TEMPERATURE_REACHED_TOLERANCE: f64 = 3.0;
+
+/// Hottest bed setpoint `set_temperature` sends; no Bambu bed goes past 110°C.
+const MAX_BED_TEMP: i32 = 110;
+
+/// Most recent commands included in a debug bundle
+const DEBUG_BUNDLE_COMMAND_LIMIT: u32 = 50;
+
+/// `ams_change_filament` target meaning "unload back into the AMS".
+const UNLOAD_FILAMENT_TARGET: u8 = 255;
+
+/// Top-level keys kept in the accumulated state: `print` reports and `info`
+/// (module versions), which arrives rarely and must survive later merges.
+const RETAINED_TOP_LEVEL_KEYS: &[&str] = &["info", "print"];
+
+/// Keys under `print` that `apply_message` reads. The accumulated state is
+/// pruned to these after every merge, so transient or unused fields Bambu sends
+/// (upgrade state, xcam settings, lights, ...) don't grow it without
+/// bound. A key the parser starts reading must be added here, otherwise it is
+/// dropped before status detection ever sees it.
+const RETAINED_PRINT_KEYS: &[&str] = &[
+	"ams",
+	"bed_target_temper",
+	"bed_temper",
+	"bed_type",
+	"big_fan1_speed",
+	"big_fan2_speed",
+	"chamber_temper",
+	"cooling_fan_speed",
+	"fan_gear",
+	"gcode_file",
+	"gcode_state",
+	"hms",
+	"ipcam",
+	"layer_num",
+	"length",
+	"lifecycle",
+	"mc_percent",
+	"mc_print_error_code",
+	"mc_print_stage",
+	"mc_print_sub_stage",
+	"mc_remaining_time",
+	"nozzle_diameter",
+	"nozzle_target_temper",
+	"nozzle_temper",
+	"nozzle_type",
+	"online",
+	"print_error",
+	"print_real",
+	"print_type",
+	"sdcard",
+	"spd_lvl",
+	"spd_mag",
+	"stg_cur",
+	"storage",
+	"subtask_name",
+	"total_layer_num",
+	"vt_tray",
+	"weight",
+	"wifi_signal",
+	"xcam",
+];
+
 // Custom certificate verifier that accepts all certificates (insecure mode)
 // This is equivalent to setInsecure() in ESP8266 WiFiClientSecure
 #[derive(Debug)]
-struct InsecureVerifier;
+struct InsecureVerifier {
+	/// Same provider the `ClientConfig` is built with, so the schemes we
+	/// advertise always match what the handshake can actually use
+	provider: Arc<CryptoProvider>,
+}
+
+impl InsecureVerifier {
+	fn new(provider: Arc<CryptoProvider>) -> Self {
+		Self { provider }
+	}
+}
+
+/// Name of the provider returned by `crypto_provider`, for diagnostics
+const CRYPTO_PROVIDER_NAME: &str = "ring";
+
+/// The crypto provider every TLS config in this module is built with.
+fn crypto_provider() -> Arc<CryptoProvider> {
+	static PROVIDER: OnceLock<Arc<CryptoProvider>> = OnceLock::new();
+	PROVIDER
+		.get_or_init(|| Arc::new(rustls::crypto::ring::default_provider()))
+		.clone()
+}
 
 impl ServerCertVerifier for InsecureVerifier {
 	fn verify_server_cert(
@@ -52,7 +213,8 @@ impl ServerCertVerifier for InsecureVerifier {
 	}
 
 	fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-		rustls::crypto::ring::default_provider()
+		self
+			.provider
 			.signature_verification_algorithms
 			.supported_schemes()
 	}
@@ -66,854 +228,5588 @@ pub struct PrinterConfig {
 	pub ip: String,
 	pub access_code: String,
 	pub serial: String,
+	/// MQTT broker port; `None` means the standard Bambu port (8883)
+	#[serde(default)]
+	pub port: Option<u16>,
+	#[serde(default)]
+	pub tls_mode: TlsMode,
+	/// Re-request full status this often. `None` (the default) never polls,
+	/// since frequent `get_status` requests make P1P printers lag.
+	#[serde(default)]
+	pub poll_interval_secs: Option<u64>,
+	/// Topics to subscribe to on top of the main report and info topics.
+	/// `{serial}` is replaced with the printer's serial.
+	#[serde(default)]
+	pub extra_topics: Vec<String>,
+	/// MQTT keep-alive; `None` means 60 seconds. Shorter values notice a
+	/// dropped connection sooner at the cost of more pings.
+	#[serde(default)]
+	pub keep_alive_secs: Option<u64>,
+	/// Give up after this many failed connection attempts in a row and mark
+	/// the printer unreachable until `reconnect_printer` is called. `None`
+	/// (the default) retries forever.
+	#[serde(default)]
+	pub max_reconnect_attempts: Option<u32>,
+	#[serde(default)]
+	pub connection_mode: ConnectionMode,
+	/// MQTT user name; `None` means `bblp`, which LAN mode expects. Other
+	/// brokers (e.g. a custom bridge) may want something else.
+	#[serde(default)]
+	pub username: Option<String>,
+	/// Send `print-complete`, `filament-runout` and `printer-error` events for
+	/// this printer. `printer-update` is sent either way.
+	#[serde(default = "PrinterConfig::default_notifications_enabled")]
+	pub notifications_enabled: bool,
+	#[serde(default)]
+	pub temperature_thresholds: TemperatureThresholds,
+	/// Free-form labels for organising printers, e.g. `"PETG station"`.
+	/// Stored and returned as-is; they don't affect the connection.
+	#[serde(default)]
+	pub tags: Vec<String>,
+	#[serde(default)]
+	pub location: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PrinterTemperatures {
-	pub nozzle: i32,
-	pub bed: i32,
-	pub chamber: i32,
+/// Readings above which a `temperature-alert` event is sent, in °C. `None`
+/// (the default) leaves that sensor unwatched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureThresholds {
+	#[serde(default)]
+	pub max_nozzle: Option<f64>,
+	#[serde(default)]
+	pub max_bed: Option<f64>,
+	#[serde(default)]
+	pub max_chamber: Option<f64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PrintJob {
-	pub progress: f64,
-	pub time_remaining: i64,
-	pub estimated_total_time: Option<i64>,
-	pub file_name: String,
-	pub print_type: Option<String>,
-	pub layer_current: i32,
-	pub layer_total: i32,
-	pub speed_level: Option<i32>,
-	pub fan_speed: Option<i32>,
-	pub stage: Option<i32>,
-	pub lifecycle: Option<String>,
+/// Where a printer's reports come from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionMode {
+	/// The printer's own MQTT broker
+	#[default]
+	Mqtt,
+	/// A simulated print fed through the normal report handling, for
+	/// working on the UI without a printer. Commands to it fail.
+	Mock(MockOptions),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FilamentInfo {
-	pub r#type: String,
-	pub color: String,
-	pub remaining: f64,
+/// How the printer's TLS certificate is checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsMode {
+	/// Accept any certificate. Stock Bambu printers present a self-signed
+	/// certificate, so this is the default.
+	#[default]
+	InsecureSkipVerify,
+	/// Verify the broker against the CA certificate(s) in a PEM file.
+	VerifyWithCa(PathBuf),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PrinterError {
-	pub print_error: i32,
-	pub error_code: i32,
-	pub stage: i32,
-	pub lifecycle: String,
-	pub gcode_state: String,
+/// A `PrinterConfig` field that failed validation.
+///
+/// Displays as `"<field>: <message>"` so the frontend can tell which input
+/// to highlight from the error string alone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigValidationError {
+	pub field: &'static str,
 	pub message: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum PrinterStatus {
-	Idle,
-	Printing,
-	Paused,
-	Error,
-	Offline,
-	Connecting,
+impl fmt::Display for ConfigValidationError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.field, self.message)
+	}
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Printer {
-	pub id: String,
-	pub name: String,
-	pub model: String,
-	pub ip: String,
-	pub access_code: String,
-	pub serial: String,
-	pub status: PrinterStatus,
-	pub online: bool,
-	pub connection_state: String,
-	pub temperatures: PrinterTemperatures,
-	pub print: Option<PrintJob>,
-	pub filament: Option<FilamentInfo>,
-	pub error: Option<PrinterError>,
-	pub last_update: DateTime<Utc>,
-}
+impl std::error::Error for ConfigValidationError {}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PrintCommand {
-	pub action: String,
-}
+impl PrinterConfig {
+	/// Length of the LAN-mode access code shown on the printer's screen
+	pub const ACCESS_CODE_LEN: usize = 8;
+	/// Port Bambu printers expose MQTT over TLS on
+	pub const DEFAULT_MQTT_PORT: u16 = 8883;
+	pub const DEFAULT_KEEP_ALIVE_SECS: u64 = 60;
+	pub const DEFAULT_USERNAME: &'static str = "bblp";
 
-// Simplified service that doesn't store MQTT connections directly
-#[derive(Clone)]
-pub struct MqttService {
-	printer_states: Arc<RwLock<HashMap<String, Printer>>>,
-	// Add persistent MQTT state accumulation
-	printer_mqtt_states: Arc<RwLock<HashMap<String, serde_json::Value>>>,
-	// Add connection pool for sending commands
-	printer_connections: Arc<RwLock<HashMap<String, AsyncClient>>>,
-	app_handle: AppHandle,
-	command_sender: mpsc::UnboundedSender<(String, PrintCommand)>,
-}
+	fn default_notifications_enabled() -> bool {
+		true
+	}
 
-impl MqttService {
-	pub fn new(app_handle: AppHandle) -> Self {
-		let (command_sender, command_receiver) = mpsc::unbounded_channel();
+	/// `ip` the way rumqttc and rustls take it. IPv6 literals lose their
+	/// brackets: rustls refuses `[fe80::1]` as a server name, and rumqttc's
+	/// `"{host}:{port}"` lookup still resolves the bare form.
+	pub fn host(&self) -> &str {
+		let host = self.ip.trim();
+		host
+			.strip_prefix('[')
+			.and_then(|inner| inner.strip_suffix(']'))
+			.filter(|inner| inner.parse::<Ipv6Addr>().is_ok())
+			.unwrap_or(host)
+	}
 
-		let service = Self {
-			printer_states: Arc::new(RwLock::new(HashMap::new())),
-			printer_mqtt_states: Arc::new(RwLock::new(HashMap::new())),
-			printer_connections: Arc::new(RwLock::new(HashMap::new())),
-			app_handle: app_handle.clone(),
-			command_sender,
-		};
+	pub fn mqtt_port(&self) -> u16 {
+		self.port.unwrap_or(Self::DEFAULT_MQTT_PORT)
+	}
 
-		// Start command handler in background using tauri async runtime
-		let printer_states = Arc::clone(&service.printer_states);
-		let printer_connections = Arc::clone(&service.printer_connections);
-		tauri::async_runtime::spawn(async move {
-			let mut receiver = command_receiver;
-			while let Some((printer_id, command)) = receiver.recv().await {
-				info!(
-					"Processing command '{}' for printer {}",
-					command.action, printer_id
-				);
+	pub fn username(&self) -> &str {
+		self.username.as_deref().unwrap_or(Self::DEFAULT_USERNAME)
+	}
 
-				// Get printer configuration and MQTT client
-				let (printer_serial, mqtt_client) = {
-					let states = printer_states.read().await;
-					let connections = printer_connections.read().await;
+	pub fn keep_alive(&self) -> Duration {
+		Duration::from_secs(
+			self
+				.keep_alive_secs
+				.unwrap_or(Self::DEFAULT_KEEP_ALIVE_SECS),
+		)
+	}
 
-					if let Some(printer) = states.get(&printer_id) {
-						if let Some(client) = connections.get(&printer_id) {
-							(printer.serial.clone(), client.clone())
-						} else {
-							error!("No MQTT connection found for printer {printer_id}");
-							continue;
-						}
-					} else {
-						error!("Printer {printer_id} not found");
-						continue;
-					}
-				};
+	/// Topic carrying the printer's `print` reports.
+	pub fn report_topic(&self) -> String {
+		format!("device/{}/report", self.serial)
+	}
 
-				// Send actual MQTT command
-				match Self::send_mqtt_command(&mqtt_client, &printer_serial, &command).await {
-					Ok(_) => {
-						info!(
-							"Command '{}' sent successfully to printer {}",
-							command.action, printer_id
-						);
-					}
-					Err(e) => {
-						error!(
-							"Failed to send command '{}' to printer {}: {}",
-							command.action, printer_id, e
-						);
-					}
-				}
+	/// Every topic to subscribe to: the main report, the info topic (module
+	/// and firmware versions), then any `extra_topics`.
+	pub fn subscription_topics(&self) -> Vec<String> {
+		let mut topics = vec![
+			self.report_topic(),
+			format!("device/{}/report/info", self.serial),
+		];
+		for topic in &self.extra_topics {
+			let topic = topic.replace("{serial}", &self.serial);
+			if !topics.contains(&topic) {
+				topics.push(topic);
 			}
-		});
-
-		service
+		}
+		topics
 	}
 
-	async fn send_mqtt_command(
-		client: &AsyncClient,
-		printer_serial: &str,
-		command: &PrintCommand,
-	) -> Result<()> {
-		let request_topic = format!("device/{printer_serial}/request");
-		let sequence_id = chrono::Utc::now().timestamp_millis().to_string();
+	pub fn validate(&self) -> Result<(), ConfigValidationError> {
+		if self.host().parse::<IpAddr>().is_err() && !is_valid_hostname(self.host()) {
+			return Err(ConfigValidationError {
+				field: "ip",
+				message: format!("'{}' is not a valid IP address or hostname", self.ip),
+			});
+		}
 
-		let mqtt_command = match command.action.as_str() {
-			"pause" => serde_json::json!({
-				"print": {
-					"command": "pause",
-					"sequence_id": sequence_id
-				}
-			}),
-			"resume" => serde_json::json!({
-				"print": {
-					"command": "resume",
-					"sequence_id": sequence_id
-				}
-			}),
-			"stop" => serde_json::json!({
-				"print": {
-					"command": "stop",
-					"sequence_id": sequence_id
-				}
-			}),
-			"get_status" => serde_json::json!({
-				"print": {
-					"command": "get_status",
-					"sequence_id": sequence_id
-				}
-			}),
-			_ => {
-				return Err(anyhow!("Unsupported command: {}", command.action));
+		if self.access_code.chars().count() != Self::ACCESS_CODE_LEN
+			|| !self.access_code.chars().all(|c| c.is_ascii_alphanumeric())
+		{
+			return Err(ConfigValidationError {
+				field: "access_code",
+				message: format!(
+					"must be the {}-character code shown on the printer",
+					Self::ACCESS_CODE_LEN
+				),
+			});
+		}
+
+		if self.serial.trim().is_empty() {
+			return Err(ConfigValidationError {
+				field: "serial",
+				message: "must not be empty".to_string(),
+			});
+		}
+
+		if self.port == Some(0) {
+			return Err(ConfigValidationError {
+				field: "port",
+				message: "must be between 1 and 65535".to_string(),
+			});
+		}
+
+		if let TlsMode::VerifyWithCa(ca_path) = &self.tls_mode {
+			if ca_path.as_os_str().is_empty() {
+				return Err(ConfigValidationError {
+					field: "tls_mode",
+					message: "CA certificate path must not be empty".to_string(),
+				});
 			}
-		};
+		}
 
-		let message = mqtt_command.to_string();
-		client
-			.publish(request_topic, QoS::AtMostOnce, false, message.as_bytes())
-			.await
-			.map_err(|e| anyhow!("MQTT publish failed: {}", e))?;
+		if self.poll_interval_secs == Some(0) {
+			return Err(ConfigValidationError {
+				field: "poll_interval_secs",
+				message: "must be at least 1 second".to_string(),
+			});
+		}
 
-		Ok(())
-	}
+		// MQTT carries keep-alive as a 16-bit value, and 0 would turn it off
+		if self
+			.keep_alive_secs
+			.is_some_and(|secs| secs == 0 || secs > u64::from(u16::MAX))
+		{
+			return Err(ConfigValidationError {
+				field: "keep_alive_secs",
+				message: format!("must be between 1 and {} seconds", u16::MAX),
+			});
+		}
 
-	pub async fn add_printer(&self, config: PrinterConfig) -> Result<()> {
-		info!("Adding printer: {} ({})", config.name, config.ip);
+		if self
+			.extra_topics
+			.iter()
+			.any(|topic| topic.trim().is_empty())
+		{
+			return Err(ConfigValidationError {
+				field: "extra_topics",
+				message: "topics must not be empty".to_string(),
+			});
+		}
 
-		// Create initial printer state
-		let printer = Printer {
-			id: config.id.clone(),
-			name: config.name.clone(),
-			model: config.model.clone(),
-			ip: config.ip.clone(),
-			access_code: config.access_code.clone(),
-			serial: config.serial.clone(),
-			status: PrinterStatus::Connecting,
-			online: false,
-			connection_state: "connecting".to_string(),
-			temperatures: PrinterTemperatures {
-				nozzle: 0,
-				bed: 0,
-				chamber: 0,
-			},
-			print: None,
-			filament: None,
-			error: None,
-			last_update: Utc::now(),
-		};
+		if self.tags.iter().any(|tag| tag.trim().is_empty()) {
+			return Err(ConfigValidationError {
+				field: "tags",
+				message: "tags must not be empty".to_string(),
+			});
+		}
 
-		// Store initial state
+		let thresholds = &self.temperature_thresholds;
+		if [
+			thresholds.max_nozzle,
+			thresholds.max_bed,
+			thresholds.max_chamber,
+		]
+		.into_iter()
+		.flatten()
+		.any(|max| !max.is_finite() || max <= 0.0)
 		{
-			let mut states = self.printer_states.write().await;
-			states.insert(config.id.clone(), printer.clone());
+			return Err(ConfigValidationError {
+				field: "temperature_thresholds",
+				message: "thresholds must be greater than 0°C".to_string(),
+			});
 		}
 
-		// Emit initial state to frontend
-		self.emit_printer_update(&printer).await;
+		if self
+			.username
+			.as_deref()
+			.is_some_and(|username| username.trim().is_empty())
+		{
+			return Err(ConfigValidationError {
+				field: "username",
+				message: "must not be empty".to_string(),
+			});
+		}
 
-		// Start MQTT connection in background
-		let app_handle = self.app_handle.clone();
-		let printer_states = Arc::clone(&self.printer_states);
-		let printer_mqtt_states = Arc::clone(&self.printer_mqtt_states);
-		let printer_connections = Arc::clone(&self.printer_connections);
-		tauri::async_runtime::spawn(async move {
-			Self::start_mqtt_connection_task(
-				config,
-				printer_states,
-				printer_mqtt_states,
-				printer_connections,
-				app_handle,
-			)
-			.await;
-		});
+		if let ConnectionMode::Mock(options) = &self.connection_mode {
+			if options.duration_secs == 0 || options.report_interval_ms == 0 {
+				return Err(ConfigValidationError {
+					field: "connection_mode",
+					message: "mock duration and report interval must be greater than 0".to_string(),
+				});
+			}
+			if options.fail_at_percent.is_some_and(|percent| percent > 100) {
+				return Err(ConfigValidationError {
+					field: "connection_mode",
+					message: "mock failure percentage must be between 0 and 100".to_string(),
+				});
+			}
+		}
 
 		Ok(())
 	}
+}
 
-	async fn start_mqtt_connection_task(
-		config: PrinterConfig,
-		printer_states: Arc<RwLock<HashMap<String, Printer>>>,
-		printer_mqtt_states: Arc<RwLock<HashMap<String, serde_json::Value>>>,
-		printer_connections: Arc<RwLock<HashMap<String, AsyncClient>>>,
-		app_handle: AppHandle,
-	) {
-		let printer_id = config.id.clone();
-		let client_id = format!("pulseprint_desktop_{}_{}", config.id, Uuid::new_v4());
+/// Friendly name for a `bed_type` code. Codes we don't know are passed
+/// through as-is; `"auto"` (plate detection without a result) counts as unknown.
+fn bed_type_name(code: &str) -> Option<String> {
+	let name = match code.trim() {
+		"" | "auto" => return None,
+		"textured_plate" => "Textured PEI",
+		"hot_plate" => "Smooth PEI",
+		"cool_plate" | "pc" => "Cool Plate",
+		"eng_plate" => "Engineering Plate",
+		other => other,
+	};
+	Some(name.to_string())
+}
 
-		let mut mqtt_options = MqttOptions::new(&client_id, &config.ip, 8883);
-		mqtt_options
-			.set_credentials("bblp", &config.access_code)
-			.set_keep_alive(Duration::from_secs(60));
-
-		// Use TLS but bypass certificate validation entirely
-		// This matches PulsePrint behavior: rejectUnauthorized: false
-		// Bambu Lab printers use self-signed certificates that don't validate
-		// Using setInsecure() equivalent by creating a custom TLS config
-		let tls_config =
-			rustls::ClientConfig::builder_with_provider(rustls::crypto::ring::default_provider().into())
-				.with_safe_default_protocol_versions()
-				.unwrap()
-				.dangerous()
-				.with_custom_certificate_verifier(Arc::new(InsecureVerifier))
-				.with_no_client_auth();
+fn value_as_u8(value: &serde_json::Value) -> Option<u8> {
+	value_as_i64(value).and_then(|n| u8::try_from(n).ok())
+}
 
-		mqtt_options.set_transport(Transport::Tls(TlsConfiguration::Rustls(Arc::new(
-			tls_config,
-		))));
+fn same_serial(a: &str, b: &str) -> bool {
+	a.trim().eq_ignore_ascii_case(b.trim())
+}
 
-		let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
-		let status_topic = format!("device/{}/report", config.serial);
+fn is_valid_hostname(host: &str) -> bool {
+	let host = host.strip_suffix('.').unwrap_or(host);
+	// An all-numeric final label is a mistyped IPv4 address, not a hostname
+	let numeric_tld = host
+		.rsplit('.')
+		.next()
+		.is_some_and(|tld| tld.chars().all(|c| c.is_ascii_digit()));
+	!host.is_empty()
+		&& !numeric_tld
+		&& host.len() <= 253
+		&& host.split('.').all(|label| {
+			!label.is_empty()
+				&& label.len() <= 63
+				&& !label.starts_with('-')
+				&& !label.ends_with('-')
+				&& label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+		})
+}
 
-		loop {
-			match event_loop.poll().await {
-				Ok(Event::Incoming(Packet::ConnAck(_))) => {
-					info!("Connected to printer {} ({})", config.name, config.ip);
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrinterTemperatures {
+	/// Readings as reported, keeping the printer's decimal precision
+	pub nozzle: f64,
+	pub bed: f64,
+	pub chamber: f64,
+	/// Target temperatures; 0 means the heater is off
+	pub nozzle_target: f64,
+	pub bed_target: f64,
+	pub nozzle_state: HeatingState,
+	pub bed_state: HeatingState,
+}
 
-					// Subscribe to status topic
-					if let Err(e) = client.subscribe(&status_topic, QoS::AtMostOnce).await {
-						error!("Failed to subscribe to {status_topic}: {e}");
-					} else {
-						info!("Subscribed to {status_topic}");
-					}
+/// Whether a heater is still converging on its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeatingState {
+	Heating,
+	Cooling,
+	Stable,
+	Off,
+}
 
-					// Request full status immediately after connection
-					let status_request = serde_json::json!({
-							"print": {
-									"command": "get_status",
-									"sequence_id": chrono::Utc::now().timestamp_millis().to_string()
-							}
-					});
+impl HeatingState {
+	/// Degrees either side of the target that still count as `Stable`
+	const TOLERANCE: f64 = 2.0;
 
-					let request_topic = format!("device/{}/request", config.serial);
-					let message = status_request.to_string();
+	pub fn from_temps(current: f64, target: f64) -> Self {
+		if target <= 0.0 {
+			HeatingState::Off
+		} else if current < target - Self::TOLERANCE {
+			HeatingState::Heating
+		} else if current > target + Self::TOLERANCE {
+			HeatingState::Cooling
+		} else {
+			HeatingState::Stable
+		}
+	}
+}
 
-					if let Err(e) = client
-						.publish(request_topic, QoS::AtMostOnce, false, message.as_bytes())
-						.await
-					{
-						error!(
-							"Failed to send initial status request to {}: {}",
-							config.name, e
-						);
-					} else {
-						info!("Initial status request sent to {}", config.name);
-					}
+/// A heater `set_temperature` can drive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Heater {
+	Nozzle,
+	Bed,
+}
 
-					// Note: Removed periodic polling to avoid hardware lag issues on P1P printers
-					// Instead, we rely on the initial status request and real-time MQTT updates
+impl Heater {
+	fn parse(target: &str) -> Result<Self> {
+		match target {
+			"nozzle" => Ok(Heater::Nozzle),
+			"bed" => Ok(Heater::Bed),
+			_ => Err(anyhow!(
+				"Unknown heater '{target}', expected 'nozzle' or 'bed'"
+			)),
+		}
+	}
 
-					// Update connection state
-					Self::update_printer_status(&printer_states, &app_handle, &printer_id, |printer| {
-						printer.online = true;
-						printer.status = PrinterStatus::Idle;
-						printer.connection_state = "connected".to_string();
-						printer.last_update = Utc::now();
-					})
-					.await;
+	/// Builds the `M104`/`M140` command setting the heater to `value` °C,
+	/// refusing anything past `max`. 0 turns the heater off.
+	fn gcode(self, value: i32, max: i32) -> Result<String> {
+		if !(0..=max).contains(&value) {
+			return Err(anyhow!(
+				"Temperature {value}°C is out of range, expected 0-{max}°C"
+			));
+		}
+		Ok(match self {
+			Heater::Nozzle => format!("M104 S{value}"),
+			Heater::Bed => format!("M140 S{value}"),
+		})
+	}
 
-					// Add MQTT client to connection pool
-					{
-						let mut connections = printer_connections.write().await;
-						connections.insert(printer_id.clone(), client.clone());
-					}
-				}
-				Ok(Event::Incoming(Packet::Publish(publish))) => {
-					debug!("Received MQTT message on topic: {}", publish.topic);
+	fn current(self, temperatures: &PrinterTemperatures) -> f64 {
+		match self {
+			Heater::Nozzle => temperatures.nozzle,
+			Heater::Bed => temperatures.bed,
+		}
+	}
+}
 
-					// Parse MQTT message
-					match serde_json::from_slice::<serde_json::Value>(&publish.payload) {
-						Ok(data) => {
-							Self::handle_printer_message(
-								&printer_states,
-								&printer_mqtt_states,
-								&app_handle,
-								&config,
-								&data,
-							)
-							.await;
-						}
-						Err(e) => {
-							error!("Failed to parse MQTT message: {e}");
-						}
-					}
-				}
-				Ok(Event::Incoming(Packet::SubAck(_))) => {
-					debug!("Successfully subscribed to topic");
-				}
-				Ok(_) => {
-					// Other events we don't need to handle
-				}
-				Err(e) => {
-					error!("MQTT connection error for {}: {}", config.name, e);
+/// A `set_temperature_and_wait` call waiting for its heater to reach target.
+struct TemperatureWaiter {
+	heater: Heater,
+	target: i32,
+	reached: oneshot::Sender<()>,
+}
 
-					// Update connection state to failed
-					Self::update_printer_status(&printer_states, &app_handle, &printer_id, |printer| {
-						printer.online = false;
-						printer.status = PrinterStatus::Offline;
-						printer.connection_state = "failed".to_string();
-						printer.last_update = Utc::now();
-					})
-					.await;
+impl TemperatureWaiter {
+	fn is_reached(&self, temperatures: &PrinterTemperatures) -> bool {
+		(self.heater.current(temperatures) - f64::from(self.target)).abs()
+			<= TEMPERATURE_REACHED_TOLERANCE
+	}
+}
 
-					// Wait before attempting reconnection
-					tokio::time::sleep(Duration::from_secs(5)).await;
-				}
-			}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrintJob {
+	pub progress: f64,
+	/// Which report field `progress` came from
+	pub progress_source: ProgressSource,
+	pub time_remaining: i64,
+	pub estimated_total_time: Option<i64>,
+	/// Display name of the job (`subtask_name`)
+	pub file_name: String,
+	/// Path of the file being printed, e.g. `"/sdcard/benchy.gcode.3mf"`
+	pub gcode_file: Option<String>,
+	pub print_type: Option<String>,
+	pub layer_current: i32,
+	pub layer_total: i32,
+	pub speed_level: Option<i32>,
+	/// Actual speed in percent of normal (`spd_mag`), e.g. 124 on Sport;
+	/// clamped to 30-200
+	pub speed_magnitude: Option<i32>,
+	pub fan_speed: Option<i32>,
+	pub fan_speeds: Option<FanSpeeds>,
+	pub stage: Option<i32>,
+	/// Motion controller stage (`mc_print_stage`): 1 idle, 2 running the job,
+	/// which includes the end G-code's cooldown after the last layer
+	pub print_stage: Option<i32>,
+	/// Step within `print_stage` (`mc_print_sub_stage`)
+	pub sub_stage: Option<i32>,
+	pub lifecycle: Option<String>,
+	/// Estimated filament use for the job in grams, if the firmware reports it
+	pub filament_weight: Option<f64>,
+	/// Estimated filament use for the job in mm, if the firmware reports it
+	pub filament_length: Option<f64>,
+	/// Seconds since `Printer::print_start`
+	pub elapsed_seconds: Option<i64>,
+	/// Why the print is paused, e.g. `"filament_runout"`; only set while paused
+	pub pause_reason: Option<String>,
+}
+
+impl PrintJob {
+	/// Reason for a pause, from the stage (`stg_cur`) and the error code.
+	fn pause_reason(stg_cur: i64, error_code: i32) -> Option<&'static str> {
+		match (stg_cur, error_code) {
+			(6, _) | (_, 1203) => Some("filament_runout"),
+			(_, 1204) => Some("filament_tangle"),
+			(16, _) => Some("user_requested"),
+			// M600 goes through the filament change stage
+			(4, _) => Some("color_change"),
+			// M400 U1 or an explicit pause in the G-code
+			(5 | 30, _) => Some("gcode_pause"),
+			(17, _) => Some("front_cover_removed"),
+			(20 | 21, _) => Some("temperature_malfunction"),
+			(26, _) => Some("ams_offline"),
+			_ => None,
 		}
 	}
 
-	async fn handle_printer_message(
-		printer_states: &Arc<RwLock<HashMap<String, Printer>>>,
-		printer_mqtt_states: &Arc<RwLock<HashMap<String, serde_json::Value>>>,
-		app_handle: &AppHandle,
-		config: &PrinterConfig,
-		data: &serde_json::Value,
-	) {
-		debug!("Processing MQTT data for {}: {}", config.name, data);
+	/// Stages (`stg_cur`) the printer runs through before the first layer:
+	/// heating, levelling, homing, calibrating and the like.
+	fn is_preparation_stage(stg_cur: i64) -> bool {
+		matches!(stg_cur, 1..=3 | 7..=9 | 11..=15 | 18 | 19)
+	}
+}
 
-		// Get or initialize persistent state for this printer
-		let persistent_state = {
-			let mut mqtt_states = printer_mqtt_states.write().await;
-			let current_state = mqtt_states
-				.get(&config.id)
-				.cloned()
-				.unwrap_or_else(|| serde_json::Value::Object(serde_json::Map::new()));
+/// Where a job's progress percentage comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressSource {
+	/// The main controller's own percentage (`mc_percent`)
+	McPercent,
+	/// `layer_num` out of `total_layer_num`, when `mc_percent` is missing
+	Layers,
+	/// Nothing to go on yet, or restored from the last session
+	Unavailable,
+}
 
-			// Deep merge the incoming data with existing state
-			let merged_state = Self::deep_merge(current_state, data.clone());
-			mqtt_states.insert(config.id.clone(), merged_state.clone());
-			merged_state
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ProgressEstimate {
+	/// 0 to 100
+	pub percent: f64,
+	pub source: ProgressSource,
+}
+
+/// Best progress percentage for a `print` report. `mc_percent` wins when
+/// the printer reports it; some firmware leaves it at 0 for the first part of
+/// a job while layers are already counting, so the layer ratio fills in.
+pub(crate) fn estimate_progress(print_data: &serde_json::Value) -> ProgressEstimate {
+	let mc_percent = print_data
+		.get("mc_percent")
+		.and_then(|v| v.as_f64())
+		.unwrap_or(0.0);
+	if mc_percent > 0.0 && mc_percent <= 100.0 {
+		return ProgressEstimate {
+			percent: mc_percent,
+			source: ProgressSource::McPercent,
 		};
+	}
 
-		// Check if we need to request full status due to minimal data
-		let print_keys: Vec<String> = if let Some(print_obj) = data.get("print") {
-			if let Some(obj) = print_obj.as_object() {
-				obj.keys().cloned().collect()
-			} else {
-				Vec::new()
-			}
-		} else {
-			Vec::new()
+	let layer = |key: &str| print_data.get(key).and_then(|v| v.as_i64()).unwrap_or(0);
+	let (layer_current, layer_total) = (layer("layer_num"), layer("total_layer_num"));
+	// An out-of-range `mc_percent` is ignored, but a real 0% holds
+	if mc_percent == 0.0 && layer_current > 0 && layer_total > 0 {
+		return ProgressEstimate {
+			percent: (layer_current as f64 / layer_total as f64 * 100.0).clamp(0.0, 100.0),
+			source: ProgressSource::Layers,
 		};
+	}
 
-		let is_minimal_update = print_keys.len() <= 3
-			&& (data
-				.get("print")
-				.and_then(|p| p.get("command"))
-				.and_then(|c| c.as_str())
-				== Some("push_status")
-				|| data
-					.get("print")
-					.and_then(|p| p.get("wifi_signal"))
-					.is_some());
+	ProgressEstimate {
+		percent: 0.0,
+		source: ProgressSource::Unavailable,
+	}
+}
 
-		if is_minimal_update {
-			info!("Minimal data detected for {}, but avoiding frequent status requests to prevent hardware lag", config.name);
-			// Note: Based on OpenBambuAPI docs, frequent status requests can cause lag on P1P printers
-			// We rely on the initial status request and accumulated state instead
-		}
+/// Individual fan speeds as percentages (0-100).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FanSpeeds {
+	/// Part cooling fan (`cooling_fan_speed`)
+	pub part: i32,
+	/// Auxiliary side fan (`big_fan1_speed`)
+	pub aux: i32,
+	/// Chamber exhaust fan (`big_fan2_speed`)
+	pub chamber: i32,
+}
 
-		info!(
-			"Raw MQTT data from {}: {}",
-			config.name,
-			serde_json::to_string_pretty(data).unwrap_or_default()
-		);
-		info!(
-			"Accumulated state for {}: {}",
-			config.name,
-			serde_json::to_string_pretty(&persistent_state).unwrap_or_default()
-		);
+impl FanSpeeds {
+	/// Bambu reports fan speeds as a 0-15 gear
+	const MAX_GEAR: i64 = 15;
 
-		Self::update_printer_status(
-            printer_states,
-            app_handle,
-            &config.id,
-            |printer| {
-                // Parse print data from accumulated state instead of just current message
-                if let Some(print_data) = persistent_state.get("print") {
-                    // Update temperatures
-                    if let Some(nozzle_temp) = print_data.get("nozzle_temper").and_then(|v| v.as_f64()) {
-                        printer.temperatures.nozzle = nozzle_temp.round() as i32;
-                    }
-                    if let Some(bed_temp) = print_data.get("bed_temper").and_then(|v| v.as_f64()) {
-                        printer.temperatures.bed = bed_temp.round() as i32;
-                    }
-                    if let Some(chamber_temp) = print_data.get("chamber_temper").and_then(|v| v.as_f64()) {
-                        printer.temperatures.chamber = chamber_temp.round() as i32;
-                    }
-
-                    // Enhanced status detection logic based on accumulated state
-                    let gcode_state = print_data.get("gcode_state").and_then(|v| v.as_str());
-                    let print_real = print_data.get("print_real").and_then(|v| v.as_i64()).unwrap_or(0);
-                    let mc_remaining_time = print_data.get("mc_remaining_time").and_then(|v| v.as_i64()).unwrap_or(0);
-                    let mc_percent = print_data.get("mc_percent").and_then(|v| v.as_f64()).unwrap_or(0.0);
-                    let layer_num = print_data.get("layer_num").and_then(|v| v.as_i64()).unwrap_or(0);
-                    let stg_cur = print_data.get("stg_cur").and_then(|v| v.as_i64()).unwrap_or(0);
-                    let print_error = print_data.get("print_error").and_then(|v| v.as_i64()).unwrap_or(0);
-                    let fan_gear = print_data.get("fan_gear").and_then(|v| v.as_i64()).unwrap_or(0);
-                    let subtask_name = print_data.get("subtask_name").and_then(|v| v.as_str()).unwrap_or("");
-
-                    info!("Status detection for {}: gcode_state={:?}, print_real={}, mc_percent={}, layer_num={}, stg_cur={}, print_error={}, mc_remaining_time={}, fan_gear={}, subtask_name={:?}",
-                        config.name, gcode_state, print_real, mc_percent, layer_num, stg_cur, print_error, mc_remaining_time, fan_gear, subtask_name);
-
-                    // Calculate key indicators for status detection
-                    let has_active_job = mc_remaining_time > 0 || (layer_num > 0 && mc_percent < 100.0);
-                    let has_progress = mc_percent > 0.0 && mc_percent < 100.0;
-                    let has_job_name = !subtask_name.is_empty() && subtask_name != "Unknown" && subtask_name != "undefined";
-                    let has_high_temps = printer.temperatures.nozzle > 150 || printer.temperatures.bed > 40;
-                    let has_active_fan = fan_gear > 0;
-                    let is_in_print_stage = stg_cur == 1 || stg_cur == 2 || stg_cur == 3;
-
-                    // Primary status detection: Start with the most reliable indicators
-                    let new_status = if print_error > 0 {
-                        info!("Status for {}: Error (print_error={})", config.name, print_error);
-                        PrinterStatus::Error
-                    } else if print_real == 1 {
-                        // print_real is the most reliable indicator of actual printing
-                        info!("Status for {}: Printing (print_real=1)", config.name);
-                        PrinterStatus::Printing
-                    } else if let Some(gcode_state) = gcode_state {
-                        // Use gcode_state when available
-                        match gcode_state {
-                            // Standard states
-                            "RUNNING" | "PRINTING" => {
-                                info!("Status for {}: Printing (gcode_state={})", config.name, gcode_state);
-                                PrinterStatus::Printing
-                            },
-                            "PAUSE" | "PAUSED" => {
-                                info!("Status for {}: Paused (gcode_state={})", config.name, gcode_state);
-                                PrinterStatus::Paused
-                            },
-                            "FAILED" | "ERROR" => {
-                                info!("Status for {}: Error (gcode_state={})", config.name, gcode_state);
-                                PrinterStatus::Error
-                            },
-                            "FINISH" | "FINISHED" => {
-                                info!("Status for {}: Idle (gcode_state={})", config.name, gcode_state);
-                                PrinterStatus::Idle
-                            },
-                            // Bambu Lab specific states
-                            "PREPARE" | "WORKING" | "SLICING" | "PRINTING_MONITOR" => {
-                                info!("Status for {}: Printing (Bambu gcode_state={})", config.name, gcode_state);
-                                PrinterStatus::Printing
-                            },
-                            "IDLE" => {
-                                // Even if gcode_state is IDLE, check other indicators
-                                if has_active_job || has_progress || (has_high_temps && has_active_fan) {
-                                    info!("Status for {}: Printing (gcode_state=IDLE but has active indicators)", config.name);
-                                    PrinterStatus::Printing
-                                } else {
-                                    info!("Status for {}: Idle (gcode_state=IDLE, no active indicators)", config.name);
-                                    PrinterStatus::Idle
-                                }
-                            },
-                            _ => {
-                                // Unknown gcode_state, use comprehensive fallback logic
-                                info!("Status for {} (unknown gcode_state={}): using fallback logic", config.name, gcode_state);
-                                Self::determine_status_from_indicators(
-                                    &config.name,
-                                    has_active_job,
-                                    has_progress,
-                                    is_in_print_stage,
-                                    stg_cur,
-                                    has_high_temps,
-                                    has_active_fan,
-                                    has_job_name,
-                                    mc_remaining_time,
-                                    layer_num,
-                                    printer.temperatures.nozzle,
-                                )
-                            }
-                        }
-                    } else {
-                        // No gcode_state available, use comprehensive fallback logic
-                        info!("Status for {} (no gcode_state): using comprehensive fallback logic", config.name);
-                        Self::determine_status_from_indicators(
-                            &config.name,
-                            has_active_job,
-                            has_progress,
-                            is_in_print_stage,
-                            stg_cur,
-                            has_high_temps,
-                            has_active_fan,
-                            has_job_name,
-                            mc_remaining_time,
-                            layer_num,
-                            printer.temperatures.nozzle,
-                        )
-                    };
-
-                    // Apply the determined status with validation
-                    let previous_status = printer.status.clone();
-                    let should_update_status = match (&previous_status, &new_status) {
-                        // Allow any change to/from Error or Offline
-                        (PrinterStatus::Error, _) | (_, PrinterStatus::Error) => true,
-                        (PrinterStatus::Offline, _) | (_, PrinterStatus::Offline) => true,
-                        (PrinterStatus::Connecting, _) | (_, PrinterStatus::Connecting) => true,
-
-                        // Allow transitions from Idle to Printing if we have strong indicators
-                        (PrinterStatus::Idle, PrinterStatus::Printing) => {
-                            has_active_job || has_progress || print_real == 1 || (has_high_temps && has_active_fan)
-                        },
-
-                        // Be more cautious about transitions from Printing to Idle
-                        (PrinterStatus::Printing, PrinterStatus::Idle) => {
-                            // Only allow if we have strong evidence that printing has stopped
-                            let has_completion_indicators = mc_percent >= 100.0 ||
-                                                           (mc_remaining_time == 0 && layer_num == 0) ||
-                                                           (!has_high_temps && !has_active_fan && !has_active_job);
-
-                            if has_completion_indicators {
-                                info!("Status for {}: Allowing transition from Printing to Idle (completion indicators)", config.name);
-                                true
-                            } else {
-                                info!("Status for {}: Preventing spurious transition from Printing to Idle (active indicators still present)", config.name);
-                                false
-                            }
-                        },
-
-                        // Allow other transitions
-                        _ => true,
-                    };
-
-                    if should_update_status {
-                        let status_changed = !matches!((&previous_status, &new_status),
-                            (PrinterStatus::Idle, PrinterStatus::Idle) |
-                            (PrinterStatus::Printing, PrinterStatus::Printing) |
-                            (PrinterStatus::Paused, PrinterStatus::Paused) |
-                            (PrinterStatus::Error, PrinterStatus::Error) |
-                            (PrinterStatus::Offline, PrinterStatus::Offline) |
-                            (PrinterStatus::Connecting, PrinterStatus::Connecting)
-                        );
-
-                        if status_changed {
-                            info!("Status for {}: Changed from {:?} to {:?}", config.name, previous_status, new_status);
-                        }
-                        printer.status = new_status;
-                    } else {
-                        info!("Status for {}: Keeping previous status {:?} (transition validation failed)", config.name, previous_status);
-                    }
-
-                    // Update print job info if printing/paused or if we have print data
-                    if matches!(printer.status, PrinterStatus::Printing | PrinterStatus::Paused) ||
-                       mc_remaining_time > 0 || layer_num > 0 || mc_percent > 0.0 || print_real == 1 {
-
-                        // Calculate estimated total time if we have progress and remaining time
-                        let estimated_total_time = if mc_percent > 0.0 && mc_remaining_time > 0 {
-                            let remaining_seconds = mc_remaining_time * 60;
-                            Some((remaining_seconds as f64 / (1.0 - mc_percent / 100.0)).round() as i64)
-                        } else {
-                            None
-                        };
-
-                        // Calculate the best progress percentage using multiple indicators
-                        let mut best_progress = 0.0;
-
-                        // Source 1: mc_percent (main controller percentage)
-                        if mc_percent > 0.0 && mc_percent <= 100.0 {
-                            best_progress = mc_percent;
-                        }
-
-                        // Source 2: Layer-based progress
-                        let layer_current = layer_num as i32;
-                        let layer_total = print_data.get("total_layer_num").and_then(|v| v.as_i64()).unwrap_or(0) as i32;
-                        if layer_current > 0 && layer_total > 0 {
-                            let layer_progress = (layer_current as f64 / layer_total as f64) * 100.0;
-                            // Use layer progress if mc_percent is not available or seems unreliable
-                            if mc_percent == 0.0 {
-                                best_progress = layer_progress;
-                            }
-                        }
-
-                        // Source 3: Time-based progress (if we have both remaining and total time)
-                        if let Some(total_time) = estimated_total_time {
-                            if mc_remaining_time > 0 && total_time > 0 {
-                                let elapsed_time = total_time - (mc_remaining_time * 60);
-                                let time_progress = (elapsed_time as f64 / total_time as f64) * 100.0;
-                                if (0.0..=100.0).contains(&time_progress) {
-                                    // Use time progress as a fallback or validation
-                                    if mc_percent == 0.0 {
-                                        best_progress = best_progress.max(time_progress);
-                                    }
-                                }
-                            }
-                        }
-
-                        // Validation: Ensure progress is reasonable
-                        best_progress = best_progress.clamp(0.0, 100.0);
-
-                        let file_name = if !subtask_name.is_empty() && subtask_name != "undefined" {
-                            subtask_name.to_string()
-                        } else {
-                            "Unknown".to_string()
-                        };
-
-                        printer.print = Some(PrintJob {
-                            progress: best_progress,
-                            time_remaining: mc_remaining_time * 60, // Convert minutes to seconds
-                            estimated_total_time,
-                            file_name,
-                            print_type: print_data.get("print_type").and_then(|v| v.as_str()).map(|s| s.to_string()),
-                            layer_current,
-                            layer_total,
-                            speed_level: print_data.get("spd_lvl").and_then(|v| v.as_i64()).map(|v| v as i32),
-                            fan_speed: print_data.get("fan_gear").and_then(|v| v.as_i64()).map(|v| v as i32),
-                            stage: print_data.get("stg_cur").and_then(|v| v.as_i64()).map(|v| v as i32),
-                            lifecycle: print_data.get("lifecycle").and_then(|v| v.as_str()).map(|s| s.to_string()),
-                        });
-                    } else {
-                        printer.print = None;
-                    }
-
-                    // Check for errors
-                    let print_error = print_data.get("print_error").and_then(|v| v.as_i64()).unwrap_or(0) as i32;
-                    let error_code = print_data.get("mc_print_error_code").and_then(|v| v.as_i64()).unwrap_or(0) as i32;
-
-                    if print_error > 0 || error_code > 0 {
-                        printer.status = PrinterStatus::Error;
-                        printer.error = Some(PrinterError {
-                            print_error,
-                            error_code,
-                            stage: print_data.get("stg_cur").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
-                            lifecycle: print_data.get("lifecycle").and_then(|v| v.as_str()).unwrap_or("Unknown").to_string(),
-                            gcode_state: print_data.get("gcode_state").and_then(|v| v.as_str()).unwrap_or("Unknown").to_string(),
-                            message: Self::get_error_message(print_error, error_code),
-                        });
-                    } else {
-                        printer.error = None;
-                    }
-                }
-
-                printer.last_update = Utc::now();
-            }
-        ).await;
+	fn from_print_data(print_data: &serde_json::Value) -> Option<Self> {
+		let part = print_data.get("cooling_fan_speed").and_then(value_as_i64);
+		let aux = print_data.get("big_fan1_speed").and_then(value_as_i64);
+		let chamber = print_data.get("big_fan2_speed").and_then(value_as_i64);
+
+		if part.is_none() && aux.is_none() && chamber.is_none() {
+			return None;
+		}
+
+		Some(Self {
+			part: Self::gear_to_percent(part.unwrap_or(0)),
+			aux: Self::gear_to_percent(aux.unwrap_or(0)),
+			chamber: Self::gear_to_percent(chamber.unwrap_or(0)),
+		})
 	}
 
-	async fn update_printer_status<F>(
-		printer_states: &Arc<RwLock<HashMap<String, Printer>>>,
-		app_handle: &AppHandle,
-		printer_id: &str,
-		update_fn: F,
-	) where
-		F: FnOnce(&mut Printer),
-	{
-		let updated_printer = {
-			let mut states = printer_states.write().await;
-			if let Some(printer) = states.get_mut(printer_id) {
-				update_fn(printer);
-				Some(printer.clone())
-			} else {
-				None
+	/// Builds the `M106` command setting `fan` to `percent` (0-100).
+	fn gcode(fan: &str, percent: u8) -> Result<String> {
+		let index = match fan {
+			"part" => 1,
+			"aux" => 2,
+			"chamber" => 3,
+			_ => {
+				return Err(anyhow!(
+					"Unknown fan '{fan}', expected 'part', 'aux' or 'chamber'"
+				))
 			}
 		};
-
-		if let Some(printer) = updated_printer {
-			// Emit update to frontend
-			if let Err(e) = app_handle.emit("printer-update", &printer) {
-				error!("Failed to emit printer update: {e}");
-			}
-		}
+		let pwm = (f64::from(percent.min(100)) * 255.0 / 100.0).round() as u8;
+		Ok(format!("M106 P{index} S{pwm}"))
 	}
 
-	async fn emit_printer_update(&self, printer: &Printer) {
-		if let Err(e) = self.app_handle.emit("printer-update", printer) {
-			error!("Failed to emit printer update: {e}");
-		}
+	fn gear_to_percent(gear: i64) -> i32 {
+		let gear = gear.clamp(0, Self::MAX_GEAR);
+		(gear as f64 * 100.0 / Self::MAX_GEAR as f64).round() as i32
 	}
+}
 
-	// Deep merge new data into existing state
-	fn deep_merge(mut base: serde_json::Value, new: serde_json::Value) -> serde_json::Value {
-		match (&mut base, new) {
-			(serde_json::Value::Object(base_map), serde_json::Value::Object(new_map)) => {
-				for (key, value) in new_map {
-					match base_map.get_mut(&key) {
-						Some(existing) => {
-							// For critical status fields, preserve existing values if new values are empty/null
-							if key == "subtask_name" && value.as_str().unwrap_or("").is_empty() {
-								if let Some(existing_str) = existing.as_str() {
-									if !existing_str.is_empty()
-										&& existing_str != "Unknown"
-										&& existing_str != "undefined"
-									{
-										// Keep existing non-empty subtask_name
-										continue;
-									}
-								}
-							}
+/// What the hardware can do, so the UI knows which controls to show and
+/// commands can refuse what the printer doesn't support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrinterCapabilities {
+	/// Can take an AMS (an AMS lite on the A1 series)
+	pub has_ams: bool,
+	pub has_chamber_fan: bool,
+	/// Reports a real chamber temperature
+	pub has_chamber_temp: bool,
+	/// Has a chamber camera, which can record timelapses
+	pub has_camera: bool,
+	/// Watches the print for spaghetti with the camera
+	pub has_spaghetti_detection: bool,
+	/// Checks the first layer with the lidar (X1 series)
+	pub has_first_layer_inspection: bool,
+	/// Highest nozzle temperature the firmware accepts, in °C
+	pub max_nozzle_temp: i32,
+}
 
-							// For progress fields, don't overwrite with zero unless it's actually finished
-							if key == "mc_percent" && value.as_f64().unwrap_or(0.0) == 0.0 {
-								if let Some(existing_percent) = existing.as_f64() {
-									if existing_percent > 0.0 && existing_percent < 100.0 {
-										// Keep existing progress unless we have evidence the print is done
-										continue;
-									}
-								}
-							}
+impl PrinterCapabilities {
+	/// Capabilities for a `PrinterConfig.model` such as `"X1C"` or `"A1-mini"`.
+	/// Unrecognized models get the benefit of the doubt.
+	pub fn for_model(model: &str) -> Self {
+		let model: String = model
+			.chars()
+			.filter(|c| c.is_ascii_alphanumeric())
+			.map(|c| c.to_ascii_uppercase())
+			.collect();
 
-							// For remaining time, don't overwrite with zero unless progress is 100%
-							if key == "mc_remaining_time" && value.as_i64().unwrap_or(0) == 0 {
-								if let Some(existing_time) = existing.as_i64() {
-									if existing_time > 0 {
-										// Keep existing remaining time if it's positive and new value is zero
-										// This prevents losing time data from partial updates
-										continue;
-									}
-								}
-							}
+		let (has_chamber_fan, has_chamber_temp, max_nozzle_temp) = match model.as_str() {
+			"X1E" => (true, true, 320),
+			"X1" | "X1C" | "X1CARBON" => (true, true, 300),
+			"P1S" => (true, false, 300),
+			"P1P" | "A1" | "A1MINI" => (false, false, 300),
+			_ => (true, true, 300),
+		};
+		let has_lidar = !matches!(model.as_str(), "P1S" | "P1P" | "A1" | "A1MINI");
 
-							*existing = Self::deep_merge(existing.clone(), value);
-						}
-						None => {
-							base_map.insert(key, value);
-						}
-					}
-				}
-				base
-			}
-			(_, new_value) => new_value,
+		Self {
+			has_ams: true,
+			has_chamber_fan,
+			has_chamber_temp,
+			// Every model so far ships with one
+			has_camera: true,
+			// The P1P's camera is an add-on the detection doesn't run on
+			has_spaghetti_detection: model != "P1P",
+			has_first_layer_inspection: has_lidar,
+			max_nozzle_temp,
 		}
 	}
+}
 
-	fn get_error_message(print_error: i32, error_code: i32) -> String {
-		match (print_error, error_code) {
-			(_, 1203) => "Filament runout detected".to_string(),
-			(_, 1204) => "Filament tangle detected".to_string(),
-			(_, 1205) => "Nozzle clog detected".to_string(),
-			(1, _) => "Print error occurred".to_string(),
-			(2, _) => "Bed adhesion failure".to_string(),
-			(3, _) => "Temperature error".to_string(),
-			_ => format!("Error: print_error={print_error}, error_code={error_code}"),
+/// Maintenance routines to run with `run_calibration`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalibrationOptions {
+	#[serde(default)]
+	pub bed_level: bool,
+	/// Vibration compensation (input shaping)
+	#[serde(default)]
+	pub vibration: bool,
+	#[serde(default)]
+	pub motor_noise: bool,
+}
+
+impl CalibrationOptions {
+	/// Value for the `option` field of the `calibration` command. Bit 0 is the
+	/// X1's lidar calibration, which we don't offer.
+	pub fn bitmask(&self) -> u64 {
+		let mut mask = 0;
+		if self.bed_level {
+			mask |= 1 << 1;
+		}
+		if self.vibration {
+			mask |= 1 << 2;
+		}
+		if self.motor_noise {
+			mask |= 1 << 3;
 		}
+		mask
 	}
 
-	pub async fn send_command(&self, printer_id: &str, command: PrintCommand) -> Result<()> {
-		self
-			.command_sender
-			.send((printer_id.to_string(), command))
-			.map_err(|e| anyhow!("Failed to send command: {}", e))?;
-		Ok(())
+	/// Description of a calibration step reported in `stg_cur`, or `None`
+	/// when the stage isn't part of a calibration.
+	pub fn stage_name(stg_cur: i64) -> Option<&'static str> {
+		match stg_cur {
+			1 => Some("Auto bed leveling"),
+			3 => Some("Vibration compensation"),
+			25 => Some("Motor noise calibration"),
+			// Plays the motors back with the new compensation to confirm it
+			31 => Some("Motor noise check"),
+			_ => None,
+		}
 	}
+}
 
-	pub async fn get_all_printers(&self) -> Vec<Printer> {
-		let states = self.printer_states.read().await;
-		states.values().cloned().collect()
+/// Settings for `start_print`, matching what Bambu Studio asks when sending
+/// a job.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StartPrintOptions {
+	/// Plate of the project to print; plate 1 when unset
+	#[serde(default)]
+	pub plate: Option<u32>,
+	#[serde(default)]
+	pub bed_leveling: bool,
+	/// Flow dynamics calibration before the print
+	#[serde(default)]
+	pub flow_cali: bool,
+	#[serde(default)]
+	pub vibration_cali: bool,
+	/// First layer inspection, on printers with a lidar
+	#[serde(default)]
+	pub layer_inspect: bool,
+	#[serde(default)]
+	pub timelapse: bool,
+	/// Feed filament from the AMS rather than the external spool
+	#[serde(default)]
+	pub use_ams: bool,
+}
+
+/// Job name for a project file, e.g. `"benchy"` for `/cache/benchy.gcode.3mf`.
+fn project_job_name(file: &str) -> &str {
+	let name = file.rsplit('/').next().unwrap_or(file);
+	name
+		.strip_suffix(".gcode.3mf")
+		.or_else(|| name.strip_suffix(".3mf"))
+		.unwrap_or(name)
+}
+
+/// Links the printer reports on itself in `print.online`, as opposed to our
+/// MQTT connection to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceLinks {
+	/// Link to the AMS hub board
+	pub ahb: Option<bool>,
+	/// Spool RFID reader
+	pub rfid: Option<bool>,
+}
+
+impl DeviceLinks {
+	/// Whether a link the printer needs is reported down: the AMS hub while AMS
+	/// units are attached. A field left out of the report never counts as down.
+	fn degraded(&self, has_ams: bool) -> bool {
+		has_ams && self.ahb == Some(false)
 	}
+}
 
-	pub async fn remove_printer(&self, printer_id: &str) -> Result<()> {
-		// Remove from states
-		{
-			let mut states = self.printer_states.write().await;
-			states.remove(printer_id);
+/// Chamber camera state, from `print.ipcam`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CameraStatus {
+	pub recording: bool,
+	pub timelapse: bool,
+	/// e.g. `"1080p"`
+	pub resolution: Option<String>,
+}
+
+impl CameraStatus {
+	/// `None` for printers without a camera, which report `ipcam_dev` as `"0"`
+	/// or leave out `ipcam` altogether.
+	fn from_print_data(print_data: &serde_json::Value) -> Option<Self> {
+		let ipcam = print_data.get("ipcam")?;
+		if ipcam.get("ipcam_dev").and_then(|v| v.as_str()) == Some("0") {
+			return None;
 		}
+		let enabled = |key: &str| ipcam.get(key).and_then(|v| v.as_str()) == Some("enable");
 
-		// Remove from MQTT states
-		{
-			let mut mqtt_states = self.printer_mqtt_states.write().await;
-			mqtt_states.remove(printer_id);
+		Some(Self {
+			recording: enabled("ipcam_record"),
+			timelapse: enabled("timelapse"),
+			resolution: ipcam
+				.get("resolution")
+				.and_then(|v| v.as_str())
+				.filter(|s| !s.is_empty())
+				.map(|s| s.to_string()),
+		})
+	}
+}
+
+/// Result of the printer's own print monitoring, from its `xcam` HMS notices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectionStatus {
+	/// Past the first layer with inspection on and no defect reported
+	FirstLayerOk,
+	FirstLayerDefect,
+	SpaghettiDetected,
+}
+
+impl DetectionStatus {
+	/// `0C00_0300_0003_0008`
+	const SPAGHETTI_HMS: (u32, u32) = (0x0C00_0300, 0x0003_0008);
+	/// `0C00_0300_0003_0007`
+	const FIRST_LAYER_DEFECT_HMS: (u32, u32) = (0x0C00_0300, 0x0003_0007);
+
+	/// `None` when the model can't tell, or there's nothing to tell yet.
+	/// Anything it can't do is ignored even if reported.
+	fn from_report(printer: &Printer, print_data: &serde_json::Value) -> Option<Self> {
+		let capabilities = &printer.capabilities;
+		let reported = |hms: (u32, u32)| printer.hms.iter().any(|code| (code.attr, code.code) == hms);
+
+		if capabilities.has_spaghetti_detection && reported(Self::SPAGHETTI_HMS) {
+			return Some(DetectionStatus::SpaghettiDetected);
+		}
+		if !capabilities.has_first_layer_inspection {
+			return None;
+		}
+		if reported(Self::FIRST_LAYER_DEFECT_HMS) {
+			return Some(DetectionStatus::FirstLayerDefect);
 		}
 
-		// Remove from connection pool
-		{
-			let mut connections = self.printer_connections.write().await;
-			connections.remove(printer_id);
+		let inspecting = print_data
+			.get("xcam")
+			.and_then(|xcam| xcam.get("first_layer_inspector"))
+			.and_then(|v| v.as_bool())
+			.unwrap_or(false);
+		let past_first_layer = matches!(
+			printer.status,
+			PrinterStatus::Printing | PrinterStatus::Paused
+		) && printer
+			.print
+			.as_ref()
+			.is_some_and(|job| job.layer_current > 1);
+		(inspecting && past_first_layer).then_some(DetectionStatus::FirstLayerOk)
+	}
+}
+
+/// SD card state. Every printer reports `sdcard`; only some firmware adds
+/// a `storage` object with free space.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageInfo {
+	pub present: bool,
+	pub free_mb: Option<i64>,
+}
+
+impl StorageInfo {
+	fn from_print_data(print_data: &serde_json::Value) -> Option<Self> {
+		let present = print_data.get("sdcard").and_then(|v| v.as_bool());
+		let free_mb = print_data
+			.get("storage")
+			.and_then(|storage| storage.get("free"))
+			.and_then(value_as_i64)
+			.filter(|free| *free >= 0);
+
+		if present.is_none() && free_mb.is_none() {
+			return None;
 		}
 
-		// Emit removal to frontend
-		if let Err(e) = self.app_handle.emit("printer-removed", printer_id) {
-			error!("Failed to emit printer removal: {e}");
+		Some(Self {
+			present: present.unwrap_or(free_mb.is_some()),
+			free_mb,
+		})
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilamentInfo {
+	pub r#type: String,
+	pub color: String,
+	/// `"#000000"` or `"#ffffff"`, whichever reads better on `color`
+	pub text_on_color: String,
+	pub remaining: f64,
+}
+
+impl FilamentInfo {
+	const DARK_TEXT: &'static str = "#000000";
+	const LIGHT_TEXT: &'static str = "#ffffff";
+
+	/// Text color for a label on a swatch of `color`, an `RRGGBB` or
+	/// `RRGGBBAA` hex string. Unparseable and fully transparent colors (an
+	/// empty tray reports `"00000000"`) get dark text, as the swatch then
+	/// shows the background.
+	pub fn text_on_color(color: &str) -> &'static str {
+		let hex = color.trim().trim_start_matches('#');
+		if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+			return Self::DARK_TEXT;
+		}
+		let channel = |index: usize| u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).ok();
+		let alpha = if hex.len() == 8 {
+			channel(3)
+		} else {
+			Some(u8::MAX)
+		};
+		let (Some(red), Some(green), Some(blue), Some(alpha)) =
+			(channel(0), channel(1), channel(2), alpha)
+		else {
+			return Self::DARK_TEXT;
+		};
+		if alpha == 0 {
+			return Self::DARK_TEXT;
 		}
 
-		info!("Removed printer: {printer_id}");
-		Ok(())
+		// Relative luminance as defined by WCAG
+		let linear = |value: u8| {
+			let value = f64::from(value) / 255.0;
+			if value <= 0.04045 {
+				value / 12.92
+			} else {
+				((value + 0.055) / 1.055).powf(2.4)
+			}
+		};
+		let luminance = 0.2126 * linear(red) + 0.7152 * linear(green) + 0.0722 * linear(blue);
+
+		// The point where black and white text have equal contrast
+		if luminance > 0.179 {
+			Self::DARK_TEXT
+		} else {
+			Self::LIGHT_TEXT
+		}
 	}
+}
 
-	#[allow(clippy::too_many_arguments)]
+/// One AMS unit. Printers with chained AMS report several, printers
+/// without one report none.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmsUnit {
+	pub id: u8,
+	/// Humidity level from 1 to 5, as shown on the printer
+	pub humidity: Option<u8>,
+	/// Temperature inside the unit in °C; not every AMS model reports it
+	pub temperature: Option<f64>,
+	pub trays: Vec<AmsTray>,
+}
+
+/// A filament slot in an AMS unit. Empty slots carry only their `id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmsTray {
+	pub id: u8,
+	pub filament_type: Option<String>,
+	/// RGBA hex, e.g. `"FFFFFFFF"`
+	pub color: Option<String>,
+	/// Readable label color on `color`; see `FilamentInfo::text_on_color`
+	pub text_on_color: Option<String>,
+	/// Remaining filament in percent, when the spool is RFID-tagged
+	pub remaining: Option<i32>,
+}
+
+impl AmsUnit {
+	/// `tray_now` values for the external spool: the 255 sentinel, or 254 (the
+	/// spool's `vt_tray` id) on newer firmware. Anything lower is an AMS slot.
+	const EXTERNAL_SPOOL_TRAYS: [u8; 2] = [254, 255];
+
+	/// Parses `print.ams.ams`, skipping entries without a usable `id`.
+	fn from_print_data(print_data: &serde_json::Value) -> Vec<Self> {
+		let Some(units) = print_data
+			.get("ams")
+			.and_then(|ams| ams.get("ams"))
+			.and_then(|units| units.as_array())
+		else {
+			return Vec::new();
+		};
+
+		units
+			.iter()
+			.filter_map(|unit| {
+				Some(Self {
+					id: unit.get("id").and_then(value_as_u8)?,
+					humidity: unit.get("humidity").and_then(value_as_u8),
+					temperature: unit
+						.get("temp")
+						.and_then(value_as_f64)
+						.filter(|temp| *temp > 0.0),
+					trays: unit
+						.get("tray")
+						.and_then(|trays| trays.as_array())
+						.map(|trays| trays.iter().filter_map(AmsTray::from_value).collect())
+						.unwrap_or_default(),
+				})
+			})
+			.collect()
+	}
+}
+
+impl AmsTray {
+	fn from_value(tray: &serde_json::Value) -> Option<Self> {
+		let non_empty = |key: &str| {
+			tray
+				.get(key)
+				.and_then(|v| v.as_str())
+				.filter(|s| !s.is_empty())
+				.map(|s| s.to_string())
+		};
+
+		let color = non_empty("tray_color");
+		Some(Self {
+			id: tray.get("id").and_then(value_as_u8)?,
+			filament_type: non_empty("tray_type"),
+			text_on_color: color
+				.as_deref()
+				.map(|color| FilamentInfo::text_on_color(color).to_string()),
+			color,
+			remaining: tray
+				.get("remain")
+				.and_then(value_as_i64)
+				.filter(|remain| *remain >= 0)
+				.map(|remain| remain as i32),
+		})
+	}
+}
+
+/// One filament slot in a flat list across every AMS unit and the external
+/// spool, so the UI doesn't have to care how the hardware is chained.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilamentSlot {
+	/// Numbered across units like `Printer::active_tray` (tray 0 of unit 1 is
+	/// 4), or `EXTERNAL_SPOOL_INDEX` for the external spool
+	pub index: u8,
+	/// `None` for the external spool
+	pub ams_id: Option<u8>,
+	pub tray_id: u8,
+	pub filament_type: Option<String>,
+	/// RGBA hex, e.g. `"FFFFFFFF"`
+	pub color: Option<String>,
+	pub text_on_color: Option<String>,
+	/// Remaining filament in percent, when the spool is RFID-tagged
+	pub remaining: Option<i32>,
+}
+
+impl FilamentSlot {
+	/// The external spool's id in `vt_tray` and `tray_now`
+	pub const EXTERNAL_SPOOL_INDEX: u8 = 254;
+	const TRAYS_PER_UNIT: u16 = 4;
+
+	/// AMS slots in chain order, then the external spool when the printer
+	/// reports one in `print.vt_tray`.
+	fn collect(units: &[AmsUnit], print_data: &serde_json::Value) -> Vec<Self> {
+		let mut slots: Vec<Self> = units
+			.iter()
+			.flat_map(|unit| {
+				unit.trays.iter().filter_map(move |tray| {
+					let index = u16::from(unit.id) * Self::TRAYS_PER_UNIT + u16::from(tray.id);
+					// Beyond the numbering, e.g. units with ids from 128 up
+					let index = u8::try_from(index)
+						.ok()
+						.filter(|index| *index < Self::EXTERNAL_SPOOL_INDEX)?;
+					Some(Self::from_tray(index, Some(unit.id), tray))
+				})
+			})
+			.collect();
+
+		if let Some(spool) = print_data.get("vt_tray").and_then(AmsTray::from_value) {
+			slots.push(Self::from_tray(Self::EXTERNAL_SPOOL_INDEX, None, &spool));
+		}
+		slots
+	}
+
+	fn from_tray(index: u8, ams_id: Option<u8>, tray: &AmsTray) -> Self {
+		Self {
+			index,
+			ams_id,
+			tray_id: tray.id,
+			filament_type: tray.filament_type.clone(),
+			color: tray.color.clone(),
+			text_on_color: tray.text_on_color.clone(),
+			remaining: tray.remaining,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrinterError {
+	pub print_error: i32,
+	pub error_code: i32,
+	pub stage: i32,
+	pub lifecycle: String,
+	pub gcode_state: String,
+	pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrinterStatus {
+	Idle,
+	/// A job has started but nothing is being laid down yet, e.g. while
+	/// slicing, heating or levelling the bed
+	Preparing,
+	Printing,
+	Paused,
+	Error,
+	Offline,
+	Connecting,
+	/// Monitoring switched off by the user; no connection is attempted
+	Disabled,
+}
+
+/// State of the MQTT session, as opposed to what the printer is doing.
+///
+/// Serializes to the lowercase strings the frontend already handles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+	Connecting,
+	Connected,
+	/// Connecting failed; another attempt follows after `RECONNECT_DELAY`
+	Failed,
+	/// Showing data that may be out of date, e.g. the last-known state from
+	/// the previous session before the printer has reported again
+	Stale,
+	/// The printer rejected the access code
+	AuthFailed,
+	/// An established session dropped and is being re-established
+	Reconnecting,
+	/// Monitoring switched off by the user
+	#[serde(rename = "paused")]
+	Disabled,
+	/// Gave up after `max_reconnect_attempts`; waits for `reconnect_printer`
+	Unreachable,
+}
+
+impl ConnectionState {
+	fn after_error(error: &rumqttc::ConnectionError, was_connected: bool) -> Self {
+		match error {
+			rumqttc::ConnectionError::ConnectionRefused(
+				rumqttc::ConnectReturnCode::BadUserNamePassword | rumqttc::ConnectReturnCode::NotAuthorized,
+			) => ConnectionState::AuthFailed,
+			_ if was_connected => ConnectionState::Reconnecting,
+			_ => ConnectionState::Failed,
+		}
+	}
+}
+
+/// Status transition produced by applying one MQTT message to a `Printer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusChange {
+	pub previous: PrinterStatus,
+	pub current: PrinterStatus,
+}
+
+impl StatusChange {
+	pub fn changed(&self) -> bool {
+		self.previous != self.current
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Printer {
+	pub id: String,
+	pub name: String,
+	pub model: String,
+	pub ip: String,
+	pub access_code: String,
+	pub serial: String,
+	pub status: PrinterStatus,
+	pub online: bool,
+	pub connection_state: ConnectionState,
+	pub temperatures: PrinterTemperatures,
+	pub capabilities: PrinterCapabilities,
+	/// Mirrors `PrinterConfig::notifications_enabled`
+	pub notifications_enabled: bool,
+	/// Mirrors `PrinterConfig::temperature_thresholds`
+	pub temperature_thresholds: TemperatureThresholds,
+	/// Mirrors `PrinterConfig::tags`
+	pub tags: Vec<String>,
+	/// Mirrors `PrinterConfig::location`
+	pub location: Option<String>,
+	pub print: Option<PrintJob>,
+	pub filament: Option<FilamentInfo>,
+	pub error: Option<PrinterError>,
+	/// Wi-Fi signal strength in dBm, as reported by the printer
+	pub wifi_signal: Option<i32>,
+	/// Installed nozzle diameter in mm (e.g. 0.4)
+	pub nozzle_diameter: Option<f64>,
+	/// Installed nozzle material, e.g. `"hardened_steel"`
+	pub nozzle_type: Option<String>,
+	/// Installed build plate, e.g. `"Textured PEI"`
+	pub bed_type: Option<String>,
+	/// Connected AMS units, in chain order
+	pub ams: Vec<AmsUnit>,
+	/// Every AMS slot and the external spool in one list
+	pub filament_slots: Vec<FilamentSlot>,
+	/// AMS slot currently feeding the nozzle, numbered across units (tray 0 of
+	/// unit 1 is 4). `None` when nothing is loaded or the external spool is in use.
+	pub active_tray: Option<u8>,
+	/// Whether the external spool is feeding the nozzle
+	pub external_spool_active: bool,
+	/// Active HMS (health management) notices, including non-fatal ones
+	pub hms: Vec<HmsCode>,
+	/// `None` until the printer reports its SD card state
+	pub storage: Option<StorageInfo>,
+	/// `None` for models without a camera
+	pub camera: Option<CameraStatus>,
+	/// What the printer's own print monitoring found, on models that have it
+	pub detection: Option<DetectionStatus>,
+	/// Whether spaghetti detection is on (`xcam.spaghetti_detector`); `None`
+	/// on models without it or until the printer reports it
+	pub ai_detection_enabled: Option<bool>,
+	/// Calibration step in progress, e.g. `"Auto bed leveling"`
+	pub calibration_stage: Option<String>,
+	/// Links the printer reports on itself; `None` until it does
+	pub device_links: Option<DeviceLinks>,
+	/// When we first saw the current job printing; cleared once the printer is idle
+	pub print_start: Option<DateTime<Utc>>,
+	/// Firmware version of the `ota` module, e.g. `"01.07.00.00"`
+	pub firmware_version: Option<String>,
+	/// Model name as the printer reports it, e.g. `"X1 Carbon"`
+	pub product_name: Option<String>,
+	pub last_update: DateTime<Utc>,
+	/// When the printer went offline, kept through reconnect attempts and
+	/// cleared once it connects
+	#[serde(skip)]
+	pub offline_since: Option<DateTime<Utc>>,
+	/// `(print_error, error_code)` the user dismissed with `clear_error`
+	#[serde(skip)]
+	pub acknowledged_error: Option<(i32, i32)>,
+}
+
+impl Printer {
+	/// Initial state for a newly added printer, before any report arrives.
+	pub fn new(config: &PrinterConfig) -> Self {
+		Self {
+			id: config.id.clone(),
+			name: config.name.clone(),
+			model: config.model.clone(),
+			ip: config.ip.clone(),
+			access_code: config.access_code.clone(),
+			serial: config.serial.clone(),
+			status: PrinterStatus::Connecting,
+			online: false,
+			connection_state: ConnectionState::Connecting,
+			temperatures: PrinterTemperatures {
+				nozzle: 0.0,
+				bed: 0.0,
+				chamber: 0.0,
+				nozzle_target: 0.0,
+				bed_target: 0.0,
+				nozzle_state: HeatingState::Off,
+				bed_state: HeatingState::Off,
+			},
+			capabilities: PrinterCapabilities::for_model(&config.model),
+			notifications_enabled: config.notifications_enabled,
+			temperature_thresholds: config.temperature_thresholds,
+			tags: config.tags.clone(),
+			location: config.location.clone(),
+			print: None,
+			filament: None,
+			error: None,
+			wifi_signal: None,
+			nozzle_diameter: None,
+			nozzle_type: None,
+			bed_type: None,
+			active_tray: None,
+			external_spool_active: false,
+			ams: Vec::new(),
+			filament_slots: Vec::new(),
+			hms: Vec::new(),
+			storage: None,
+			camera: None,
+			detection: None,
+			ai_detection_enabled: None,
+			device_links: None,
+			calibration_stage: None,
+			print_start: None,
+			firmware_version: None,
+			product_name: None,
+			last_update: Utc::now(),
+			offline_since: None,
+			acknowledged_error: None,
+		}
+	}
+
+	/// Snapshot for the database, see `restore_last_known`.
+	fn to_stored_state(&self) -> PrinterState {
+		let status = serde_json::to_value(&self.status)
+			.ok()
+			.and_then(|status| status.as_str().map(|s| s.to_string()))
+			.unwrap_or_default();
+		PrinterState {
+			printer_id: self.id.clone(),
+			status,
+			nozzle_temp: self.temperatures.nozzle,
+			bed_temp: self.temperatures.bed,
+			chamber_temp: self.temperatures.chamber,
+			print_progress: self.print.as_ref().map(|job| job.progress),
+			print_filename: self.print.as_ref().map(|job| job.file_name.clone()),
+			layer_current: self.print.as_ref().map(|job| job.layer_current),
+			layer_total: self.print.as_ref().map(|job| job.layer_total),
+			time_remaining: self
+				.print
+				.as_ref()
+				.map(|job| i32::try_from(job.time_remaining).unwrap_or(i32::MAX)),
+			filament_type: self.filament.as_ref().map(|f| f.r#type.clone()),
+			filament_color: self.filament.as_ref().map(|f| f.color.clone()),
+			error_message: self.error.as_ref().map(|e| e.message.clone()),
+			error_code: self.error.as_ref().map(|e| e.error_code),
+			last_seen: self.last_update.to_rfc3339(),
+			updated_at: Utc::now().to_rfc3339(),
+		}
+	}
+
+	/// Seeds a freshly added printer with what we knew last session, marked
+	/// `Stale` until the printer reports again.
+	fn restore_last_known(&mut self, state: &PrinterState) {
+		let Ok(status) = serde_json::from_value::<PrinterStatus>(serde_json::json!(state.status))
+		else {
+			return;
+		};
+
+		self.status = status;
+		self.connection_state = ConnectionState::Stale;
+		self.temperatures.nozzle = state.nozzle_temp;
+		self.temperatures.bed = state.bed_temp;
+		self.temperatures.chamber = state.chamber_temp;
+		self.print = state.print_filename.as_ref().map(|file_name| PrintJob {
+			progress: state.print_progress.unwrap_or(0.0),
+			progress_source: ProgressSource::Unavailable,
+			time_remaining: i64::from(state.time_remaining.unwrap_or(0)),
+			estimated_total_time: None,
+			file_name: file_name.clone(),
+			gcode_file: None,
+			print_type: None,
+			layer_current: state.layer_current.unwrap_or(0),
+			layer_total: state.layer_total.unwrap_or(0),
+			speed_level: None,
+			speed_magnitude: None,
+			fan_speed: None,
+			fan_speeds: None,
+			stage: None,
+			print_stage: None,
+			sub_stage: None,
+			lifecycle: None,
+			filament_weight: None,
+			filament_length: None,
+			elapsed_seconds: None,
+			pause_reason: None,
+		});
+		self.error = state.error_message.as_ref().map(|message| PrinterError {
+			print_error: 0,
+			error_code: state.error_code.unwrap_or(0),
+			stage: 0,
+			lifecycle: "Unknown".to_string(),
+			gcode_state: "Unknown".to_string(),
+			message: message.clone(),
+		});
+		if let Ok(last_seen) = DateTime::parse_from_rfc3339(&state.last_seen) {
+			self.last_update = last_seen.with_timezone(&Utc);
+		}
+	}
+
+	/// Whether the printer has been unreachable since before `cutoff`. A
+	/// printer with monitoring switched off doesn't count.
+	fn is_offline_since(&self, cutoff: DateTime<Utc>) -> bool {
+		let offline = self.status == PrinterStatus::Offline
+			|| self.connection_state == ConnectionState::Unreachable;
+		offline
+			&& self.status != PrinterStatus::Disabled
+			&& self.offline_since.is_some_and(|since| since < cutoff)
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrintCommand {
+	pub action: String,
+	/// Action-specific argument, e.g. the G-code line for `gcode_line`
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub param: Option<serde_json::Value>,
+}
+
+impl PrintCommand {
+	pub fn new(action: &str) -> Self {
+		Self {
+			action: action.to_string(),
+			param: None,
+		}
+	}
+
+	pub fn with_param(action: &str, param: serde_json::Value) -> Self {
+		Self {
+			action: action.to_string(),
+			param: Some(param),
+		}
+	}
+
+	/// Statuses in which a job-control action makes sense, or `None` for
+	/// actions that don't depend on the job.
+	fn allowed_statuses(&self) -> Option<&'static [PrinterStatus]> {
+		match self.action.as_str() {
+			"pause" => Some(&[PrinterStatus::Printing]),
+			// Recovering from a paused error goes through the Error status
+			"resume" => Some(&[PrinterStatus::Paused, PrinterStatus::Error]),
+			"stop" => Some(&[
+				PrinterStatus::Preparing,
+				PrinterStatus::Printing,
+				PrinterStatus::Paused,
+				PrinterStatus::Error,
+			]),
+			"skip_objects" => Some(&[PrinterStatus::Printing]),
+			"project_file" => Some(&[PrinterStatus::Idle]),
+			_ => None,
+		}
+	}
+
+	fn param_str(&self) -> Result<&str> {
+		self
+			.param
+			.as_ref()
+			.and_then(|param| param.as_str())
+			.ok_or_else(|| anyhow!("Command '{}' requires a string parameter", self.action))
+	}
+
+	fn param_u64(&self, key: &str) -> Result<u64> {
+		self
+			.param
+			.as_ref()
+			.and_then(|param| param.get(key))
+			.and_then(|value| value.as_u64())
+			.ok_or_else(|| anyhow!("Command '{}' requires a numeric '{}'", self.action, key))
+	}
+
+	fn param_string(&self, key: &str) -> Result<&str> {
+		self
+			.param
+			.as_ref()
+			.and_then(|param| param.get(key))
+			.and_then(|value| value.as_str())
+			.ok_or_else(|| anyhow!("Command '{}' requires a string '{}'", self.action, key))
+	}
+
+	fn param_bool(&self, key: &str) -> Result<bool> {
+		self
+			.param
+			.as_ref()
+			.and_then(|param| param.get(key))
+			.and_then(|value| value.as_bool())
+			.ok_or_else(|| anyhow!("Command '{}' requires a boolean '{}'", self.action, key))
+	}
+}
+
+/// Connection counters for diagnosing flaky setups.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionMetrics {
+	pub messages_received: u64,
+	pub last_message_at: Option<DateTime<Utc>>,
+	/// Failed connection attempts since the last successful `ConnAck`
+	pub reconnects: u32,
+	/// Delay before the next reconnect attempt; `None` while connected
+	pub backoff_secs: Option<u64>,
+}
+
+/// Criteria for `query_printers`; unset fields match every printer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrinterFilter {
+	#[serde(default)]
+	pub status: Option<PrinterStatus>,
+	/// Case-insensitive substring of the printer's name
+	#[serde(default)]
+	pub name: Option<String>,
+	/// One of the printer's tags, compared case-insensitively
+	#[serde(default)]
+	pub tag: Option<String>,
+}
+
+impl PrinterFilter {
+	fn matches(&self, printer: &Printer) -> bool {
+		let status_matches = self
+			.status
+			.as_ref()
+			.map_or(true, |status| *status == printer.status);
+		let name_matches = self.name.as_deref().map_or(true, |name| {
+			printer
+				.name
+				.to_lowercase()
+				.contains(&name.trim().to_lowercase())
+		});
+		let tag_matches = self.tag.as_deref().map_or(true, |tag| {
+			printer
+				.tags
+				.iter()
+				.any(|candidate| candidate.trim().eq_ignore_ascii_case(tag.trim()))
+		});
+		status_matches && name_matches && tag_matches
+	}
+}
+
+/// Fleet-wide counts for a dashboard header.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceSummary {
+	pub total: usize,
+	pub preparing: usize,
+	pub printing: usize,
+	pub paused: usize,
+	pub idle: usize,
+	pub error: usize,
+	pub offline: usize,
+	pub connecting: usize,
+	pub disabled: usize,
+	/// Printers currently carrying a `PrinterError`
+	pub with_errors: usize,
+	/// Printers with at least one fatal or serious HMS notice
+	pub with_hms_errors: usize,
+}
+
+impl ServiceSummary {
+	fn from_printers<'a>(printers: impl IntoIterator<Item = &'a Printer>) -> Self {
+		let mut summary = Self::default();
+		for printer in printers {
+			summary.total += 1;
+			match printer.status {
+				PrinterStatus::Preparing => summary.preparing += 1,
+				PrinterStatus::Printing => summary.printing += 1,
+				PrinterStatus::Paused => summary.paused += 1,
+				PrinterStatus::Idle => summary.idle += 1,
+				PrinterStatus::Error => summary.error += 1,
+				PrinterStatus::Offline => summary.offline += 1,
+				PrinterStatus::Connecting => summary.connecting += 1,
+				PrinterStatus::Disabled => summary.disabled += 1,
+			}
+			if printer.error.is_some() {
+				summary.with_errors += 1;
+			}
+			if printer
+				.hms
+				.iter()
+				.any(|hms| matches!(hms.severity, HmsSeverity::Fatal | HmsSeverity::Serious))
+			{
+				summary.with_hms_errors += 1;
+			}
+		}
+		summary
+	}
+}
+
+/// Service internals for a bug report, as opposed to `ServiceSummary`,
+/// which is about what the printers are doing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostics {
+	pub app_version: String,
+	/// Crypto backend behind rustls
+	pub tls_provider: String,
+	pub printers: usize,
+	/// Printers with a live MQTT client that commands can go through
+	pub connections: usize,
+	/// Connection tasks still running (mock printers included)
+	pub running_connection_tasks: usize,
+	/// Callers currently waiting on a command acknowledgment
+	pub pending_acknowledgments: usize,
+	pub cached_thumbnails: usize,
+	pub printer_diagnostics: Vec<PrinterDiagnostics>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrinterDiagnostics {
+	pub id: String,
+	pub name: String,
+	pub model: String,
+	pub firmware_version: Option<String>,
+	pub connection_state: ConnectionState,
+	pub metrics: ConnectionMetrics,
+}
+
+/// One printer's state for a bug report, from `export_debug_bundle`. The
+/// access code is always redacted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebugBundle {
+	pub app_version: String,
+	pub created_at: DateTime<Utc>,
+	pub config: PrinterConfig,
+	/// Accumulated MQTT state the `printer` was parsed from
+	pub raw_state: serde_json::Value,
+	pub printer: Printer,
+	pub metrics: ConnectionMetrics,
+	/// Empty when the database isn't available
+	pub command_history: Vec<CommandHistoryEntry>,
+}
+
+impl DebugBundle {
+	/// Serializes the bundle with `access_code` removed wherever it appears,
+	/// not just in the fields known to hold it.
+	fn into_redacted_json(mut self, access_code: &str) -> Result<String> {
+		self.config.access_code.clear();
+		self.printer.access_code.clear();
+		let mut bundle = serde_json::to_value(&self)?;
+		if !access_code.is_empty() {
+			redact_strings(&mut bundle, access_code);
+		}
+		Ok(serde_json::to_string_pretty(&bundle)?)
+	}
+}
+
+fn redact_strings(value: &mut serde_json::Value, secret: &str) {
+	match value {
+		serde_json::Value::String(text) if text.contains(secret) => {
+			*text = text.replace(secret, "[redacted]");
+		}
+		serde_json::Value::Array(items) => {
+			for item in items {
+				redact_strings(item, secret);
+			}
+		}
+		serde_json::Value::Object(map) => {
+			for item in map.values_mut() {
+				redact_strings(item, secret);
+			}
+		}
+		_ => {}
+	}
+}
+
+/// Payload of the `connection-error` event, telling the UI why a printer
+/// dropped offline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionErrorEvent {
+	pub printer_id: String,
+	pub error: String,
+	/// `rumqttc::ConnectionError` variant, e.g. `"Tls"` or `"Io"`
+	pub kind: String,
+	pub retry_in_secs: u64,
+}
+
+/// Payload of the `layer-change` event, sent when a print advances a layer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerChangeEvent {
+	pub printer_id: String,
+	pub layer_current: i32,
+	pub layer_total: i32,
+}
+
+impl LayerChangeEvent {
+	/// Only an increment within a job we were already tracking counts; a
+	/// repeated layer or the first report after connecting does not.
+	fn between(printer_id: &str, previous: Option<i32>, current: Option<&PrintJob>) -> Option<Self> {
+		let current = current?;
+		(current.layer_current > previous?).then(|| Self {
+			printer_id: printer_id.to_string(),
+			layer_current: current.layer_current,
+			layer_total: current.layer_total,
+		})
+	}
+}
+
+/// Payload of the `error-cleared` event, sent when a printer stops reporting
+/// an error, so the UI can dismiss its notification.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorClearedEvent {
+	pub printer_id: String,
+	pub print_error: i32,
+	pub error_code: i32,
+}
+
+impl ErrorClearedEvent {
+	/// Only the falling edge counts: an error present before the report and
+	/// gone after it. A dismissed error is already gone by the next report.
+	fn between(
+		printer_id: &str,
+		previous: Option<&PrinterError>,
+		current: Option<&PrinterError>,
+	) -> Option<Self> {
+		let previous = previous.filter(|_| current.is_none())?;
+		Some(Self {
+			printer_id: printer_id.to_string(),
+			print_error: previous.print_error,
+			error_code: previous.error_code,
+		})
+	}
+}
+
+/// Payload of the `detection-alert` event, sent when the printer's monitoring
+/// starts reporting spaghetti.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetectionAlert {
+	pub printer_id: String,
+	pub printer_name: String,
+	pub detection: DetectionStatus,
+}
+
+impl DetectionAlert {
+	/// Only the rising edge counts, not every report while it persists.
+	fn between(previous: Option<DetectionStatus>, printer: &Printer) -> Option<Self> {
+		let spaghetti = Some(DetectionStatus::SpaghettiDetected);
+		(printer.detection == spaghetti && previous != spaghetti).then(|| Self {
+			printer_id: printer.id.clone(),
+			printer_name: printer.name.clone(),
+			detection: DetectionStatus::SpaghettiDetected,
+		})
+	}
+}
+
+/// A temperature sensor watched by `TemperatureThresholds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureSensor {
+	Nozzle,
+	Bed,
+	Chamber,
+}
+
+/// Payload of the `temperature-alert` event, sent when a reading climbs past
+/// the printer's configured threshold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureAlert {
+	pub printer_id: String,
+	pub printer_name: String,
+	pub sensor: TemperatureSensor,
+	pub reading: f64,
+	pub threshold: f64,
+}
+
+impl TemperatureAlert {
+	/// Only the rising edge counts: a reading at or below its threshold before
+	/// the report and above it after. It has to drop back before alerting again.
+	fn between(previous: &PrinterTemperatures, printer: &Printer) -> Vec<Self> {
+		let thresholds = &printer.temperature_thresholds;
+		let current = &printer.temperatures;
+		[
+			(
+				TemperatureSensor::Nozzle,
+				thresholds.max_nozzle,
+				previous.nozzle,
+				current.nozzle,
+			),
+			(
+				TemperatureSensor::Bed,
+				thresholds.max_bed,
+				previous.bed,
+				current.bed,
+			),
+			(
+				TemperatureSensor::Chamber,
+				thresholds.max_chamber,
+				previous.chamber,
+				current.chamber,
+			),
+		]
+		.into_iter()
+		.filter_map(|(sensor, threshold, before, reading)| {
+			let threshold = threshold?;
+			(reading > threshold && before <= threshold).then(|| Self {
+				printer_id: printer.id.clone(),
+				printer_name: printer.name.clone(),
+				sensor,
+				reading,
+				threshold,
+			})
+		})
+		.collect()
+	}
+}
+
+/// A status change the user may want a desktop notification for. Only sent
+/// for printers with `notifications_enabled`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrinterNotification {
+	/// Event name: `print-complete`, `filament-runout` or `printer-error`
+	#[serde(skip)]
+	pub event: &'static str,
+	pub printer_id: String,
+	pub printer_name: String,
+	/// Job name for a finished print, otherwise the error message
+	pub message: Option<String>,
+}
+
+impl PrinterNotification {
+	/// Compares `printer` after a report with its status and error before
+	/// it. Only the transition itself counts, not every report after it.
+	fn between(
+		previous_status: &PrinterStatus,
+		previous_error: Option<&PrinterError>,
+		printer: &Printer,
+	) -> Option<Self> {
+		let notification = |event: &'static str, message: Option<String>| Self {
+			event,
+			printer_id: printer.id.clone(),
+			printer_name: printer.name.clone(),
+			message,
+		};
+
+		if let Some(error) = &printer.error {
+			let is_new = previous_error.map_or(true, |previous| {
+				(previous.print_error, previous.error_code) != (error.print_error, error.error_code)
+			});
+			if !is_new {
+				return None;
+			}
+			let event = match PrintJob::pause_reason(i64::from(error.stage), error.error_code) {
+				Some("filament_runout") => "filament-runout",
+				_ => "printer-error",
+			};
+			return Some(notification(event, Some(error.message.clone())));
+		}
+
+		let was_printing = matches!(
+			previous_status,
+			PrinterStatus::Printing | PrinterStatus::Paused
+		);
+		(was_printing && printer.status == PrinterStatus::Idle).then(|| {
+			notification(
+				"print-complete",
+				printer.print.as_ref().map(|job| job.file_name.clone()),
+			)
+		})
+	}
+}
+
+/// Outcome of one printer's command within a batch operation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchCommandResult {
+	pub printer_id: String,
+	pub success: bool,
+	pub error: Option<String>,
+}
+
+/// Outcome of one entry in an `import_printers` list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrinterImportResult {
+	pub name: String,
+	pub serial: String,
+	/// `false` if the entry was invalid or the printer is already added
+	pub added: bool,
+	pub error: Option<String>,
+}
+
+/// A command waiting in the handler queue. `reply`, when set, receives the
+/// outcome once the command has been published (or has failed).
+struct QueuedCommand {
+	printer_id: String,
+	command: PrintCommand,
+	/// Receives the `sequence_id` the command was published with
+	reply: Option<oneshot::Sender<Result<String>>>,
+}
+
+/// Publishes one command, resolving to its `sequence_id`.
+type CommandDispatch = Arc<
+	dyn Fn(String, PrintCommand) -> Pin<Box<dyn Future<Output = Result<String>> + Send>>
+		+ Send
+		+ Sync,
+>;
+
+/// Commands waiting to be published, one queue per printer. Each printer has
+/// a worker that sends its commands in order, so a printer whose publish hangs
+/// (e.g. a slow TLS link) doesn't hold up everyone else's. A command can be
+/// seen and cancelled until its worker picks it up.
+#[derive(Clone)]
+struct CommandQueues {
+	queues: Arc<std::sync::Mutex<HashMap<String, PrinterCommandQueue>>>,
+	dispatch: CommandDispatch,
+}
+
+#[derive(Default)]
+struct PrinterCommandQueue {
+	pending: VecDeque<QueuedCommand>,
+	/// Wakes the printer's worker when a command is queued
+	wake: Arc<Notify>,
+}
+
+impl CommandQueues {
+	fn new<F, Fut>(dispatch: F) -> Self
+	where
+		F: Fn(String, PrintCommand) -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = Result<String>> + Send + 'static,
+	{
+		Self {
+			queues: Arc::default(),
+			dispatch: Arc::new(move |printer_id, command| Box::pin(dispatch(printer_id, command))),
+		}
+	}
+
+	fn push(&self, queued: QueuedCommand) {
+		let mut queues = self.queues.lock().unwrap();
+		let queue = queues.entry(queued.printer_id.clone()).or_insert_with(|| {
+			let queue = PrinterCommandQueue::default();
+			self.spawn_worker(queued.printer_id.clone(), Arc::clone(&queue.wake));
+			queue
+		});
+		queue.pending.push_back(queued);
+		queue.wake.notify_one();
+	}
+
+	/// Actions of the commands not yet picked up for `printer_id`, oldest first.
+	fn pending(&self, printer_id: &str) -> Vec<String> {
+		self
+			.queues
+			.lock()
+			.unwrap()
+			.get(printer_id)
+			.map(|queue| {
+				queue
+					.pending
+					.iter()
+					.map(|queued| queued.command.action.clone())
+					.collect()
+			})
+			.unwrap_or_default()
+	}
+
+	/// Drops the commands not yet picked up for `printer_id`, failing anyone
+	/// waiting on them. Returns how many were dropped.
+	fn cancel(&self, printer_id: &str) -> usize {
+		let cancelled: Vec<QueuedCommand> = self
+			.queues
+			.lock()
+			.unwrap()
+			.get_mut(printer_id)
+			.map(|queue| queue.pending.drain(..).collect())
+			.unwrap_or_default();
+
+		for queued in &cancelled {
+			info!(
+				"Cancelled command '{}' for printer {}",
+				queued.command.action, printer_id
+			);
+		}
+		let count = cancelled.len();
+		for queued in cancelled {
+			if let Some(reply) = queued.reply {
+				let _ = reply.send(Err(anyhow!(
+					"Command '{}' was cancelled before it was sent",
+					queued.command.action
+				)));
+			}
+		}
+		count
+	}
+
+	fn spawn_worker(&self, printer_id: String, wake: Arc<Notify>) {
+		let queues = Arc::clone(&self.queues);
+		let dispatch = Arc::clone(&self.dispatch);
+		tauri::async_runtime::spawn(async move {
+			loop {
+				let next = queues
+					.lock()
+					.unwrap()
+					.get_mut(&printer_id)
+					.and_then(|queue| queue.pending.pop_front());
+				let Some(QueuedCommand { command, reply, .. }) = next else {
+					// A permit stored by `push` while we were busy makes this return at once
+					wake.notified().await;
+					continue;
+				};
+
+				let result = dispatch(printer_id.clone(), command).await;
+				if let Some(reply) = reply {
+					// The caller may have stopped waiting; nothing to do then
+					let _ = reply.send(result);
+				}
+			}
+		});
+	}
+}
+
+/// The printer's answer to a command, matched to it by `sequence_id`.
+#[derive(Debug, Clone)]
+struct CommandResponse {
+	printer_id: String,
+	sequence_id: String,
+	/// `"success"` or `"failed"`
+	result: Option<String>,
+	reason: Option<String>,
+}
+
+impl CommandResponse {
+	/// Picks the command echo out of a report. Periodic `push_status`
+	/// reports carry a `sequence_id` too, but aren't answers to anything.
+	fn from_report(printer_id: &str, data: &serde_json::Value) -> Option<Self> {
+		let print = data.get("print")?;
+		let command = print.get("command")?.as_str()?;
+		if command == "push_status" {
+			return None;
+		}
+
+		let text = |key: &str| print.get(key).and_then(|v| v.as_str()).map(str::to_string);
+		Some(Self {
+			printer_id: printer_id.to_string(),
+			sequence_id: text("sequence_id")?,
+			result: text("result"),
+			reason: text("reason"),
+		})
+	}
+
+	fn is_failure(&self) -> bool {
+		self
+			.result
+			.as_deref()
+			.is_some_and(|result| result.eq_ignore_ascii_case("failed"))
+	}
+}
+
+/// A plate preview and the job (`gcode_file` and `subtask_name`) it shows.
+struct CachedThumbnail {
+	job: String,
+	image: Vec<u8>,
+}
+
+/// An operator's own MQTT broker that parsed printer state is mirrored to.
+///
+/// Each printer is published as JSON (without its access code) to
+/// `pulseprint/{printer_id}/state`, retained, whenever it changes. A removed
+/// printer's topic is cleared with an empty retained message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorConfig {
+	pub host: String,
+	#[serde(default = "MirrorConfig::default_port")]
+	pub port: u16,
+	#[serde(default)]
+	pub username: Option<String>,
+	#[serde(default)]
+	pub password: Option<String>,
+	/// `None` connects over plain TCP
+	#[serde(default)]
+	pub tls: Option<TlsMode>,
+}
+
+impl MirrorConfig {
+	fn default_port() -> u16 {
+		1883
+	}
+
+	pub fn state_topic(printer_id: &str) -> String {
+		format!("pulseprint/{printer_id}/state")
+	}
+
+	fn mqtt_options(&self) -> Result<MqttOptions> {
+		if self.host.trim().is_empty() {
+			return Err(anyhow!("Mirror broker host must not be empty"));
+		}
+		if self.port == 0 {
+			return Err(anyhow!("Mirror broker port must be between 1 and 65535"));
+		}
+
+		let client_id = format!("pulseprint_mirror_{}", Uuid::new_v4());
+		let mut mqtt_options = MqttOptions::new(client_id, self.host.trim(), self.port);
+		mqtt_options.set_keep_alive(Duration::from_secs(30));
+		if let Some(username) = &self.username {
+			mqtt_options.set_credentials(username, self.password.as_deref().unwrap_or_default());
+		}
+		if let Some(tls_mode) = &self.tls {
+			mqtt_options.set_transport(Transport::Tls(TlsConfiguration::Rustls(Arc::new(
+				MqttService::build_tls_config(tls_mode)?,
+			))));
+		}
+		Ok(mqtt_options)
+	}
+
+	/// Printers to publish because they changed since `published`, and ids
+	/// whose topic to clear because the printer is gone. Unchanged printers
+	/// are the same `Arc` as last time, so comparing pointers is enough.
+	fn changes(
+		snapshot: &PrinterMap,
+		published: &HashMap<String, Arc<Printer>>,
+	) -> (Vec<Arc<Printer>>, Vec<String>) {
+		let changed = snapshot
+			.iter()
+			.filter(|(id, printer)| {
+				published
+					.get(*id)
+					.map_or(true, |last| !Arc::ptr_eq(last, printer))
+			})
+			.map(|(_, printer)| Arc::clone(printer))
+			.collect();
+		let removed = published
+			.keys()
+			.filter(|id| !snapshot.contains_key(*id))
+			.cloned()
+			.collect();
+		(changed, removed)
+	}
+}
+
+/// Background MQTT task for one printer, with the token that asks it to
+/// disconnect cleanly.
+struct ConnectionTask {
+	handle: JoinHandle<()>,
+	cancel: CancellationToken,
+}
+
+/// The printers, keyed by id. Printers are held behind `Arc` so an update
+/// can hand out a snapshot for emitting without cloning the whole struct
+/// under the lock.
+type PrinterMap = HashMap<String, Arc<Printer>>;
+
+/// Printer state shared by the connection tasks and the commands.
+///
+/// Message handlers take short write locks many times a second, which can
+/// starve a reader that wants every printer at once. Each write therefore
+/// publishes a fresh snapshot that bulk readers grab without locking.
+struct PrinterStates {
+	states: RwLock<PrinterMap>,
+	snapshot: watch::Sender<Arc<PrinterMap>>,
+	versions: std::sync::Mutex<PrinterVersions>,
+}
+
+/// Which write last changed each printer, for `get_printers_since`.
+#[derive(Default)]
+struct PrinterVersions {
+	/// Bumped by every write that changes at least one printer
+	current: u64,
+	modified: HashMap<String, u64>,
+}
+
+impl PrinterVersions {
+	/// Unchanged printers are the same `Arc` in both maps, so comparing
+	/// pointers finds the ones this write touched.
+	fn record(&mut self, previous: &PrinterMap, current: &PrinterMap) {
+		let changed: Vec<&String> = current
+			.iter()
+			.filter(|(id, printer)| {
+				previous
+					.get(*id)
+					.map_or(true, |old| !Arc::ptr_eq(old, printer))
+			})
+			.map(|(id, _)| id)
+			.collect();
+		self.modified.retain(|id, _| current.contains_key(id));
+		if changed.is_empty() {
+			return;
+		}
+
+		self.current += 1;
+		for id in changed {
+			self.modified.insert(id.clone(), self.current);
+		}
+	}
+}
+
+impl PrinterStates {
+	fn new() -> Self {
+		Self {
+			states: RwLock::new(HashMap::new()),
+			snapshot: watch::channel(Arc::new(HashMap::new())).0,
+			versions: std::sync::Mutex::default(),
+		}
+	}
+
+	async fn read(&self) -> RwLockReadGuard<'_, PrinterMap> {
+		self.states.read().await
+	}
+
+	/// The snapshot is republished when the returned guard is dropped.
+	async fn write(&self) -> PrinterStatesGuard<'_> {
+		PrinterStatesGuard {
+			states: self.states.write().await,
+			snapshot: &self.snapshot,
+			versions: &self.versions,
+		}
+	}
+
+	/// Every printer as of the last write, without waiting on the lock.
+	fn snapshot(&self) -> Arc<PrinterMap> {
+		Arc::clone(&self.snapshot.borrow())
+	}
+
+	/// The printers changed after `version`, and the version to pass next
+	/// time. A snapshot is published before its version is recorded, so a
+	/// printer may come back twice but is never missed.
+	fn since(&self, version: u64) -> (u64, Vec<Arc<Printer>>) {
+		let versions = self.versions.lock().unwrap();
+		let printers = self
+			.snapshot()
+			.iter()
+			.filter(|(id, _)| {
+				versions
+					.modified
+					.get(*id)
+					.map_or(true, |modified| *modified > version)
+			})
+			.map(|(_, printer)| Arc::clone(printer))
+			.collect();
+		(versions.current, printers)
+	}
+}
+
+struct PrinterStatesGuard<'a> {
+	states: RwLockWriteGuard<'a, PrinterMap>,
+	snapshot: &'a watch::Sender<Arc<PrinterMap>>,
+	versions: &'a std::sync::Mutex<PrinterVersions>,
+}
+
+impl std::ops::Deref for PrinterStatesGuard<'_> {
+	type Target = PrinterMap;
+
+	fn deref(&self) -> &PrinterMap {
+		&self.states
+	}
+}
+
+impl std::ops::DerefMut for PrinterStatesGuard<'_> {
+	fn deref_mut(&mut self) -> &mut PrinterMap {
+		&mut self.states
+	}
+}
+
+impl Drop for PrinterStatesGuard<'_> {
+	fn drop(&mut self) {
+		// Cloning the map only bumps each printer's refcount
+		let current = Arc::new(self.states.clone());
+		let previous = self.snapshot.send_replace(Arc::clone(&current));
+		self.versions.lock().unwrap().record(&previous, &current);
+	}
+}
+
+/// Where the service sends its events and finds the app's other managed
+/// state. The app passes its `AppHandle`; tests record the events instead,
+/// so the real connection task can run without a Tauri app.
+pub trait EventSink: Send + Sync + 'static {
+	fn emit_event(&self, event: &str, payload: serde_json::Value) -> Result<()>;
+	fn state_store(&self) -> Option<PrinterStateStore>;
+	fn command_history(&self) -> Option<CommandHistory>;
+}
+
+impl EventSink for AppHandle {
+	fn emit_event(&self, event: &str, payload: serde_json::Value) -> Result<()> {
+		Ok(self.emit(event, payload)?)
+	}
+
+	fn state_store(&self) -> Option<PrinterStateStore> {
+		self
+			.try_state::<PrinterStateStore>()
+			.map(|store| (*store).clone())
+	}
+
+	fn command_history(&self) -> Option<CommandHistory> {
+		self
+			.try_state::<CommandHistory>()
+			.map(|history| (*history).clone())
+	}
+}
+
+/// The service's `EventSink`, shared by its background tasks.
+#[derive(Clone)]
+struct Events(Arc<dyn EventSink>);
+
+impl Events {
+	fn emit<S: Serialize>(&self, event: &str, payload: S) -> Result<()> {
+		self.0.emit_event(event, serde_json::to_value(payload)?)
+	}
+}
+
+impl std::ops::Deref for Events {
+	type Target = dyn EventSink;
+
+	fn deref(&self) -> &Self::Target {
+		self.0.as_ref()
+	}
+}
+
+// Simplified service that doesn't store MQTT connections directly
+#[derive(Clone)]
+pub struct MqttService {
+	printer_states: Arc<PrinterStates>,
+	// Add persistent MQTT state accumulation
+	printer_mqtt_states: Arc<RwLock<HashMap<String, serde_json::Value>>>,
+	// Add connection pool for sending commands
+	printer_connections: Arc<RwLock<HashMap<String, AsyncClient>>>,
+	// Configs as last added/updated, used to detect connection-relevant edits
+	printer_configs: Arc<RwLock<HashMap<String, PrinterConfig>>>,
+	// Background MQTT tasks, kept so a connection can be torn down and restarted
+	connection_tasks: Arc<RwLock<HashMap<String, ConnectionTask>>>,
+	connection_metrics: Arc<RwLock<HashMap<String, ConnectionMetrics>>>,
+	// Command echoes from every printer, for callers waiting on an acknowledgment
+	command_responses: broadcast::Sender<CommandResponse>,
+	// Last fetched plate preview per printer, keyed by the job it belongs to
+	thumbnail_cache: Arc<RwLock<HashMap<String, CachedThumbnail>>>,
+	// State saved by the previous session, used once when each printer is added
+	last_known_states: Arc<RwLock<HashMap<String, PrinterState>>>,
+	// When each printer was last sent `pushall`, for rate limiting `force_refresh`
+	last_refresh: Arc<RwLock<HashMap<String, Instant>>>,
+	// Republishes printer state to the operator's own broker; off unless configured
+	mirror_task: Arc<RwLock<Option<ConnectionTask>>>,
+	// `set_temperature_and_wait` calls per printer, resolved as reports come in
+	temperature_waiters: Arc<RwLock<HashMap<String, Vec<TemperatureWaiter>>>>,
+	// Signalled whenever a client joins `printer_connections`, for commands waiting on one
+	connection_ready: Arc<Notify>,
+	// Printers whose raw messages are being recorded; empty unless debugging
+	captures: Captures,
+	events: Events,
+	command_queues: CommandQueues,
+}
+
+impl MqttService {
+	pub fn new(app_handle: AppHandle) -> Self {
+		Self::with_events(Arc::new(app_handle))
+	}
+
+	/// A service sending its events to `sink` rather than to a Tauri app.
+	pub(crate) fn with_events(sink: Arc<dyn EventSink>) -> Self {
+		let events = Events(sink);
+		let printer_states = Arc::new(PrinterStates::new());
+		let printer_connections = Arc::new(RwLock::new(HashMap::new()));
+		let connection_ready = Arc::new(Notify::new());
+
+		// Commands are published in the background, one worker per printer
+		let command_queues = CommandQueues::new({
+			let printer_states = Arc::clone(&printer_states);
+			let printer_connections = Arc::clone(&printer_connections);
+			let connection_ready = Arc::clone(&connection_ready);
+			let events = events.clone();
+			move |printer_id, command| {
+				let printer_states = Arc::clone(&printer_states);
+				let printer_connections = Arc::clone(&printer_connections);
+				let connection_ready = Arc::clone(&connection_ready);
+				let events = events.clone();
+				async move {
+					Self::dispatch_command(
+						&printer_states,
+						&printer_connections,
+						&connection_ready,
+						&events,
+						&printer_id,
+						&command,
+					)
+					.await
+				}
+			}
+		});
+
+		Self {
+			printer_states,
+			printer_mqtt_states: Arc::new(RwLock::new(HashMap::new())),
+			printer_connections,
+			printer_configs: Arc::new(RwLock::new(HashMap::new())),
+			connection_tasks: Arc::new(RwLock::new(HashMap::new())),
+			connection_metrics: Arc::new(RwLock::new(HashMap::new())),
+			command_responses: broadcast::channel(64).0,
+			thumbnail_cache: Arc::new(RwLock::new(HashMap::new())),
+			last_known_states: Arc::new(RwLock::new(HashMap::new())),
+			last_refresh: Arc::new(RwLock::new(HashMap::new())),
+			mirror_task: Arc::new(RwLock::new(None)),
+			temperature_waiters: Arc::new(RwLock::new(HashMap::new())),
+			connection_ready,
+			captures: Captures::default(),
+			events,
+			command_queues,
+		}
+	}
+
+	/// Publishes one queued command and records the outcome.
+	async fn dispatch_command(
+		printer_states: &Arc<PrinterStates>,
+		printer_connections: &Arc<RwLock<HashMap<String, AsyncClient>>>,
+		connection_ready: &Notify,
+		events: &Events,
+		printer_id: &str,
+		command: &PrintCommand,
+	) -> Result<String> {
+		info!(
+			"Processing command '{}' for printer {}",
+			command.action, printer_id
+		);
+
+		let result = async {
+			let (printer_serial, mqtt_client) = Self::connected_client(
+				printer_states,
+				printer_connections,
+				connection_ready,
+				printer_id,
+				COMMAND_CONNECTION_WAIT,
+			)
+			.await?;
+
+			// Send actual MQTT command
+			Self::send_mqtt_command(&mqtt_client, &printer_serial, command).await
+		}
+		.await;
+
+		match &result {
+			Ok(_) => {
+				info!(
+					"Command '{}' sent successfully to printer {}",
+					command.action, printer_id
+				);
+			}
+			Err(e) => {
+				error!(
+					"Failed to send command '{}' to printer {}: {}",
+					command.action, printer_id, e
+				);
+			}
+		}
+
+		// Record the outcome here rather than on the message path, so a slow
+		// database never holds up incoming printer reports
+		if let Some(history) = events.command_history() {
+			let error = result.as_ref().err().map(|e| e.to_string());
+			if let Err(e) = history
+				.record(printer_id, &command.action, error.as_deref())
+				.await
+			{
+				error!("Failed to record command history for printer {printer_id}: {e}");
+			}
+		}
+
+		result
+	}
+
+	/// The printer's serial and MQTT client. A printer that is still connecting
+	/// gets up to `wait` to finish, so a command sent right after adding it
+	/// goes out once the connection is up instead of being lost.
+	async fn connected_client(
+		printer_states: &PrinterStates,
+		printer_connections: &RwLock<HashMap<String, AsyncClient>>,
+		connection_ready: &Notify,
+		printer_id: &str,
+		wait: Duration,
+	) -> Result<(String, AsyncClient)> {
+		let deadline = tokio::time::Instant::now() + wait;
+		loop {
+			// Registered before checking, so a connection made in between still wakes us
+			let ready = connection_ready.notified();
+			tokio::pin!(ready);
+			ready.as_mut().enable();
+
+			{
+				let states = printer_states.read().await;
+				let printer = states
+					.get(printer_id)
+					.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+				if let Some(client) = printer_connections.read().await.get(printer_id) {
+					return Ok((printer.serial.clone(), client.clone()));
+				}
+				// Nothing is going to connect these without the user stepping in
+				if matches!(
+					printer.connection_state,
+					ConnectionState::Disabled | ConnectionState::AuthFailed | ConnectionState::Unreachable
+				) {
+					return Err(anyhow!(
+						"No MQTT connection for printer {printer_id} ({:?})",
+						printer.connection_state
+					));
+				}
+			}
+
+			if tokio::time::timeout_at(deadline, ready).await.is_err() {
+				return Err(anyhow!(
+					"Printer {printer_id} didn't connect within {}s; command not sent",
+					wait.as_secs()
+				));
+			}
+		}
+	}
+
+	/// Publishes `command`, returning the `sequence_id` the printer will echo
+	/// back in its answer.
+	async fn send_mqtt_command(
+		client: &AsyncClient,
+		printer_serial: &str,
+		command: &PrintCommand,
+	) -> Result<String> {
+		let request_topic = format!("device/{printer_serial}/request");
+		let sequence_id = chrono::Utc::now().timestamp_millis().to_string();
+		let mqtt_command = Self::build_command_payload(command, &sequence_id)?;
+
+		let message = mqtt_command.to_string();
+		client
+			.publish(request_topic, QoS::AtMostOnce, false, message.as_bytes())
+			.await
+			.map_err(|e| anyhow!("MQTT publish failed: {}", e))?;
+
+		Ok(sequence_id)
+	}
+
+	/// Builds the JSON request published to `device/{serial}/request`.
+	fn build_command_payload(command: &PrintCommand, sequence_id: &str) -> Result<serde_json::Value> {
+		let mqtt_command = match command.action.as_str() {
+			"pause" => serde_json::json!({
+				"print": {
+					"command": "pause",
+					"sequence_id": sequence_id
+				}
+			}),
+			"resume" => serde_json::json!({
+				"print": {
+					"command": "resume",
+					"sequence_id": sequence_id
+				}
+			}),
+			"stop" => serde_json::json!({
+				"print": {
+					"command": "stop",
+					"sequence_id": sequence_id
+				}
+			}),
+			"get_status" => serde_json::json!({
+				"print": {
+					"command": "get_status",
+					"sequence_id": sequence_id
+				}
+			}),
+			"pushall" => serde_json::json!({
+				"pushing": {
+					"command": "pushall",
+					"sequence_id": sequence_id
+				}
+			}),
+			"get_version" => serde_json::json!({
+				"info": {
+					"command": "get_version",
+					"sequence_id": sequence_id
+				}
+			}),
+			"gcode_line" => serde_json::json!({
+				"print": {
+					"command": "gcode_line",
+					"param": format!("{}\n", command.param_str()?.trim_end()),
+					"sequence_id": sequence_id
+				}
+			}),
+			"print_speed" => serde_json::json!({
+				"print": {
+					"command": "print_speed",
+					"param": command.param_str()?,
+					"sequence_id": sequence_id
+				}
+			}),
+			"skip_objects" => serde_json::json!({
+				"print": {
+					"command": "skip_objects",
+					"obj_list": command
+						.param
+						.as_ref()
+						.and_then(|param| param.as_array())
+						.filter(|ids| !ids.is_empty())
+						.ok_or_else(|| anyhow!("Command 'skip_objects' requires a list of object ids"))?,
+					"sequence_id": sequence_id
+				}
+			}),
+			"calibration" => serde_json::json!({
+				"print": {
+					"command": "calibration",
+					"option": command.param_u64("option")?,
+					"sequence_id": sequence_id
+				}
+			}),
+			"ipcam_timelapse" => serde_json::json!({
+				"camera": {
+					"command": "ipcam_timelapse",
+					"control": command.param_str()?,
+					"sequence_id": sequence_id
+				}
+			}),
+			// Spaghetti detection, pausing the print when it fires
+			"xcam_control_set" => {
+				let enabled = command.param_bool("control")?;
+				serde_json::json!({
+					"xcam": {
+						"command": "xcam_control_set",
+						"module_name": "spaghetti_detector",
+						"control": enabled,
+						"print_halt": enabled,
+						"sequence_id": sequence_id
+					}
+				})
+			}
+			// Starts a project stored on the printer; the IDs only matter for cloud jobs
+			"project_file" => {
+				let file = command.param_string("file")?.trim_start_matches('/');
+				let options: StartPrintOptions = command
+					.param
+					.as_ref()
+					.and_then(|param| param.get("options"))
+					.map(|options| serde_json::from_value(options.clone()))
+					.transpose()?
+					.unwrap_or_default();
+				serde_json::json!({
+					"print": {
+						"command": "project_file",
+						"param": format!("Metadata/plate_{}.gcode", options.plate.unwrap_or(1)),
+						"url": format!("file:///sdcard/{file}"),
+						"subtask_name": project_job_name(file),
+						"project_id": "0",
+						"profile_id": "0",
+						"task_id": "0",
+						"subtask_id": "0",
+						"md5": "",
+						"bed_type": "auto",
+						"bed_levelling": options.bed_leveling,
+						"flow_cali": options.flow_cali,
+						"vibration_cali": options.vibration_cali,
+						"layer_inspect": options.layer_inspect,
+						"timelapse": options.timelapse,
+						"use_ams": options.use_ams,
+						"sequence_id": sequence_id
+					}
+				})
+			}
+			"ams_change_filament" => serde_json::json!({
+				"print": {
+					"command": "ams_change_filament",
+					"target": command.param_u64("target")?,
+					"curr_temp": command.param_u64("curr_temp")?,
+					"tar_temp": command.param_u64("tar_temp")?,
+					"sequence_id": sequence_id
+				}
+			}),
+			_ => {
+				return Err(anyhow!("Unsupported command: {}", command.action));
+			}
+		};
+
+		Ok(mqtt_command)
+	}
+
+	/// Switches the active print to one of Bambu's speed profiles:
+	/// 1 = silent, 2 = standard, 3 = sport, 4 = ludicrous.
+	///
+	/// With `optimistic`, the new level is applied to the printer's state and
+	/// emitted as soon as the command is published, instead of waiting for the
+	/// next report. A report that disagrees overwrites it as usual.
+	pub async fn set_print_speed(&self, printer_id: &str, level: u8, optimistic: bool) -> Result<()> {
+		if !(1..=4).contains(&level) {
+			return Err(anyhow!(
+				"Invalid speed level {level}, expected 1 (silent) to 4 (ludicrous)"
+			));
+		}
+
+		let command = PrintCommand::with_param("print_speed", serde_json::json!(level.to_string()));
+		if !optimistic {
+			return self.send_command(printer_id, command).await;
+		}
+
+		self.send_command_confirmed(printer_id, command).await?;
+		Self::update_printer_status(&self.printer_states, &self.events, printer_id, |printer| {
+			if let Some(job) = printer.print.as_mut() {
+				job.speed_level = Some(i32::from(level));
+			}
+		})
+		.await;
+
+		Ok(())
+	}
+
+	/// Skips the given objects for the rest of the current print, so a plate
+	/// can be salvaged when one object fails.
+	pub async fn skip_objects(&self, printer_id: &str, object_ids: Vec<i64>) -> Result<()> {
+		if object_ids.is_empty() {
+			return Err(anyhow!("No objects given to skip"));
+		}
+
+		let command = PrintCommand::with_param("skip_objects", serde_json::json!(object_ids));
+		self.check_preconditions(printer_id, &command).await?;
+		self.send_command(printer_id, command).await
+	}
+
+	/// Loads the filament in AMS `slot` (`unit * 4 + tray`), heating the nozzle
+	/// to `target_temp` first.
+	pub async fn change_filament(&self, printer_id: &str, slot: u8, target_temp: i32) -> Result<()> {
+		if target_temp <= 0 {
+			return Err(anyhow!("Invalid target temperature {target_temp}"));
+		}
+
+		let command = {
+			let states = self.printer_states.read().await;
+			let printer = states
+				.get(printer_id)
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+			let command = PrintCommand::with_param(
+				"ams_change_filament",
+				serde_json::json!({
+					"target": slot,
+					"curr_temp": printer.temperatures.nozzle.max(0.0).round() as i32,
+					"tar_temp": target_temp,
+				}),
+			);
+			Self::check_command(printer, &command)?;
+			command
+		};
+
+		self.send_command(printer_id, command).await
+	}
+
+	/// Unloads the current filament back into the AMS.
+	pub async fn unload_filament(&self, printer_id: &str) -> Result<()> {
+		let command = {
+			let states = self.printer_states.read().await;
+			let printer = states
+				.get(printer_id)
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+			let current_temp = printer.temperatures.nozzle.max(0.0).round() as i32;
+			let command = PrintCommand::with_param(
+				"ams_change_filament",
+				serde_json::json!({
+					"target": UNLOAD_FILAMENT_TARGET,
+					"curr_temp": current_temp,
+					"tar_temp": current_temp,
+				}),
+			);
+			Self::check_command(printer, &command)?;
+			command
+		};
+
+		self.send_command(printer_id, command).await
+	}
+
+	/// Checks that `printer` can take `command` right now: job control only in
+	/// the statuses that allow it, and commands for optional hardware only
+	/// where the printer has it. The methods sending these commands and
+	/// `validate_command` both go through here, so a dry run agrees with them.
+	fn check_command(printer: &Printer, command: &PrintCommand) -> Result<()> {
+		if let Some(allowed) = command.allowed_statuses() {
+			if !allowed.contains(&printer.status) {
+				return Err(anyhow!(
+					"Cannot {} {} while it is {:?}",
+					command.action,
+					printer.name,
+					printer.status
+				));
+			}
+		}
+
+		match command.action.as_str() {
+			"xcam_control_set" if !printer.capabilities.has_spaghetti_detection => Err(anyhow!(
+				"{} ({}) has no spaghetti detection",
+				printer.name,
+				printer.model
+			)),
+			"ams_change_filament" => {
+				let target = command.param_u64("target")?;
+				let slot = if target == u64::from(UNLOAD_FILAMENT_TARGET) {
+					None
+				} else {
+					Some(
+						u8::try_from(target)
+							.map_err(|_| anyhow!("AMS slot {target} not found on {}", printer.name))?,
+					)
+				};
+				Self::check_filament_change(printer, slot)?;
+
+				let target_temp = command.param_u64("tar_temp")?;
+				let max_temp = printer.capabilities.max_nozzle_temp;
+				if i64::try_from(target_temp).map_or(true, |temp| temp > i64::from(max_temp)) {
+					return Err(anyhow!(
+						"Target temperature {target_temp}°C is above the {max_temp}°C limit of {}",
+						printer.name
+					));
+				}
+				Ok(())
+			}
+			_ => Ok(()),
+		}
+	}
+
+	/// `check_command` against the printer's current state.
+	async fn check_preconditions(&self, printer_id: &str, command: &PrintCommand) -> Result<()> {
+		let states = self.printer_states.read().await;
+		let printer = states
+			.get(printer_id)
+			.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+		Self::check_command(printer, command)
+	}
+
+	/// Filament can't be swapped mid-print, and a load must target a tray the
+	/// printer has actually reported.
+	fn check_filament_change(printer: &Printer, slot: Option<u8>) -> Result<()> {
+		if matches!(
+			printer.status,
+			PrinterStatus::Preparing | PrinterStatus::Printing
+		) {
+			return Err(anyhow!(
+				"Cannot change filament on {} while it is printing",
+				printer.name
+			));
+		}
+
+		if let Some(slot) = slot {
+			let known = printer.ams.iter().any(|unit| {
+				unit.trays.iter().any(|tray| {
+					u16::from(unit.id) * FilamentSlot::TRAYS_PER_UNIT + u16::from(tray.id) == u16::from(slot)
+				})
+			});
+			if !known {
+				return Err(anyhow!("AMS slot {} not found on {}", slot, printer.name));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Sets a fan's speed via `M106`. `fan` is one of `part`, `aux` or
+	/// `chamber`; `percent` is clamped to 100.
+	pub async fn set_fan_speed(&self, printer_id: &str, fan: &str, percent: u8) -> Result<()> {
+		let gcode = FanSpeeds::gcode(fan, percent)?;
+		if fan == "chamber" {
+			let states = self.printer_states.read().await;
+			let printer = states
+				.get(printer_id)
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+			if !printer.capabilities.has_chamber_fan {
+				return Err(anyhow!(
+					"{} ({}) has no chamber fan",
+					printer.name,
+					printer.model
+				));
+			}
+		}
+
+		self
+			.send_command(
+				printer_id,
+				PrintCommand::with_param("gcode_line", serde_json::json!(gcode)),
+			)
+			.await
+	}
+
+	/// Nudges the Z offset mid-print (baby-stepping) with `M290`, to tune the
+	/// first layer. `delta_mm` is clamped to ±0.5 mm; negative moves the
+	/// nozzle closer to the bed.
+	pub async fn adjust_z_offset(&self, printer_id: &str, delta_mm: f64) -> Result<()> {
+		let gcode = Self::z_offset_gcode(delta_mm)?;
+
+		{
+			let states = self.printer_states.read().await;
+			let printer = states
+				.get(printer_id)
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+			if printer.status != PrinterStatus::Printing {
+				return Err(anyhow!(
+					"Can only adjust the Z offset while printing, {} is {:?}",
+					printer.name,
+					printer.status
+				));
+			}
+		}
+
+		self
+			.send_command(
+				printer_id,
+				PrintCommand::with_param("gcode_line", serde_json::json!(gcode)),
+			)
+			.await
+	}
+
+	fn z_offset_gcode(delta_mm: f64) -> Result<String> {
+		if !delta_mm.is_finite() || delta_mm == 0.0 {
+			return Err(anyhow!("Invalid Z offset change {delta_mm}"));
+		}
+		let delta = delta_mm.clamp(-MAX_Z_OFFSET_STEP_MM, MAX_Z_OFFSET_STEP_MM);
+		Ok(format!("M290 Z{delta:.3}"))
+	}
+
+	/// Sets the nozzle or bed heater to `value` °C with `M104`/`M140`.
+	/// `target` is `nozzle` or `bed`; 0 turns the heater off.
+	pub async fn set_temperature(&self, printer_id: &str, target: &str, value: i32) -> Result<()> {
+		let command = self
+			.temperature_command(printer_id, Heater::parse(target)?, value)
+			.await?;
+		self.send_command(printer_id, command).await
+	}
+
+	/// The `gcode_line` setting `heater` to `value`, checked against the
+	/// printer's limits.
+	async fn temperature_command(
+		&self,
+		printer_id: &str,
+		heater: Heater,
+		value: i32,
+	) -> Result<PrintCommand> {
+		let max = {
+			let states = self.printer_states.read().await;
+			let printer = states
+				.get(printer_id)
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+			match heater {
+				Heater::Nozzle => printer.capabilities.max_nozzle_temp,
+				Heater::Bed => MAX_BED_TEMP,
+			}
+		};
+		let gcode = heater.gcode(value, max)?;
+		Ok(PrintCommand::with_param(
+			"gcode_line",
+			serde_json::json!(gcode),
+		))
+	}
+
+	/// Like `set_temperature`, but only returns once a report shows the
+	/// heater within `TEMPERATURE_REACHED_TOLERANCE` of `value`, so scripted
+	/// preheats know when the printer is actually ready. Fails if that takes
+	/// longer than `timeout_secs`. Turning a heater off isn't waited for, as
+	/// it cools towards room temperature rather than to 0 °C.
+	pub async fn set_temperature_and_wait(
+		&self,
+		printer_id: &str,
+		target: &str,
+		value: i32,
+		timeout_secs: u64,
+	) -> Result<()> {
+		if timeout_secs == 0 {
+			return Err(anyhow!("Timeout must be at least 1s"));
+		}
+		if value <= 0 {
+			return Err(anyhow!(
+				"Can only wait for a heater being set to a temperature, not {value}°C"
+			));
+		}
+		// Checked before touching the waiters, so a bad call doesn't leave one behind
+		let heater = Heater::parse(target)?;
+		let command = self.temperature_command(printer_id, heater, value).await?;
+
+		// Registered before the setpoint goes out, so even the first report after it counts
+		let (reached, wait) = oneshot::channel();
+		self
+			.temperature_waiters
+			.write()
+			.await
+			.entry(printer_id.to_string())
+			.or_default()
+			.push(TemperatureWaiter {
+				heater,
+				target: value,
+				reached,
+			});
+
+		// Confirmed, so a printer that can't be reached fails now rather than at the timeout
+		self.send_command_confirmed(printer_id, command).await?;
+
+		match tokio::time::timeout(Duration::from_secs(timeout_secs), wait).await {
+			Ok(Ok(())) => Ok(()),
+			Ok(Err(_)) => Err(anyhow!(
+				"Printer {printer_id} was removed while waiting for the {target} to reach {value}°C"
+			)),
+			Err(_) => Err(anyhow!(
+				"The {target} of printer {printer_id} didn't reach {value}°C within {timeout_secs}s"
+			)),
+		}
+	}
+
+	/// Wakes the `set_temperature_and_wait` calls whose heater has reached
+	/// target, and forgets the ones that gave up.
+	async fn resolve_temperature_waiters(
+		temperature_waiters: &RwLock<HashMap<String, Vec<TemperatureWaiter>>>,
+		printer_id: &str,
+		temperatures: &PrinterTemperatures,
+	) {
+		let mut all_waiters = temperature_waiters.write().await;
+		let Some(waiters) = all_waiters.get_mut(printer_id) else {
+			return;
+		};
+
+		let (reached, waiting): (Vec<_>, Vec<_>) = std::mem::take(waiters)
+			.into_iter()
+			.filter(|waiter| !waiter.reached.is_closed())
+			.partition(|waiter| waiter.is_reached(temperatures));
+		for waiter in reached {
+			let _ = waiter.reached.send(());
+		}
+		if waiting.is_empty() {
+			all_waiters.remove(printer_id);
+		} else {
+			*waiters = waiting;
+		}
+	}
+
+	/// Sets the chamber exhaust fan, for enclosure temperature control.
+	pub async fn set_chamber_fan(&self, printer_id: &str, percent: u8) -> Result<()> {
+		self.set_fan_speed(printer_id, "chamber", percent).await
+	}
+
+	/// Turns timelapse recording on or off for the next print. The printer
+	/// confirms through `ipcam.timelapse`, which shows up in `camera`.
+	pub async fn set_timelapse(&self, printer_id: &str, enabled: bool) -> Result<()> {
+		{
+			let states = self.printer_states.read().await;
+			let printer = states
+				.get(printer_id)
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+			if !printer.capabilities.has_camera {
+				return Err(anyhow!("{} has no camera", printer.name));
+			}
+		}
+
+		let control = if enabled { "enable" } else { "disable" };
+		self
+			.send_command(
+				printer_id,
+				PrintCommand::with_param("ipcam_timelapse", serde_json::json!(control)),
+			)
+			.await
+	}
+
+	/// Turns the camera's spaghetti detection on or off, together with pausing
+	/// the print when it fires. The printer confirms through
+	/// `xcam.spaghetti_detector`, which shows up in `ai_detection_enabled`.
+	///
+	/// This goes out as `xcam_control_set` rather than `print_option`: the
+	/// latter only carries the printer's own switches (`auto_recovery`,
+	/// `sound_enable` and the like), while the camera's detectors are toggled
+	/// per `module_name` through `xcam`, the same way Bambu Studio does it.
+	pub async fn set_ai_detection(&self, printer_id: &str, enabled: bool) -> Result<()> {
+		let command = PrintCommand::with_param(
+			"xcam_control_set",
+			serde_json::json!({ "control": enabled }),
+		);
+		self.check_preconditions(printer_id, &command).await?;
+		self.send_command(printer_id, command).await
+	}
+
+	/// Asks the printer to push its complete state with `pushall`, for when the
+	/// accumulated state looks stale or incomplete. This is much heavier than
+	/// the `get_status` sent on connect, and sending it often makes P1P
+	/// printers lag, so it is limited to once per `FORCE_REFRESH_INTERVAL`.
+	pub async fn force_refresh(&self, printer_id: &str) -> Result<()> {
+		if !self.printer_states.read().await.contains_key(printer_id) {
+			return Err(anyhow!("Printer {printer_id} not found"));
+		}
+
+		{
+			let mut last_refresh = self.last_refresh.write().await;
+			let now = Instant::now();
+			if let Some(wait) = last_refresh
+				.get(printer_id)
+				.and_then(|at| FORCE_REFRESH_INTERVAL.checked_sub(now.duration_since(*at)))
+				.filter(|wait| !wait.is_zero())
+			{
+				return Err(anyhow!(
+					"Printer {printer_id} was just refreshed; try again in {}s",
+					wait.as_secs().max(1)
+				));
+			}
+			last_refresh.insert(printer_id.to_string(), now);
+		}
+
+		self
+			.send_command(printer_id, PrintCommand::new("pushall"))
+			.await
+	}
+
+	/// Homes all axes with `G28`. Refused mid-job, where homing would ruin
+	/// the print.
+	pub async fn home_printer(&self, printer_id: &str) -> Result<()> {
+		{
+			let states = self.printer_states.read().await;
+			let printer = states
+				.get(printer_id)
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+			if matches!(
+				printer.status,
+				PrinterStatus::Preparing | PrinterStatus::Printing | PrinterStatus::Paused
+			) {
+				return Err(anyhow!(
+					"Cannot home {} while a print is in progress",
+					printer.name
+				));
+			}
+		}
+
+		self
+			.send_command(
+				printer_id,
+				PrintCommand::with_param("gcode_line", serde_json::json!("G28")),
+			)
+			.await
+	}
+
+	/// Starts the selected calibration routines. The printer reports each step
+	/// through `stg_cur`, which shows up as `calibration_stage`.
+	pub async fn run_calibration(&self, printer_id: &str, options: CalibrationOptions) -> Result<()> {
+		let option = options.bitmask();
+		if option == 0 {
+			return Err(anyhow!("No calibration selected"));
+		}
+
+		{
+			let states = self.printer_states.read().await;
+			let printer = states
+				.get(printer_id)
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+			if matches!(
+				printer.status,
+				PrinterStatus::Preparing | PrinterStatus::Printing | PrinterStatus::Paused
+			) {
+				return Err(anyhow!(
+					"Cannot calibrate {} while a print is in progress",
+					printer.name
+				));
+			}
+		}
+
+		self
+			.send_command(
+				printer_id,
+				PrintCommand::with_param("calibration", serde_json::json!({ "option": option })),
+			)
+			.await
+	}
+
+	/// Starts `file`, a `.3mf` project already on the printer's storage, e.g.
+	/// `/cache/benchy.gcode.3mf`. Only an idle printer takes a new job.
+	pub async fn start_print(
+		&self,
+		printer_id: &str,
+		file: &str,
+		options: StartPrintOptions,
+	) -> Result<()> {
+		let file = file.trim();
+		// The storage is mounted at /sdcard on the printer, but is the FTP root
+		let path = file.strip_prefix("/sdcard").unwrap_or(file);
+		let path = path.trim_start_matches('/');
+		if !path.to_ascii_lowercase().ends_with(".3mf") {
+			return Err(anyhow!("Only .3mf projects can be started, not '{file}'"));
+		}
+		if options.plate == Some(0) {
+			return Err(anyhow!("Plate numbers start at 1"));
+		}
+
+		let command = PrintCommand::with_param(
+			"project_file",
+			serde_json::json!({ "file": format!("/{path}"), "options": options }),
+		);
+		self.check_preconditions(printer_id, &command).await?;
+		self.send_command(printer_id, command).await
+	}
+
+	/// Adds a printer and starts connecting to it in the background.
+	///
+	/// With `require_connection` unset, `Ok` only means the printer was saved;
+	/// one that is switched off connects whenever it comes up. Set, it waits
+	/// for the first `ConnAck` and fails, without keeping the printer, if the
+	/// printer can't be reached or rejects the access code.
+	pub async fn add_printer(&self, config: PrinterConfig, require_connection: bool) -> Result<()> {
+		config.validate()?;
+
+		info!("Adding printer: {} ({})", config.name, config.ip);
+
+		// Store initial state, checking for duplicates under the same lock
+		let printer = {
+			let mut states = self.printer_states.write().await;
+			Self::ensure_unique_serial(&states, &config)?;
+
+			// Show last session's state until the printer reports; only taken
+			// once the printer is sure to be added, so a rejected add keeps it
+			let mut printer = Printer::new(&config);
+			if let Some(state) = self.last_known_states.write().await.remove(&config.id) {
+				printer.restore_last_known(&state);
+			}
+			let printer = Arc::new(printer);
+			states.insert(config.id.clone(), Arc::clone(&printer));
+			printer
+		};
+		{
+			let mut configs = self.printer_configs.write().await;
+			configs.insert(config.id.clone(), config.clone());
+		}
+
+		// Emit initial state to frontend
+		self.emit_printer_update(&printer).await;
+
+		// Start MQTT connection in background
+		let printer_id = config.id.clone();
+		self.spawn_connection(config).await;
+
+		if require_connection {
+			if let Err(e) = self.wait_for_connection(&printer_id).await {
+				if let Err(remove_error) = self.remove_printer(&printer_id).await {
+					warn!("Failed to remove unreachable printer {printer_id}: {remove_error}");
+				}
+				return Err(e);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Waits until the printer's first connection attempt settles, one way or
+	/// the other, for at most `ADD_PRINTER_CONNECT_TIMEOUT`.
+	async fn wait_for_connection(&self, printer_id: &str) -> Result<()> {
+		let mut snapshots = self.printer_states.snapshot.subscribe();
+		let settled = tokio::time::timeout(ADD_PRINTER_CONNECT_TIMEOUT, async {
+			loop {
+				let printer = snapshots.borrow_and_update().get(printer_id).cloned();
+				if let Some(outcome) = Self::connection_outcome(printer_id, printer.as_deref()) {
+					return outcome;
+				}
+				if snapshots.changed().await.is_err() {
+					return Err(anyhow!("Printer states are no longer being updated"));
+				}
+			}
+		})
+		.await;
+
+		settled.unwrap_or_else(|_| {
+			Err(anyhow!(
+				"Printer {printer_id} didn't connect within {}s",
+				ADD_PRINTER_CONNECT_TIMEOUT.as_secs()
+			))
+		})
+	}
+
+	/// Whether a connection attempt has settled: `Ok` once connected, an error
+	/// once it failed, `None` while it's still going.
+	fn connection_outcome(printer_id: &str, printer: Option<&Printer>) -> Option<Result<()>> {
+		let Some(printer) = printer else {
+			return Some(Err(anyhow!(
+				"Printer {printer_id} was removed while connecting"
+			)));
+		};
+
+		match printer.connection_state {
+			ConnectionState::Connected => Some(Ok(())),
+			ConnectionState::AuthFailed => {
+				Some(Err(anyhow!("{} rejected the access code", printer.name)))
+			}
+			ConnectionState::Failed | ConnectionState::Unreachable => Some(Err(anyhow!(
+				"Couldn't connect to {} at {}",
+				printer.name,
+				printer.ip
+			))),
+			ConnectionState::Disabled => Some(Err(anyhow!(
+				"Monitoring of {} was switched off while connecting",
+				printer.name
+			))),
+			ConnectionState::Connecting | ConnectionState::Stale | ConnectionState::Reconnecting => None,
+		}
+	}
+
+	/// Updates a printer's configuration in place.
+	///
+	/// If a connection-relevant field (IP, port, TLS mode, access code or serial) changed the
+	/// MQTT connection is torn down and restarted; a rename only updates the
+	/// in-memory `Printer`. Accumulated MQTT state is kept unless the serial
+	/// changed, since it then belongs to a different device.
+	pub async fn update_printer(&self, config: PrinterConfig) -> Result<()> {
+		config.validate()?;
+		Self::ensure_unique_serial(&*self.printer_states.read().await, &config)?;
+
+		let previous = {
+			let configs = self.printer_configs.read().await;
+			configs
+				.get(&config.id)
+				.cloned()
+				.ok_or_else(|| anyhow!("Printer {} not found", config.id))?
+		};
+
+		// A printer with monitoring switched off picks up the new config when re-enabled
+		let disabled = self
+			.printer_states
+			.read()
+			.await
+			.get(&config.id)
+			.is_some_and(|printer| printer.status == PrinterStatus::Disabled);
+
+		let serial_changed = previous.serial != config.serial;
+		let reconnect = !disabled
+			&& (serial_changed
+				|| previous.ip != config.ip
+				|| previous.access_code != config.access_code
+				|| previous.mqtt_port() != config.mqtt_port()
+				|| previous.tls_mode != config.tls_mode
+				|| previous.poll_interval_secs != config.poll_interval_secs
+				|| previous.keep_alive() != config.keep_alive()
+				|| previous.username() != config.username()
+				|| previous.max_reconnect_attempts != config.max_reconnect_attempts
+				|| previous.connection_mode != config.connection_mode
+				|| previous.extra_topics != config.extra_topics);
+
+		info!(
+			"Updating printer: {} ({}){}",
+			config.name,
+			config.ip,
+			if reconnect { ", reconnecting" } else { "" }
+		);
+
+		{
+			let mut configs = self.printer_configs.write().await;
+			configs.insert(config.id.clone(), config.clone());
+		}
+
+		if reconnect {
+			self.stop_connection(&config.id).await;
+		}
+
+		if serial_changed {
+			let mut mqtt_states = self.printer_mqtt_states.write().await;
+			mqtt_states.remove(&config.id);
+		}
+
+		Self::update_printer_status(&self.printer_states, &self.events, &config.id, |printer| {
+			printer.name = config.name.clone();
+			printer.model = config.model.clone();
+			printer.capabilities = PrinterCapabilities::for_model(&config.model);
+			printer.notifications_enabled = config.notifications_enabled;
+			printer.temperature_thresholds = config.temperature_thresholds;
+			printer.tags = config.tags.clone();
+			printer.location = config.location.clone();
+			printer.ip = config.ip.clone();
+			printer.access_code = config.access_code.clone();
+			printer.serial = config.serial.clone();
+			if reconnect {
+				printer.online = false;
+				printer.status = PrinterStatus::Connecting;
+				printer.connection_state = ConnectionState::Connecting;
+			}
+			printer.last_update = Utc::now();
+		})
+		.await;
+
+		if reconnect {
+			self.spawn_connection(config).await;
+		}
+
+		Ok(())
+	}
+
+	/// Changes only the display name, leaving the connection alone.
+	pub async fn rename_printer(&self, printer_id: &str, name: &str) -> Result<()> {
+		let name = name.trim();
+		if name.is_empty() {
+			return Err(anyhow!("Printer name must not be empty"));
+		}
+
+		{
+			let mut configs = self.printer_configs.write().await;
+			let config = configs
+				.get_mut(printer_id)
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+			info!("Renaming printer {} to {}", config.name, name);
+			config.name = name.to_string();
+		}
+
+		Self::update_printer_status(&self.printer_states, &self.events, printer_id, |printer| {
+			printer.name = name.to_string();
+		})
+		.await;
+
+		Ok(())
+	}
+
+	/// Switches monitoring for a printer off or back on. While off, the MQTT
+	/// connection is torn down but the printer stays listed as `Disabled`.
+	pub async fn set_monitoring_enabled(&self, printer_id: &str, enabled: bool) -> Result<()> {
+		let config = {
+			let configs = self.printer_configs.read().await;
+			configs
+				.get(printer_id)
+				.cloned()
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?
+		};
+
+		let disabled = self
+			.printer_states
+			.read()
+			.await
+			.get(printer_id)
+			.is_some_and(|printer| printer.status == PrinterStatus::Disabled);
+		if enabled != disabled {
+			// Already in the requested state
+			return Ok(());
+		}
+
+		if enabled {
+			info!("Resuming monitoring for {}", config.name);
+			Self::update_printer_status(&self.printer_states, &self.events, printer_id, |printer| {
+				printer.online = false;
+				printer.status = PrinterStatus::Connecting;
+				printer.connection_state = ConnectionState::Connecting;
+				printer.last_update = Utc::now();
+			})
+			.await;
+			self.spawn_connection(config).await;
+		} else {
+			info!("Pausing monitoring for {}", config.name);
+			self.stop_connection(printer_id).await;
+			Self::update_printer_status(&self.printer_states, &self.events, printer_id, |printer| {
+				printer.online = false;
+				printer.status = PrinterStatus::Disabled;
+				printer.connection_state = ConnectionState::Disabled;
+				printer.last_update = Utc::now();
+				printer.offline_since = None;
+			})
+			.await;
+		}
+
+		Ok(())
+	}
+
+	/// Tears down the printer's connection and starts a fresh one, e.g. after
+	/// it was marked unreachable by `max_reconnect_attempts`.
+	pub async fn reconnect_printer(&self, printer_id: &str) -> Result<()> {
+		let config = {
+			let configs = self.printer_configs.read().await;
+			configs
+				.get(printer_id)
+				.cloned()
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?
+		};
+
+		let disabled = self
+			.printer_states
+			.read()
+			.await
+			.get(printer_id)
+			.is_some_and(|printer| printer.status == PrinterStatus::Disabled);
+		if disabled {
+			return Err(anyhow!(
+				"Monitoring is disabled for {}; enable it to reconnect",
+				config.name
+			));
+		}
+
+		info!("Reconnecting to {}", config.name);
+		self.stop_connection(printer_id).await;
+		Self::update_printer_status(&self.printer_states, &self.events, printer_id, |printer| {
+			printer.online = false;
+			printer.status = PrinterStatus::Connecting;
+			printer.connection_state = ConnectionState::Connecting;
+			printer.last_update = Utc::now();
+		})
+		.await;
+		Self::update_metrics(&self.connection_metrics, printer_id, |metrics| {
+			metrics.reconnects = 0;
+			metrics.backoff_secs = None;
+		})
+		.await;
+		self.spawn_connection(config).await;
+
+		Ok(())
+	}
+
+	/// Rejects a config whose serial already belongs to another printer, since
+	/// two clients on the same `device/{serial}/report` topic fight each other.
+	fn ensure_unique_serial(
+		states: &HashMap<String, Arc<Printer>>,
+		config: &PrinterConfig,
+	) -> Result<()> {
+		let duplicate = states
+			.values()
+			.find(|printer| printer.id != config.id && same_serial(&printer.serial, &config.serial));
+
+		match duplicate {
+			Some(existing) => Err(anyhow!(
+				"printer with serial {} already added as '{}'",
+				config.serial.trim(),
+				existing.name
+			)),
+			None => Ok(()),
+		}
+	}
+
+	async fn spawn_connection(&self, config: PrinterConfig) {
+		let printer_id = config.id.clone();
+		let service = self.clone();
+		let cancel = CancellationToken::new();
+		let task_cancel = cancel.clone();
+		let handle = tauri::async_runtime::spawn(async move {
+			if let ConnectionMode::Mock(options) = config.connection_mode.clone() {
+				Self::run_mock_printer(service, config, options).await;
+				return;
+			}
+
+			let poll = config.poll_interval_secs.map(|secs| {
+				Self::poll_status(
+					Arc::clone(&service.printer_connections),
+					config.id.clone(),
+					config.serial.clone(),
+					Duration::from_secs(secs),
+				)
+			});
+			let connection = Self::start_mqtt_connection_task(service, config, task_cancel);
+
+			// Polling runs in the same task so aborting the connection stops it too
+			match poll {
+				Some(poll) => {
+					tokio::select! {
+						_ = connection => {}
+						_ = poll => {}
+					}
+				}
+				None => connection.await,
+			}
+		});
+
+		let mut tasks = self.connection_tasks.write().await;
+		if let Some(previous) = tasks.insert(printer_id, ConnectionTask { handle, cancel }) {
+			previous.handle.abort();
+		}
+	}
+
+	/// Plays a simulated print through `handle_printer_message`, as if the
+	/// reports came from a printer. Stops once the print is over.
+	async fn run_mock_printer(service: MqttService, config: PrinterConfig, options: MockOptions) {
+		info!("Starting mock printer {}", config.name);
+		Self::update_printer_status(
+			&service.printer_states,
+			&service.events,
+			&config.id,
+			|printer| {
+				printer.online = true;
+				printer.status = PrinterStatus::Idle;
+				printer.connection_state = ConnectionState::Connected;
+				printer.last_update = Utc::now();
+			},
+		)
+		.await;
+
+		let topic = format!("device/{}/report", config.serial);
+		let started = Instant::now();
+		let mut ticker = tokio::time::interval(options.report_interval());
+		loop {
+			ticker.tick().await;
+			let elapsed = started.elapsed();
+
+			Self::update_metrics(&service.connection_metrics, &config.id, |metrics| {
+				metrics.messages_received += 1;
+				metrics.last_message_at = Some(Utc::now());
+			})
+			.await;
+			Self::handle_printer_message(
+				&service.printer_states,
+				&service.printer_mqtt_states,
+				&service.command_responses,
+				&service.temperature_waiters,
+				&service.events,
+				&config,
+				&topic,
+				options.report(elapsed),
+			)
+			.await;
+
+			if options.is_done(elapsed) {
+				info!("Mock print on {} is over", config.name);
+				return;
+			}
+		}
+	}
+
+	/// Fixed delay for this printer within `MAX_STATUS_JITTER`. Derived from
+	/// the id rather than random, so each printer keeps its own slot.
+	fn status_jitter(printer_id: &str) -> Duration {
+		let mut hasher = DefaultHasher::new();
+		printer_id.hash(&mut hasher);
+		let max_millis = MAX_STATUS_JITTER.as_millis() as u64;
+		Duration::from_millis(hasher.finish() % max_millis)
+	}
+
+	/// Asks for the full status and version info after `status_jitter`,
+	/// without holding up the event loop in the meantime.
+	fn request_initial_status(client: AsyncClient, config: &PrinterConfig) {
+		let name = config.name.clone();
+		let serial = config.serial.clone();
+		let delay = Self::status_jitter(&config.id);
+		tauri::async_runtime::spawn(async move {
+			tokio::time::sleep(delay).await;
+
+			match Self::send_mqtt_command(&client, &serial, &PrintCommand::new("get_status")).await {
+				Ok(_) => info!("Initial status request sent to {name}"),
+				Err(e) => error!("Failed to send initial status request to {name}: {e}"),
+			}
+			if let Err(e) =
+				Self::send_mqtt_command(&client, &serial, &PrintCommand::new("get_version")).await
+			{
+				error!("Failed to request version info from {name}: {e}");
+			}
+		});
+	}
+
+	/// Sends `get_status` every `interval` while the printer is connected.
+	async fn poll_status(
+		printer_connections: Arc<RwLock<HashMap<String, AsyncClient>>>,
+		printer_id: String,
+		printer_serial: String,
+		interval: Duration,
+	) {
+		// Connecting already requests status, so the first poll is one interval out
+		let start = tokio::time::Instant::now() + interval + Self::status_jitter(&printer_id);
+		let mut ticker = tokio::time::interval_at(start, interval);
+		ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+		loop {
+			ticker.tick().await;
+
+			let client = printer_connections.read().await.get(&printer_id).cloned();
+			let Some(client) = client else {
+				continue;
+			};
+
+			if let Err(e) =
+				Self::send_mqtt_command(&client, &printer_serial, &PrintCommand::new("get_status")).await
+			{
+				error!("Failed to poll status for printer {printer_id}: {e}");
+			}
+		}
+	}
+
+	async fn stop_connection(&self, printer_id: &str) {
+		if let Some(task) = self.connection_tasks.write().await.remove(printer_id) {
+			task.handle.abort();
+		}
+
+		let mut connections = self.printer_connections.write().await;
+		connections.remove(printer_id);
+	}
+
+	async fn start_mqtt_connection_task(
+		service: MqttService,
+		config: PrinterConfig,
+		cancel: CancellationToken,
+	) {
+		let MqttService {
+			printer_states,
+			printer_mqtt_states,
+			printer_connections,
+			connection_metrics,
+			command_responses,
+			temperature_waiters,
+			connection_ready,
+			captures,
+			events,
+			..
+		} = service;
+		let printer_id = config.id.clone();
+		// The client id changes on every reconnect attempt, not just per task. A
+		// broker that still holds our previous session (the old socket half-open
+		// after a network drop) or sees another app connecting with the same id
+		// drops one of the two, and with a fixed id both keep kicking each other
+		// off in a reconnect loop.
+		let client_id = Self::fresh_client_id(&config);
+		info!("Connecting to {} as {client_id}", config.name);
+
+		let mqtt_options = match Self::mqtt_options(&config, &client_id) {
+			Ok(mqtt_options) => mqtt_options,
+			Err(e) => {
+				// A bad CA file won't fix itself by retrying, so give up on this connection
+				error!("Failed to set up TLS for {}: {}", config.name, e);
+				Self::update_printer_status(&printer_states, &events, &printer_id, |printer| {
+					printer.online = false;
+					printer.status = PrinterStatus::Offline;
+					printer.connection_state = ConnectionState::Failed;
+					printer.last_update = Utc::now();
+					printer.offline_since.get_or_insert_with(Utc::now);
+				})
+				.await;
+				return;
+			}
+		};
+
+		let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
+		let topics = config.subscription_topics();
+		let mut last_error_event: Option<(Instant, String)> = None;
+		let mut connected = false;
+		let mut failed_attempts: u32 = 0;
+
+		loop {
+			let event = tokio::select! {
+				_ = cancel.cancelled() => {
+					if connected {
+						Self::disconnect(&client, &mut event_loop).await;
+						info!("Disconnected from printer {}", config.name);
+					}
+					return;
+				}
+				event = event_loop.poll() => event,
+			};
+
+			match event {
+				Ok(Event::Incoming(Packet::ConnAck(_))) => {
+					info!("Connected to printer {} ({})", config.name, config.ip);
+					connected = true;
+					failed_attempts = 0;
+
+					Self::update_metrics(&connection_metrics, &printer_id, |metrics| {
+						metrics.reconnects = 0;
+						metrics.backoff_secs = None;
+					})
+					.await;
+
+					// Subscribe to status topic
+					for topic in &topics {
+						if let Err(e) = client.subscribe(topic, QoS::AtMostOnce).await {
+							error!("Failed to subscribe to {topic}: {e}");
+						} else {
+							info!("Subscribed to {topic}");
+						}
+					}
+
+					// Request full status and module versions (which are only sent on request)
+					Self::request_initial_status(client.clone(), &config);
+
+					// Note: Periodic polling is opt-in (`poll_interval_secs`) to avoid hardware lag
+					// on P1P printers; by default we rely on this request and real-time MQTT updates
+
+					// Update connection state; a restored snapshot stays until the first report
+					Self::update_printer_status(&printer_states, &events, &printer_id, |printer| {
+						printer.online = true;
+						if printer.connection_state != ConnectionState::Stale {
+							printer.status = PrinterStatus::Idle;
+							printer.connection_state = ConnectionState::Connected;
+						}
+						printer.last_update = Utc::now();
+						printer.offline_since = None;
+					})
+					.await;
+
+					// Add MQTT client to connection pool
+					{
+						let mut connections = printer_connections.write().await;
+						connections.insert(printer_id.clone(), client.clone());
+					}
+					connection_ready.notify_waiters();
+				}
+				Ok(Event::Incoming(Packet::Publish(publish))) => {
+					debug!("Received MQTT message on topic: {}", publish.topic);
+
+					Self::update_metrics(&connection_metrics, &printer_id, |metrics| {
+						metrics.messages_received += 1;
+						metrics.last_message_at = Some(Utc::now());
+					})
+					.await;
+					captures.record(&printer_id, &publish.topic, &publish.payload);
+
+					for data in Self::parse_payload(&config.name, &publish.payload) {
+						Self::handle_printer_message(
+							&printer_states,
+							&printer_mqtt_states,
+							&command_responses,
+							&temperature_waiters,
+							&events,
+							&config,
+							&publish.topic,
+							data,
+						)
+						.await;
+					}
+				}
+				Ok(Event::Incoming(Packet::SubAck(_))) => {
+					debug!("Successfully subscribed to topic");
+				}
+				Ok(_) => {
+					// Other events we don't need to handle
+				}
+				Err(e) => {
+					error!("MQTT connection error for {}: {}", config.name, e);
+					let connection_state = ConnectionState::after_error(&e, connected);
+					connected = false;
+
+					let error = e.to_string();
+					let now = Instant::now();
+					if Self::should_emit_connection_error(&last_error_event, &error, now) {
+						let event = ConnectionErrorEvent {
+							printer_id: printer_id.clone(),
+							error: error.clone(),
+							kind: Self::connection_error_kind(&e),
+							retry_in_secs: RECONNECT_DELAY.as_secs(),
+						};
+						if let Err(e) = events.emit("connection-error", &event) {
+							error!("Failed to emit connection error: {e}");
+						}
+						last_error_event = Some((now, error));
+					}
+
+					Self::update_printer_status(&printer_states, &events, &printer_id, |printer| {
+						printer.online = false;
+						printer.status = PrinterStatus::Offline;
+						printer.connection_state = connection_state;
+						printer.last_update = Utc::now();
+						// Each failed attempt refreshes `last_update`, but the printer went offline at the first
+						printer.offline_since.get_or_insert_with(Utc::now);
+					})
+					.await;
+
+					failed_attempts = failed_attempts.saturating_add(1);
+					if config
+						.max_reconnect_attempts
+						.is_some_and(|max| failed_attempts > max)
+					{
+						warn!(
+							"Giving up on {} after {} failed connection attempts",
+							config.name, failed_attempts
+						);
+						Self::update_printer_status(&printer_states, &events, &printer_id, |printer| {
+							printer.connection_state = ConnectionState::Unreachable;
+						})
+						.await;
+						Self::update_metrics(&connection_metrics, &printer_id, |metrics| {
+							metrics.reconnects = failed_attempts;
+							metrics.backoff_secs = None;
+						})
+						.await;
+						return;
+					}
+
+					Self::update_metrics(&connection_metrics, &printer_id, |metrics| {
+						metrics.reconnects += 1;
+						metrics.backoff_secs = Some(RECONNECT_DELAY.as_secs());
+					})
+					.await;
+
+					// Wait before attempting reconnection
+					tokio::select! {
+						_ = cancel.cancelled() => return,
+						_ = tokio::time::sleep(RECONNECT_DELAY) => {}
+					}
+
+					let client_id = Self::fresh_client_id(&config);
+					match Self::mqtt_options(&config, &client_id) {
+						Ok(mqtt_options) => {
+							info!("Reconnecting to {} as {client_id}", config.name);
+							event_loop.mqtt_options = mqtt_options;
+						}
+						Err(e) => warn!(
+							"Failed to rebuild options for {}, reconnecting with the previous client id: {e}",
+							config.name
+						),
+					}
+				}
+			}
+		}
+	}
+
+	/// Sends an MQTT DISCONNECT and keeps polling until it has been written,
+	/// so the printer closes the session instead of waiting for it to time out.
+	async fn disconnect(client: &AsyncClient, event_loop: &mut EventLoop) {
+		if client.disconnect().await.is_err() {
+			return;
+		}
+
+		loop {
+			match event_loop.poll().await {
+				Ok(Event::Outgoing(Outgoing::Disconnect)) | Err(_) => break,
+				Ok(_) => {}
+			}
+		}
+	}
+
+	/// Disconnects every printer cleanly, giving up after `SHUTDOWN_TIMEOUT`.
+	pub async fn shutdown(&self) {
+		let mut tasks: Vec<ConnectionTask> = {
+			let mut tasks = self.connection_tasks.write().await;
+			tasks.drain().map(|(_, task)| task).collect()
+		};
+
+		info!("Disconnecting {} printer(s)", tasks.len());
+		tasks.extend(self.mirror_task.write().await.take());
+		for task in &tasks {
+			task.cancel.cancel();
+		}
+
+		let mut handles: Vec<JoinHandle<()>> = tasks.into_iter().map(|task| task.handle).collect();
+		let all_stopped = async {
+			for handle in handles.iter_mut() {
+				let _ = handle.await;
+			}
+		};
+		if tokio::time::timeout(SHUTDOWN_TIMEOUT, all_stopped)
+			.await
+			.is_err()
+		{
+			warn!("Timed out waiting for printers to disconnect");
+		}
+
+		for handle in &handles {
+			handle.abort();
+		}
+		self.printer_connections.write().await.clear();
+
+		// Whatever changed since the last flush would otherwise be lost
+		if let Err(e) = self.flush_printer_states().await {
+			error!("Failed to save printer states on shutdown: {e}");
+		}
+	}
+
+	/// Saves every printer whose state changed since the last flush every
+	/// `PrinterStateStore::flush_interval`, for as long as the app runs.
+	pub fn start_state_flusher(&self, store: PrinterStateStore) {
+		let service = self.clone();
+		tauri::async_runtime::spawn(async move {
+			loop {
+				tokio::time::sleep(store.flush_interval()).await;
+				if let Err(e) = service.flush_printer_states().await {
+					error!("Failed to save printer states: {e}");
+				}
+			}
+		});
+	}
+
+	/// Writes the state of the printers marked dirty since the last flush,
+	/// returning how many were saved. Failed writes are retried next time.
+	pub async fn flush_printer_states(&self) -> Result<usize> {
+		let Some(store) = self.events.state_store() else {
+			return Ok(0);
+		};
+		let dirty = store.take_dirty();
+		if dirty.is_empty() {
+			return Ok(0);
+		}
+
+		let states: Vec<PrinterState> = {
+			let printers = self.printer_states.read().await;
+			// Removed printers are skipped; their rows are already deleted
+			dirty
+				.iter()
+				.filter_map(|printer_id| printers.get(printer_id))
+				.map(|printer| printer.to_stored_state())
+				.collect()
+		};
+		if let Err(e) = store.save_all(&states).await {
+			for printer_id in &dirty {
+				store.mark_dirty(printer_id);
+			}
+			return Err(e);
+		}
+
+		debug!("Saved state for {} printer(s)", states.len());
+		Ok(states.len())
+	}
+
+	/// Starts mirroring printer state to `config`'s broker, replacing any
+	/// previous mirror. `None` turns mirroring off.
+	pub async fn set_mirror_broker(&self, config: Option<MirrorConfig>) -> Result<()> {
+		let task = match config {
+			Some(config) => {
+				let mqtt_options = config.mqtt_options()?;
+				let snapshots = self.printer_states.snapshot.subscribe();
+				let cancel = CancellationToken::new();
+				let handle = tauri::async_runtime::spawn(Self::run_mirror(
+					config.host.clone(),
+					mqtt_options,
+					snapshots,
+					cancel.clone(),
+				));
+				Some(ConnectionTask { handle, cancel })
+			}
+			None => None,
+		};
+
+		let previous = std::mem::replace(&mut *self.mirror_task.write().await, task);
+		if let Some(previous) = previous {
+			previous.cancel.cancel();
+		}
+		Ok(())
+	}
+
+	async fn run_mirror(
+		host: String,
+		mqtt_options: MqttOptions,
+		mut snapshots: watch::Receiver<Arc<PrinterMap>>,
+		cancel: CancellationToken,
+	) {
+		let (client, mut event_loop) = AsyncClient::new(mqtt_options, 64);
+		let mut published: HashMap<String, Arc<Printer>> = HashMap::new();
+
+		loop {
+			tokio::select! {
+				_ = cancel.cancelled() => {
+					Self::disconnect(&client, &mut event_loop).await;
+					info!("Stopped mirroring to {host}");
+					return;
+				}
+				changed = snapshots.changed() => {
+					if changed.is_err() {
+						return;
+					}
+					let snapshot = Arc::clone(&snapshots.borrow_and_update());
+					Self::publish_mirror(&client, &snapshot, &mut published);
+				}
+				event = event_loop.poll() => match event {
+					Ok(Event::Incoming(Packet::ConnAck(_))) => {
+						info!("Mirroring printer state to {host}");
+						// Retained messages may have been lost with the broker, so send everything
+						published.clear();
+						let snapshot = Arc::clone(&snapshots.borrow_and_update());
+						Self::publish_mirror(&client, &snapshot, &mut published);
+					}
+					Ok(_) => {}
+					Err(e) => {
+						warn!("Mirror broker {host} connection error: {e}");
+						tokio::select! {
+							_ = cancel.cancelled() => return,
+							_ = tokio::time::sleep(RECONNECT_DELAY) => {}
+						}
+					}
+				},
+			}
+		}
+	}
+
+	/// Queues the changes since `published` without waiting, so a slow mirror
+	/// broker can't hold up the event loop that drains the queue.
+	fn publish_mirror(
+		client: &AsyncClient,
+		snapshot: &PrinterMap,
+		published: &mut HashMap<String, Arc<Printer>>,
+	) {
+		let (changed, removed) = MirrorConfig::changes(snapshot, published);
+		for printer in changed {
+			let mut state = match serde_json::to_value(printer.as_ref()) {
+				Ok(state) => state,
+				Err(e) => {
+					error!("Failed to serialize {} for the mirror: {e}", printer.name);
+					continue;
+				}
+			};
+			if let Some(state) = state.as_object_mut() {
+				state.remove("access_code");
+			}
+			let topic = MirrorConfig::state_topic(&printer.id);
+			match client.try_publish(topic, QoS::AtMostOnce, true, state.to_string()) {
+				Ok(()) => {
+					published.insert(printer.id.clone(), printer);
+				}
+				Err(e) => warn!("Dropped mirror update for {}: {e}", printer.name),
+			}
+		}
+		for printer_id in removed {
+			let topic = MirrorConfig::state_topic(&printer_id);
+			if client
+				.try_publish(topic, QoS::AtMostOnce, true, Vec::new())
+				.is_ok()
+			{
+				published.remove(&printer_id);
+			}
+		}
+	}
+
+	/// Emits a `connection-error` when the error changes, or when the same
+	/// error has persisted for `CONNECTION_ERROR_EVENT_INTERVAL`.
+	fn should_emit_connection_error(
+		last: &Option<(Instant, String)>,
+		error: &str,
+		now: Instant,
+	) -> bool {
+		match last {
+			Some((emitted_at, last_error)) => {
+				last_error != error || now.duration_since(*emitted_at) >= CONNECTION_ERROR_EVENT_INTERVAL
+			}
+			None => true,
+		}
+	}
+
+	/// Variant name of a `rumqttc::ConnectionError`, taken from its `Debug`
+	/// output so feature-gated variants are covered too.
+	fn connection_error_kind(error: &rumqttc::ConnectionError) -> String {
+		format!("{error:?}")
+			.split(|c: char| !c.is_alphanumeric())
+			.next()
+			.unwrap_or_default()
+			.to_string()
+	}
+
+	async fn update_metrics<F>(
+		connection_metrics: &Arc<RwLock<HashMap<String, ConnectionMetrics>>>,
+		printer_id: &str,
+		update_fn: F,
+	) where
+		F: FnOnce(&mut ConnectionMetrics),
+	{
+		let mut metrics = connection_metrics.write().await;
+		update_fn(metrics.entry(printer_id.to_string()).or_default());
+	}
+
+	/// A client id no other session has used, so the broker never mistakes a
+	/// new connection for one it already holds.
+	fn fresh_client_id(config: &PrinterConfig) -> String {
+		format!("pulseprint_desktop_{}_{}", config.id, Uuid::new_v4())
+	}
+
+	/// Connection options for `config`, including TLS. Fails only if the TLS
+	/// setup does, e.g. on an unreadable CA file.
+	fn mqtt_options(config: &PrinterConfig, client_id: &str) -> Result<MqttOptions> {
+		let mut mqtt_options = MqttOptions::new(client_id, config.host(), config.mqtt_port());
+		mqtt_options
+			.set_credentials(config.username(), &config.access_code)
+			.set_keep_alive(config.keep_alive())
+			.set_transport(Transport::Tls(TlsConfiguration::Rustls(Arc::new(
+				Self::build_tls_config(&config.tls_mode)?,
+			))));
+		Ok(mqtt_options)
+	}
+
+	fn build_tls_config(mode: &TlsMode) -> Result<rustls::ClientConfig> {
+		let provider = crypto_provider();
+		let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+			.with_safe_default_protocol_versions()?;
+
+		let tls_config = match mode {
+			// Use TLS but bypass certificate validation entirely
+			// This matches PulsePrint behavior: rejectUnauthorized: false
+			// Bambu Lab printers use self-signed certificates that don't validate
+			// Using setInsecure() equivalent by creating a custom TLS config
+			TlsMode::InsecureSkipVerify => builder
+				.dangerous()
+				.with_custom_certificate_verifier(Arc::new(InsecureVerifier::new(provider)))
+				.with_no_client_auth(),
+			TlsMode::VerifyWithCa(ca_path) => builder
+				.with_root_certificates(Self::load_root_store(ca_path)?)
+				.with_no_client_auth(),
+		};
+
+		Ok(tls_config)
+	}
+
+	fn load_root_store(ca_path: &Path) -> Result<rustls::RootCertStore> {
+		let file = std::fs::File::open(ca_path)
+			.map_err(|e| anyhow!("Failed to open CA certificate {}: {}", ca_path.display(), e))?;
+		let mut reader = std::io::BufReader::new(file);
+
+		let mut root_store = rustls::RootCertStore::empty();
+		for cert in rustls_pemfile::certs(&mut reader) {
+			let cert =
+				cert.map_err(|e| anyhow!("Failed to read CA certificate {}: {}", ca_path.display(), e))?;
+			root_store
+				.add(cert)
+				.map_err(|e| anyhow!("Invalid CA certificate in {}: {}", ca_path.display(), e))?;
+		}
+
+		if root_store.is_empty() {
+			return Err(anyhow!("No certificates found in {}", ca_path.display()));
+		}
+
+		Ok(root_store)
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	async fn handle_printer_message(
+		printer_states: &Arc<PrinterStates>,
+		printer_mqtt_states: &Arc<RwLock<HashMap<String, serde_json::Value>>>,
+		command_responses: &broadcast::Sender<CommandResponse>,
+		temperature_waiters: &RwLock<HashMap<String, Vec<TemperatureWaiter>>>,
+		events: &Events,
+		config: &PrinterConfig,
+		topic: &str,
+		data: serde_json::Value,
+	) {
+		debug!(
+			"Processing MQTT data for {} from {}: {}",
+			config.name, topic, data
+		);
+		if let Some(response) = CommandResponse::from_report(&config.id, &data) {
+			// No receivers just means nobody is waiting on this command
+			let _ = command_responses.send(response);
+		}
+		let data = &Self::scope_to_topic(config, topic, data);
+
+		// Get or initialize persistent state for this printer
+		let persistent_state = {
+			let mut mqtt_states = printer_mqtt_states.write().await;
+			let current_state = mqtt_states
+				.get(&config.id)
+				.cloned()
+				.unwrap_or_else(|| serde_json::Value::Object(serde_json::Map::new()));
+
+			// Deep merge the incoming data with existing state
+			let merged_state = Self::merge_report(current_state, data.clone());
+			mqtt_states.insert(config.id.clone(), merged_state.clone());
+			merged_state
+		};
+
+		// Check if we need to request full status due to minimal data
+		let print_keys: Vec<String> = if let Some(print_obj) = data.get("print") {
+			if let Some(obj) = print_obj.as_object() {
+				obj.keys().cloned().collect()
+			} else {
+				Vec::new()
+			}
+		} else {
+			Vec::new()
+		};
+
+		let is_minimal_update = print_keys.len() <= 3
+			&& (data
+				.get("print")
+				.and_then(|p| p.get("command"))
+				.and_then(|c| c.as_str())
+				== Some("push_status")
+				|| data
+					.get("print")
+					.and_then(|p| p.get("wifi_signal"))
+					.is_some());
+
+		if is_minimal_update {
+			info!("Minimal data detected for {}, but avoiding frequent status requests to prevent hardware lag", config.name);
+			// Note: Based on OpenBambuAPI docs, frequent status requests can cause lag on P1P printers
+			// We rely on the initial status request and accumulated state instead
+		}
+
+		// Pretty-printing the full state on every report is expensive, so skip it unless asked for
+		if log_enabled!(Level::Debug) {
+			debug!(
+				"Raw MQTT data from {}: {}",
+				config.name,
+				serde_json::to_string_pretty(data).unwrap_or_default()
+			);
+			debug!(
+				"Accumulated state for {}: {}",
+				config.name,
+				serde_json::to_string_pretty(&persistent_state).unwrap_or_default()
+			);
+		}
+
+		let state_store = events.state_store();
+		let mut status_change = None;
+		let mut layer_change = None;
+		let mut error_cleared = None;
+		let mut notification = None;
+		let mut detection_alert = None;
+		let mut temperature_alerts = Vec::new();
+		let mut temperatures = None;
+		Self::update_printer_status(printer_states, events, &config.id, |printer| {
+			if printer.connection_state == ConnectionState::Stale {
+				// First report since restoring last session's state: let it decide the status afresh
+				printer.status = PrinterStatus::Connecting;
+				printer.connection_state = ConnectionState::Connected;
+			}
+			let previous_layer = printer.print.as_ref().map(|job| job.layer_current);
+			let previous_error = printer.error.clone();
+			let previous_detection = printer.detection;
+			let previous_temperatures = printer.temperatures.clone();
+			let change = Self::apply_message(printer, &persistent_state);
+			detection_alert = DetectionAlert::between(previous_detection, printer);
+			temperature_alerts = TemperatureAlert::between(&previous_temperatures, printer);
+			layer_change = LayerChangeEvent::between(&printer.id, previous_layer, printer.print.as_ref());
+			error_cleared =
+				ErrorClearedEvent::between(&printer.id, previous_error.as_ref(), printer.error.as_ref());
+			if printer.notifications_enabled {
+				notification =
+					PrinterNotification::between(&change.previous, previous_error.as_ref(), printer);
+			}
+			temperatures = Some(printer.temperatures.clone());
+			printer.last_update = Utc::now();
+			// Written by the state flusher, off the message path
+			if let Some(store) = &state_store {
+				store.mark_dirty(&printer.id);
+			}
+			status_change = Some(change);
+		})
+		.await;
+
+		if let Some(temperatures) = &temperatures {
+			Self::resolve_temperature_waiters(temperature_waiters, &config.id, temperatures).await;
+		}
+
+		if let Some(event) = layer_change {
+			debug!(
+				"Layer change for {}: {}/{}",
+				config.name, event.layer_current, event.layer_total
+			);
+			if let Err(e) = events.emit("layer-change", &event) {
+				error!("Failed to emit layer change: {e}");
+			}
+		}
+
+		if let Some(event) = error_cleared {
+			info!("Error {} cleared on {}", event.error_code, config.name);
+			if let Err(e) = events.emit("error-cleared", &event) {
+				error!("Failed to emit error cleared: {e}");
+			}
+		}
+
+		if let Some(alert) = detection_alert {
+			warn!("{} reports possible spaghetti", config.name);
+			if let Err(e) = events.emit("detection-alert", &alert) {
+				error!("Failed to emit detection alert: {e}");
+			}
+		}
+
+		for alert in temperature_alerts {
+			warn!(
+				"{} {:?} temperature {}°C is above the {}°C threshold",
+				config.name, alert.sensor, alert.reading, alert.threshold
+			);
+			if let Err(e) = events.emit("temperature-alert", &alert) {
+				error!("Failed to emit temperature alert: {e}");
+			}
+		}
+
+		if let Some(notification) = notification {
+			if let Err(e) = events.emit(notification.event, &notification) {
+				error!("Failed to emit {}: {e}", notification.event);
+			}
+		}
+
+		if let Some(change) = status_change.filter(StatusChange::changed) {
+			info!(
+				"Status for {}: Changed from {:?} to {:?}",
+				config.name, change.previous, change.current
+			);
+		}
+	}
+
+	/// Parses the accumulated MQTT state into `printer`.
+	///
+	/// This is the pure half of `handle_printer_message`: it has no Tauri
+	/// dependency and performs no I/O, so the status detection heuristics can
+	/// be exercised directly from unit tests.
+	pub(crate) fn apply_message(
+		printer: &mut Printer,
+		accumulated: &serde_json::Value,
+	) -> StatusChange {
+		let previous_status = printer.status.clone();
+		let name = printer.name.clone();
+
+		let report = BambuReport::from_value(accumulated);
+		let non_empty = |value: &Option<String>| value.clone().filter(|s| !s.is_empty());
+
+		// Version info arrives rarely, so keep what we have until a new one shows up
+		if let Some(info) = &report.info {
+			let ota = info
+				.module
+				.iter()
+				.find(|module| module.name.as_deref() == Some("ota"));
+			if let Some(version) = ota.and_then(|module| non_empty(&module.sw_ver)) {
+				printer.firmware_version = Some(version);
+			}
+			if let Some(product) = info
+				.module
+				.iter()
+				.find_map(|module| non_empty(&module.product_name))
+			{
+				printer.product_name = Some(product);
+			}
+		}
+
+		// Parse print data from accumulated state instead of just current message
+		let (Some(print_data), Some(report)) = (accumulated.get("print"), report.print) else {
+			return StatusChange {
+				previous: previous_status.clone(),
+				current: previous_status,
+			};
+		};
+
+		// Update temperatures
+		if let Some(nozzle_temp) = report.nozzle_temper {
+			printer.temperatures.nozzle = nozzle_temp;
+		}
+		if let Some(bed_temp) = report.bed_temper {
+			printer.temperatures.bed = bed_temp;
+		}
+		if let Some(chamber_temp) = report.chamber_temper {
+			printer.temperatures.chamber = chamber_temp;
+		}
+		if let Some(target) = report.nozzle_target_temper {
+			printer.temperatures.nozzle_target = target;
+		}
+		if let Some(target) = report.bed_target_temper {
+			printer.temperatures.bed_target = target;
+		}
+		let temperatures = &mut printer.temperatures;
+		temperatures.nozzle_state =
+			HeatingState::from_temps(temperatures.nozzle, temperatures.nozzle_target);
+		temperatures.bed_state = HeatingState::from_temps(temperatures.bed, temperatures.bed_target);
+
+		printer.wifi_signal = report.wifi_signal;
+		printer.nozzle_diameter = report.nozzle_diameter.filter(|diameter| *diameter > 0.0);
+		printer.nozzle_type = non_empty(&report.nozzle_type);
+		printer.bed_type = report.bed_type.as_deref().and_then(bed_type_name);
+		printer.ams = AmsUnit::from_print_data(print_data);
+		printer.filament_slots = FilamentSlot::collect(&printer.ams, print_data);
+		let tray_now = report
+			.ams
+			.as_ref()
+			.and_then(|ams| ams.tray_now)
+			.and_then(|tray| u8::try_from(tray).ok());
+		printer.external_spool_active =
+			tray_now.is_some_and(|tray| AmsUnit::EXTERNAL_SPOOL_TRAYS.contains(&tray));
+		printer.active_tray = tray_now.filter(|tray| !AmsUnit::EXTERNAL_SPOOL_TRAYS.contains(tray));
+		printer.hms = HmsCode::from_print_data(print_data);
+		printer.storage = StorageInfo::from_print_data(print_data);
+		printer.camera = CameraStatus::from_print_data(print_data);
+		printer.calibration_stage = report
+			.stg_cur
+			.and_then(CalibrationOptions::stage_name)
+			.map(|name| name.to_string());
+
+		// Enhanced status detection logic based on accumulated state
+		let gcode_state = report.gcode_state.as_deref();
+		let print_real = report.print_real.unwrap_or(0);
+		let mc_remaining_time = report.mc_remaining_time.unwrap_or(0);
+		let mc_percent = report.mc_percent.unwrap_or(0.0);
+		let layer_num = report.layer_num.unwrap_or(0);
+		let stg_cur = report.stg_cur.unwrap_or(0);
+		let print_error = report.print_error.unwrap_or(0);
+		let error_code = report.mc_print_error_code.unwrap_or(0) as i32;
+		let fan_gear = report.fan_gear.unwrap_or(0);
+		let mc_print_stage = report.mc_print_stage;
+		let subtask_name = report.subtask_name.as_deref().unwrap_or("");
+
+		info!("Status detection for {}: gcode_state={:?}, print_real={}, mc_percent={}, layer_num={}, stg_cur={}, print_error={}, mc_remaining_time={}, fan_gear={}, subtask_name={:?}",
+			name, gcode_state, print_real, mc_percent, layer_num, stg_cur, print_error, mc_remaining_time, fan_gear, subtask_name);
+
+		// An error the user dismissed stays hidden until the printer reports a different one
+		let error_key = (print_error as i32, error_code);
+		let has_error = error_key != (0, 0) || matches!(gcode_state, Some("FAILED" | "ERROR"));
+		if !has_error || printer.acknowledged_error != Some(error_key) {
+			printer.acknowledged_error = None;
+		}
+		let error_acknowledged = printer.acknowledged_error.is_some();
+		let print_error = if error_acknowledged { 0 } else { print_error };
+
+		// Calculate key indicators for status detection
+		// In the final minute the printer reports 0 minutes left while still printing
+		let finishing = mc_remaining_time == 0 && (95.0..100.0).contains(&mc_percent);
+		let has_active_job =
+			mc_remaining_time > 0 || finishing || (layer_num > 0 && mc_percent < 100.0);
+		let has_progress = mc_percent > 0.0 && mc_percent < 100.0;
+		let has_job_name =
+			!subtask_name.is_empty() && subtask_name != "Unknown" && subtask_name != "undefined";
+		// Whole degrees are plenty for telling a heated printer from a cold one
+		let nozzle_temp = printer.temperatures.nozzle.round() as i32;
+		let has_high_temps = nozzle_temp > 150 || printer.temperatures.bed.round() as i32 > 40;
+		let has_active_fan = fan_gear > 0;
+		let is_in_print_stage = stg_cur == 1 || stg_cur == 2 || stg_cur == 3;
+		// Material is going down once layers count up; before that the job is still preparing
+		let preparing = layer_num == 0 && print_real != 1;
+		// Still running the job, e.g. cooling down after the last layer with gcode_state already FINISH
+		let mc_running_job = mc_print_stage == Some(2);
+
+		// Primary status detection: Start with the most reliable indicators
+		let new_status = if print_error > 0 {
+			info!("Status for {name}: Error (print_error={print_error})");
+			PrinterStatus::Error
+		} else if print_real == 1 {
+			// print_real is the most reliable indicator of actual printing
+			info!("Status for {name}: Printing (print_real=1)");
+			PrinterStatus::Printing
+		} else if let Some(gcode_state) = gcode_state {
+			// Use gcode_state when available
+			match gcode_state {
+				// Standard states
+				"RUNNING" | "PRINTING" if preparing && PrintJob::is_preparation_stage(stg_cur) => {
+					info!("Status for {name}: Preparing (gcode_state={gcode_state}, stg_cur={stg_cur})");
+					PrinterStatus::Preparing
+				}
+				"RUNNING" | "PRINTING" => {
+					info!("Status for {name}: Printing (gcode_state={gcode_state})");
+					PrinterStatus::Printing
+				}
+				"PAUSE" | "PAUSED" => {
+					info!("Status for {name}: Paused (gcode_state={gcode_state})");
+					PrinterStatus::Paused
+				}
+				"FAILED" | "ERROR" => {
+					info!("Status for {name}: Error (gcode_state={gcode_state})");
+					PrinterStatus::Error
+				}
+				"FINISH" | "FINISHED" => {
+					info!("Status for {name}: Idle (gcode_state={gcode_state})");
+					PrinterStatus::Idle
+				}
+				// Bambu Lab specific states
+				"PREPARE" | "SLICING" if preparing => {
+					info!("Status for {name}: Preparing (gcode_state={gcode_state})");
+					PrinterStatus::Preparing
+				}
+				"PREPARE" | "WORKING" | "SLICING" | "PRINTING_MONITOR" => {
+					info!("Status for {name}: Printing (Bambu gcode_state={gcode_state})");
+					PrinterStatus::Printing
+				}
+				"IDLE" => {
+					// Even if gcode_state is IDLE, check other indicators
+					if has_active_job || has_progress || (has_high_temps && has_active_fan) {
+						info!("Status for {name}: Printing (gcode_state=IDLE but has active indicators)");
+						PrinterStatus::Printing
+					} else {
+						info!("Status for {name}: Idle (gcode_state=IDLE, no active indicators)");
+						PrinterStatus::Idle
+					}
+				}
+				_ => {
+					// Unknown gcode_state, use comprehensive fallback logic
+					info!("Status for {name} (unknown gcode_state={gcode_state}): using fallback logic");
+					Self::determine_status_from_indicators(
+						&name,
+						has_active_job,
+						has_progress,
+						is_in_print_stage,
+						stg_cur,
+						has_high_temps,
+						has_active_fan,
+						has_job_name,
+						mc_remaining_time,
+						layer_num,
+						nozzle_temp,
+					)
+				}
+			}
+		} else {
+			// No gcode_state available, use comprehensive fallback logic
+			info!("Status for {name} (no gcode_state): using comprehensive fallback logic");
+			Self::determine_status_from_indicators(
+				&name,
+				has_active_job,
+				has_progress,
+				is_in_print_stage,
+				stg_cur,
+				has_high_temps,
+				has_active_fan,
+				has_job_name,
+				mc_remaining_time,
+				layer_num,
+				nozzle_temp,
+			)
+		};
+
+		let new_status = if error_acknowledged && new_status == PrinterStatus::Error {
+			PrinterStatus::Idle
+		} else {
+			new_status
+		};
+
+		// Apply the determined status with validation
+		let should_update_status = match (&previous_status, &new_status) {
+			// Allow any change to/from Error or Offline
+			(PrinterStatus::Error, _) | (_, PrinterStatus::Error) => true,
+			(PrinterStatus::Offline, _) | (_, PrinterStatus::Offline) => true,
+			(PrinterStatus::Connecting, _) | (_, PrinterStatus::Connecting) => true,
+
+			// Allow transitions from Idle to Printing if we have strong indicators
+			(PrinterStatus::Idle, PrinterStatus::Printing) => {
+				has_active_job || has_progress || print_real == 1 || (has_high_temps && has_active_fan)
+			}
+
+			// Layers don't un-print: a preparation stage mid-job (e.g. reheating after a
+			// filament change) is still printing
+			(PrinterStatus::Printing | PrinterStatus::Paused, PrinterStatus::Preparing) => {
+				info!("Status for {name}: Not returning to Preparing mid-print");
+				false
+			}
+
+			// Be more cautious about transitions from Printing to Idle
+			(PrinterStatus::Printing, PrinterStatus::Idle) => {
+				// Only allow if we have strong evidence that printing has stopped
+				let has_completion_indicators = mc_percent >= 100.0
+					|| (mc_remaining_time == 0 && layer_num == 0 && !finishing)
+					|| (!has_high_temps && !has_active_fan && !has_active_job);
+
+				if has_completion_indicators && mc_running_job {
+					info!("Status for {name}: Keeping Printing until the post-print cooldown ends (mc_print_stage=2)");
+					false
+				} else if has_completion_indicators {
+					info!(
+						"Status for {name}: Allowing transition from Printing to Idle (completion indicators)"
+					);
+					true
+				} else {
+					info!("Status for {name}: Preventing spurious transition from Printing to Idle (active indicators still present)");
+					false
+				}
+			}
+
+			// Allow other transitions
+			_ => true,
+		};
+
+		if should_update_status {
+			printer.status = new_status;
+		} else {
+			info!(
+				"Status for {name}: Keeping previous status {previous_status:?} (transition validation failed)"
+			);
+		}
+
+		let job_name = if !subtask_name.is_empty() && subtask_name != "undefined" {
+			subtask_name
+		} else {
+			"Unknown"
+		};
+
+		// Our own clock for the job, independent of the printer's estimates. A
+		// different name, or layers starting over, means a new job.
+		match printer.status {
+			PrinterStatus::Preparing | PrinterStatus::Printing | PrinterStatus::Paused => {
+				let previous_job = printer.print.as_ref();
+				let is_new_job = printer.print_start.is_none()
+					|| previous_job.is_some_and(|job| {
+						job.file_name != job_name
+							|| (layer_num <= 1 && i64::from(job.layer_current) > layer_num)
+					});
+				if is_new_job {
+					printer.print_start = Some(Utc::now());
+				}
+			}
+			PrinterStatus::Idle => printer.print_start = None,
+			// Keep the start time through errors and dropped connections
+			_ => {}
+		}
+
+		// Update print job info if printing/paused or if we have print data
+		if matches!(
+			printer.status,
+			PrinterStatus::Preparing | PrinterStatus::Printing | PrinterStatus::Paused
+		) || mc_remaining_time > 0
+			|| layer_num > 0
+			|| mc_percent > 0.0
+			|| print_real == 1
+		{
+			// Calculate estimated total time if we have progress and remaining time
+			let estimated_total_time = if mc_percent > 0.0 && mc_remaining_time > 0 {
+				let remaining_seconds = mc_remaining_time * 60;
+				Some((remaining_seconds as f64 / (1.0 - mc_percent / 100.0)).round() as i64)
+			} else {
+				None
+			};
+
+			let layer_current = layer_num as i32;
+			let layer_total = report.total_layer_num.unwrap_or(0) as i32;
+			let progress = estimate_progress(print_data);
+
+			let file_name = job_name.to_string();
+
+			printer.print = Some(PrintJob {
+				progress: progress.percent,
+				progress_source: progress.source,
+				time_remaining: if finishing {
+					SUB_MINUTE_REMAINING_SECS
+				} else {
+					mc_remaining_time * 60 // Convert minutes to seconds
+				},
+				estimated_total_time,
+				file_name,
+				gcode_file: non_empty(&report.gcode_file),
+				print_type: report.print_type.clone(),
+				layer_current,
+				layer_total,
+				speed_level: report.spd_lvl.map(|v| v as i32),
+				speed_magnitude: report.spd_mag.map(|v| v.clamp(30, 200) as i32),
+				fan_speed: report.fan_gear.map(|v| v as i32),
+				fan_speeds: FanSpeeds::from_print_data(print_data),
+				stage: report.stg_cur.map(|v| v as i32),
+				print_stage: mc_print_stage.map(|v| v as i32),
+				sub_stage: report.mc_print_sub_stage.map(|v| v as i32),
+				lifecycle: report.lifecycle.clone(),
+				// Only newer firmware reports these; zero means "not known"
+				filament_weight: report.weight.filter(|weight| *weight > 0.0),
+				filament_length: report.length.filter(|length| *length > 0.0),
+				elapsed_seconds: printer
+					.print_start
+					.map(|start| (Utc::now() - start).num_seconds().max(0)),
+				pause_reason: (printer.status == PrinterStatus::Paused)
+					.then(|| PrintJob::pause_reason(stg_cur, error_code))
+					.flatten()
+					.map(|reason| reason.to_string()),
+			});
+		} else {
+			printer.print = None;
+		}
+
+		// Check for errors
+		let (print_error, error_code) = error_key;
+		if (print_error > 0 || error_code > 0) && !error_acknowledged {
+			printer.status = PrinterStatus::Error;
+			printer.error = Some(PrinterError {
+				print_error,
+				error_code,
+				stage: stg_cur as i32,
+				lifecycle: report.lifecycle.as_deref().unwrap_or("Unknown").to_string(),
+				gcode_state: gcode_state.unwrap_or("Unknown").to_string(),
+				message: Self::get_error_message(print_error, error_code, &printer.hms),
+			});
+		} else {
+			printer.error = None;
+		}
+
+		printer.detection = DetectionStatus::from_report(printer, print_data);
+		printer.ai_detection_enabled = report
+			.xcam
+			.and_then(|xcam| xcam.spaghetti_detector)
+			.filter(|_| printer.capabilities.has_spaghetti_detection);
+
+		// A message means the connection is up, but the printer may still be unwell
+		let has_ams = !printer.ams.is_empty();
+		let was_degraded = printer
+			.device_links
+			.is_some_and(|links| links.degraded(has_ams));
+		printer.device_links = report.online.map(|online| DeviceLinks {
+			ahb: online.ahb,
+			rfid: online.rfid,
+		});
+		if printer
+			.device_links
+			.is_some_and(|links| links.degraded(has_ams))
+		{
+			printer.online = false;
+		} else if was_degraded {
+			printer.online = true;
+		}
+
+		StatusChange {
+			previous: previous_status,
+			current: printer.status.clone(),
+		}
+	}
+
+	async fn update_printer_status<F>(
+		printer_states: &Arc<PrinterStates>,
+		events: &Events,
+		printer_id: &str,
+		update_fn: F,
+	) where
+		F: FnOnce(&mut Printer),
+	{
+		let updated_printer = {
+			let mut states = printer_states.write().await;
+			if let Some(printer) = states.get_mut(printer_id) {
+				// Copies the printer, as the published snapshot still holds the old one
+				update_fn(Arc::make_mut(printer));
+				Some(Arc::clone(printer))
+			} else {
+				None
+			}
+		};
+
+		// Emit update to frontend, serializing outside the lock
+		if let Some(printer) = updated_printer {
+			if let Err(e) = events.emit("printer-update", printer.as_ref()) {
+				error!("Failed to emit printer update: {e}");
+			}
+		}
+	}
+
+	async fn emit_printer_update(&self, printer: &Printer) {
+		if let Err(e) = self.events.emit("printer-update", printer) {
+			error!("Failed to emit printer update: {e}");
+		}
+	}
+
+	// Deep merge new data into existing state
+	//
+	// Objects merge key by key. Arrays whose elements all carry an `id` field
+	// (e.g. `print.ams.ams` and each unit's `tray` list) merge element by
+	// element, so a partial AMS update doesn't drop the units it omits. A
+	// listed element replaces the one with its `id` outright: an emptied tray
+	// is sent as just `{"id": ...}`, and must lose its old filament. Any
+	// other array (e.g. `print.hms`) is replaced wholesale.
+	fn deep_merge(mut base: serde_json::Value, new: serde_json::Value) -> serde_json::Value {
+		match (&mut base, new) {
+			(serde_json::Value::Object(base_map), serde_json::Value::Object(new_map)) => {
+				for (key, value) in new_map {
+					match base_map.get_mut(&key) {
+						Some(existing) => {
+							// For critical status fields, preserve existing values if new values are empty/null
+							if (key == "subtask_name" || key == "gcode_file")
+								&& value.as_str().unwrap_or("").is_empty()
+							{
+								if let Some(existing_str) = existing.as_str() {
+									if !existing_str.is_empty()
+										&& existing_str != "Unknown"
+										&& existing_str != "undefined"
+									{
+										// Keep existing non-empty subtask_name
+										continue;
+									}
+								}
+							}
+
+							// For progress fields, don't overwrite with zero unless it's actually finished
+							if key == "mc_percent" && value.as_f64().unwrap_or(0.0) == 0.0 {
+								if let Some(existing_percent) = existing.as_f64() {
+									if existing_percent > 0.0 && existing_percent < 100.0 {
+										// Keep existing progress unless we have evidence the print is done
+										continue;
+									}
+								}
+							}
+
+							// For remaining time, don't overwrite with zero unless progress is 100%
+							if key == "mc_remaining_time" && value.as_i64().unwrap_or(0) == 0 {
+								if let Some(existing_time) = existing.as_i64() {
+									if existing_time > 0 {
+										// Keep existing remaining time if it's positive and new value is zero
+										// This prevents losing time data from partial updates
+										continue;
+									}
+								}
+							}
+
+							*existing = Self::deep_merge(existing.clone(), value);
+						}
+						None => {
+							base_map.insert(key, value);
+						}
+					}
+				}
+				base
+			}
+			(serde_json::Value::Array(base_items), serde_json::Value::Array(new_items))
+				if Self::is_keyed_array(&new_items) =>
+			{
+				for item in new_items {
+					let existing = base_items
+						.iter_mut()
+						.find(|existing| existing.get("id") == item.get("id"));
+					match existing {
+						Some(existing) => *existing = item,
+						None => base_items.push(item),
+					}
+				}
+				base
+			}
+			(_, new_value) => new_value,
+		}
+	}
+
+	fn is_keyed_array(items: &[serde_json::Value]) -> bool {
+		!items.is_empty() && items.iter().all(|item| item.get("id").is_some())
+	}
+
+	/// Decodes an MQTT payload into the JSON objects it carries. Some firmware
+	/// sends two objects back to back in one message, so each complete object
+	/// is returned in order; a truncated tail is dropped rather than taking
+	/// the valid objects before it down with it.
+	fn parse_payload(printer_name: &str, payload: &[u8]) -> Vec<serde_json::Value> {
+		let mut values = Vec::new();
+		for value in serde_json::Deserializer::from_slice(payload).into_iter::<serde_json::Value>() {
+			match value {
+				Ok(value) if value.is_object() => values.push(value),
+				Ok(value) => warn!("Ignoring non-object MQTT message from {printer_name}: {value}"),
+				Err(e) => {
+					error!("Failed to parse MQTT message from {printer_name}: {e}");
+					debug!(
+						"Malformed payload from {printer_name}: {}",
+						String::from_utf8_lossy(payload)
+					);
+					break;
+				}
+			}
+		}
+		values
+	}
+
+	/// Only the main report topic may update `print` state. Other topics (e.g.
+	/// the info topic) can echo partial `print` data, which is dropped so it
+	/// doesn't clobber what the reports established.
+	fn scope_to_topic(
+		config: &PrinterConfig,
+		topic: &str,
+		mut data: serde_json::Value,
+	) -> serde_json::Value {
+		if topic != config.report_topic() {
+			if let Some(root) = data.as_object_mut() {
+				root.remove("print");
+			}
+		}
+		data
+	}
+
+	/// Merges a report into the accumulated state and prunes the result down to
+	/// `print` and its `RETAINED_PRINT_KEYS`.
+	///
+	/// A full report (`msg` 0, e.g. the answer to `pushall`) lists every AMS
+	/// unit, so units it leaves out have been detached and are dropped.
+	fn merge_report(base: serde_json::Value, new: serde_json::Value) -> serde_json::Value {
+		let full_ams = (new.pointer("/print/msg").and_then(|msg| msg.as_i64()) == Some(0))
+			.then(|| new.pointer("/print/ams/ams").cloned())
+			.flatten()
+			.filter(|units| units.is_array());
+		let mut merged = Self::deep_merge(base, new);
+		if let (Some(units), Some(merged_units)) = (full_ams, merged.pointer_mut("/print/ams/ams")) {
+			*merged_units = units;
+		}
+		if let Some(root) = merged.as_object_mut() {
+			root.retain(|key, _| RETAINED_TOP_LEVEL_KEYS.contains(&key.as_str()));
+			if let Some(print) = root.get_mut("print").and_then(|p| p.as_object_mut()) {
+				print.retain(|key, _| RETAINED_PRINT_KEYS.contains(&key.as_str()));
+			}
+		}
+		merged
+	}
+
+	/// Prefers the specific `mc_print_error_code` messages, then the first
+	/// catalogued HMS description, then a generic message.
+	fn get_error_message(print_error: i32, error_code: i32, hms: &[HmsCode]) -> String {
+		let hms_message = hms.iter().find_map(|entry| entry.message.as_deref());
+		match (print_error, error_code, hms_message) {
+			(_, 1203, _) => "Filament runout detected".to_string(),
+			(_, 1204, _) => "Filament tangle detected".to_string(),
+			(_, 1205, _) => "Nozzle clog detected".to_string(),
+			(_, _, Some(message)) => message.to_string(),
+			(1, _, _) => "Print error occurred".to_string(),
+			(2, _, _) => "Bed adhesion failure".to_string(),
+			(3, _, _) => "Temperature error".to_string(),
+			_ => format!("Error: print_error={print_error}, error_code={error_code}"),
+		}
+	}
+
+	pub async fn send_command(&self, printer_id: &str, command: PrintCommand) -> Result<()> {
+		self.command_queues.push(QueuedCommand {
+			printer_id: printer_id.to_string(),
+			command,
+			reply: None,
+		});
+		Ok(())
+	}
+
+	/// Checks that `command` would be accepted without sending it, so a UI can
+	/// grey out actions: the command is well formed, the printer is in a state
+	/// to take it (see `check_command`) and is connected. Sending a raw command
+	/// skips the state check, since the reported status can lag behind the
+	/// printer.
+	pub async fn validate_command(&self, printer_id: &str, command: &PrintCommand) -> Result<()> {
+		Self::build_command_payload(command, "0")?;
+
+		let states = self.printer_states.read().await;
+		let printer = states
+			.get(printer_id)
+			.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+		Self::check_command(printer, command)?;
+		if !self
+			.printer_connections
+			.read()
+			.await
+			.contains_key(printer_id)
+		{
+			return Err(anyhow!(
+				"No MQTT connection for printer {printer_id} ({:?})",
+				printer.connection_state
+			));
+		}
+
+		Ok(())
+	}
+
+	/// Actions of the commands queued for `printer_id` that haven't been sent
+	/// yet, oldest first. The command being published right now isn't included.
+	pub fn get_pending_commands(&self, printer_id: &str) -> Vec<String> {
+		self.command_queues.pending(printer_id)
+	}
+
+	/// Drops the commands queued for `printer_id` that haven't been sent yet,
+	/// e.g. when the user changes their mind before a slow printer caught up.
+	/// Returns how many were dropped.
+	pub fn cancel_pending_commands(&self, printer_id: &str) -> usize {
+		self.command_queues.cancel(printer_id)
+	}
+
+	/// Like `send_command`, but waits until the command has actually been
+	/// published and returns the publish result (the command's `sequence_id`).
+	pub async fn send_command_confirmed(
+		&self,
+		printer_id: &str,
+		command: PrintCommand,
+	) -> Result<String> {
+		let (reply, outcome) = oneshot::channel();
+		self.command_queues.push(QueuedCommand {
+			printer_id: printer_id.to_string(),
+			command,
+			reply: Some(reply),
+		});
+
+		outcome
+			.await
+			.map_err(|_| anyhow!("Command handler stopped before sending the command"))?
+	}
+
+	/// Like `send_command_confirmed`, but also waits for the printer to echo
+	/// the command back, failing if it reports `"failed"` or never answers.
+	pub async fn send_command_acknowledged(
+		&self,
+		printer_id: &str,
+		command: PrintCommand,
+	) -> Result<()> {
+		// Subscribe first so a fast answer can't slip past
+		let mut responses = self.command_responses.subscribe();
+		let action = command.action.clone();
+		let sequence_id = self.send_command_confirmed(printer_id, command).await?;
+
+		let response = tokio::time::timeout(COMMAND_ACK_TIMEOUT, async {
+			loop {
+				match responses.recv().await {
+					Ok(response)
+						if response.printer_id == printer_id && response.sequence_id == sequence_id =>
+					{
+						return Ok(response);
+					}
+					Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+					Err(broadcast::error::RecvError::Closed) => {
+						return Err(anyhow!("Response channel closed"));
+					}
+				}
+			}
+		})
+		.await
+		.map_err(|_| {
+			anyhow!(
+				"Printer {printer_id} did not acknowledge '{action}' within {}s",
+				COMMAND_ACK_TIMEOUT.as_secs()
+			)
+		})??;
+
+		if response.is_failure() {
+			return Err(anyhow!(
+				"Printer {printer_id} rejected '{action}': {}",
+				response.reason.as_deref().unwrap_or("no reason given")
+			));
+		}
+
+		Ok(())
+	}
+
+	/// Sends `action` to every printer currently in one of `statuses`.
+	///
+	/// Commands are dispatched concurrently so one slow printer doesn't hold
+	/// up the rest; the returned list reports whether each printer's command
+	/// was actually published.
+	pub async fn send_command_to_all(
+		&self,
+		action: &str,
+		statuses: &[PrinterStatus],
+	) -> Vec<BatchCommandResult> {
+		let targets: Vec<String> = {
+			let states = self.printer_states.read().await;
+			states
+				.values()
+				.filter(|printer| statuses.contains(&printer.status))
+				.map(|printer| printer.id.clone())
+				.collect()
+		};
+
+		info!("Sending '{action}' to {} printer(s)", targets.len());
+
+		let mut tasks = JoinSet::new();
+		for printer_id in targets {
+			let service = self.clone();
+			let command = PrintCommand::new(action);
+			tasks.spawn(async move {
+				let result = service.send_command_confirmed(&printer_id, command).await;
+				(printer_id, result)
+			});
+		}
+
+		let mut results = Vec::new();
+		while let Some(joined) = tasks.join_next().await {
+			match joined {
+				Ok((printer_id, result)) => results.push(BatchCommandResult {
+					printer_id,
+					success: result.is_ok(),
+					error: result.err().map(|e| e.to_string()),
+				}),
+				Err(e) => error!("Batch '{action}' task failed: {e}"),
+			}
+		}
+		results.sort_by(|a, b| a.printer_id.cmp(&b.printer_id));
+		results
+	}
+
+	/// Dismisses the printer's current error in the UI. Nothing is sent to
+	/// the printer, and a different error in a later report shows as usual.
+	pub async fn clear_error(&self, printer_id: &str) -> Result<()> {
+		if !self.printer_states.read().await.contains_key(printer_id) {
+			return Err(anyhow!("Printer {printer_id} not found"));
+		}
+		let accumulated = self
+			.printer_mqtt_states
+			.read()
+			.await
+			.get(printer_id)
+			.cloned();
+
+		Self::update_printer_status(&self.printer_states, &self.events, printer_id, |printer| {
+			printer.acknowledged_error = Some(
+				printer
+					.error
+					.as_ref()
+					.map_or((0, 0), |error| (error.print_error, error.error_code)),
+			);
+			// Re-derive the status as if the error had never been reported
+			match &accumulated {
+				Some(accumulated) => {
+					Self::apply_message(printer, accumulated);
+				}
+				None => printer.error = None,
+			}
+			printer.last_update = Utc::now();
+		})
+		.await;
+
+		Ok(())
+	}
+
+	/// Serializes every stored config as a JSON array, sorted by name. Access
+	/// codes are blanked unless `include_access_codes` is set, so the result
+	/// can be shared without leaking credentials.
+	pub async fn export_printers(&self, include_access_codes: bool) -> Result<String> {
+		let configs: Vec<PrinterConfig> = self
+			.printer_configs
+			.read()
+			.await
+			.values()
+			.cloned()
+			.collect();
+		Self::export_configs(configs, include_access_codes)
+	}
+
+	fn export_configs(mut configs: Vec<PrinterConfig>, include_access_codes: bool) -> Result<String> {
+		configs.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+		if !include_access_codes {
+			for config in &mut configs {
+				config.access_code.clear();
+			}
+		}
+		Ok(serde_json::to_string_pretty(&configs)?)
+	}
+
+	/// Adds every printer in a JSON array produced by `export_printers`.
+	/// Entries that fail validation (e.g. a redacted access code) or match an
+	/// existing printer are skipped and reported rather than aborting the rest.
+	pub async fn import_printers(&self, json: &str) -> Result<Vec<PrinterImportResult>> {
+		let configs: Vec<PrinterConfig> =
+			serde_json::from_str(json).map_err(|e| anyhow!("Invalid printer list: {}", e))?;
+
+		let mut results = Vec::with_capacity(configs.len());
+		for config in configs {
+			let name = config.name.clone();
+			let serial = config.serial.clone();
+			let outcome = if self.printer_configs.read().await.contains_key(&config.id) {
+				Err(anyhow!("printer '{}' is already added", config.name))
+			} else {
+				self.add_printer(config, false).await
+			};
+
+			if let Err(e) = &outcome {
+				warn!("Skipping imported printer {}: {}", name, e);
+			}
+			results.push(PrinterImportResult {
+				name,
+				serial,
+				added: outcome.is_ok(),
+				error: outcome.err().map(|e| e.to_string()),
+			});
+		}
+
+		Ok(results)
+	}
+
+	/// Recovers from a paused error such as a filament runout in one step:
+	/// dismisses the error, resumes once the printer acknowledges it, then
+	/// checks the printer hasn't raised an error again straight away.
+	pub async fn resume_after_error(&self, printer_id: &str) -> Result<()> {
+		self.clear_error(printer_id).await?;
+		self
+			.send_command_acknowledged(printer_id, PrintCommand::new("resume"))
+			.await?;
+
+		tokio::time::sleep(RESUME_ERROR_WINDOW).await;
+
+		let error_key = {
+			let mqtt_states = self.printer_mqtt_states.read().await;
+			let print_data = mqtt_states
+				.get(printer_id)
+				.and_then(|state| state.get("print"));
+			let code = |key: &str| {
+				print_data
+					.and_then(|print| print.get(key))
+					.and_then(|v| v.as_i64())
+					.unwrap_or(0) as i32
+			};
+			(code("print_error"), code("mc_print_error_code"))
+		};
+		if error_key == (0, 0) {
+			return Ok(());
+		}
+
+		// Still failing: stop hiding the error the user just dismissed
+		let accumulated = self
+			.printer_mqtt_states
+			.read()
+			.await
+			.get(printer_id)
+			.cloned();
+		let mut message = None;
+		Self::update_printer_status(&self.printer_states, &self.events, printer_id, |printer| {
+			printer.acknowledged_error = None;
+			if let Some(accumulated) = &accumulated {
+				Self::apply_message(printer, accumulated);
+			}
+			message = printer.error.as_ref().map(|error| error.message.clone());
+			printer.last_update = Utc::now();
+		})
+		.await;
+
+		Err(anyhow!(
+			"Printer {printer_id} reported an error again after resuming: {}",
+			message.unwrap_or_else(|| Self::get_error_message(error_key.0, error_key.1, &[]))
+		))
+	}
+
+	/// Returns the current job's plate preview as PNG bytes, pulled over FTPS
+	/// from the project file on the printer. Cached per job, so only the
+	/// first call for a print downloads anything.
+	pub async fn get_print_thumbnail(&self, printer_id: &str) -> Result<Vec<u8>> {
+		let config = {
+			let configs = self.printer_configs.read().await;
+			configs
+				.get(printer_id)
+				.cloned()
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?
+		};
+
+		let (gcode_file, subtask_name) = {
+			let mqtt_states = self.printer_mqtt_states.read().await;
+			let print_data = mqtt_states
+				.get(printer_id)
+				.and_then(|state| state.get("print"));
+			let text = |key: &str| {
+				print_data
+					.and_then(|print| print.get(key))
+					.and_then(|v| v.as_str())
+					.unwrap_or("")
+					.to_string()
+			};
+			(text("gcode_file"), text("subtask_name"))
+		};
+		if gcode_file.is_empty() && subtask_name.is_empty() {
+			return Err(anyhow!("{} has no current print job", config.name));
+		}
+
+		let job = format!("{gcode_file}|{subtask_name}");
+		if let Some(cached) = self.thumbnail_cache.read().await.get(printer_id) {
+			if cached.job == job {
+				return Ok(cached.image.clone());
+			}
+		}
+
+		let image = tokio::time::timeout(
+			THUMBNAIL_FETCH_TIMEOUT,
+			Self::fetch_thumbnail(&config, &gcode_file, &subtask_name),
+		)
+		.await
+		.map_err(|_| anyhow!("Timed out fetching the thumbnail from {}", config.name))??;
+
+		self.thumbnail_cache.write().await.insert(
+			printer_id.to_string(),
+			CachedThumbnail {
+				job,
+				image: image.clone(),
+			},
+		);
+		Ok(image)
+	}
+
+	async fn fetch_thumbnail(
+		config: &PrinterConfig,
+		gcode_file: &str,
+		subtask_name: &str,
+	) -> Result<Vec<u8>> {
+		let tls_config = Arc::new(Self::build_tls_config(&config.tls_mode)?);
+		let mut client = FtpsClient::connect(config.host(), &config.access_code, tls_config).await?;
+
+		// Where the project lives depends on how the job was started, so try each spot
+		let mut project = Err(anyhow!("No project file to look for"));
+		for path in thumbnail::candidate_paths(gcode_file, subtask_name) {
+			project = client.retrieve(&path).await;
+			match &project {
+				Ok(_) => break,
+				Err(e) => debug!("No project at {} on {}: {}", path, config.name, e),
+			}
+		}
+		client.quit().await;
+
+		thumbnail::extract_file(&project?, &thumbnail::plate_image_path(gcode_file))
+	}
+
+	/// Lists the `.3mf` and `.gcode` files on the printer's storage, newest
+	/// first, for picking one to hand to `start_print`.
+	pub async fn list_printer_files(&self, printer_id: &str) -> Result<Vec<PrinterFile>> {
+		let config = {
+			let configs = self.printer_configs.read().await;
+			configs
+				.get(printer_id)
+				.cloned()
+				.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?
+		};
+
+		tokio::time::timeout(FILE_LIST_TIMEOUT, Self::fetch_file_list(&config))
+			.await
+			.map_err(|_| anyhow!("Timed out listing the files on {}", config.name))?
+	}
+
+	async fn fetch_file_list(config: &PrinterConfig) -> Result<Vec<PrinterFile>> {
+		let tls_config = Arc::new(Self::build_tls_config(&config.tls_mode)?);
+		let mut client = FtpsClient::connect(config.host(), &config.access_code, tls_config).await?;
+
+		let mut files = Vec::new();
+		for dir in PRINTER_FILE_DIRS {
+			match client.list(dir).await {
+				Ok(listing) => files.extend(listing),
+				// Nothing has been sent from Bambu Studio yet
+				Err(e) if dir != "/" => debug!("No {} on {}: {}", dir, config.name, e),
+				Err(e) => return Err(e),
+			}
+		}
+		client.quit().await;
+
+		files.retain(|file| {
+			let name = file.name.to_ascii_lowercase();
+			name.ends_with(".3mf") || name.ends_with(".gcode")
+		});
+		files.sort_by_key(|file| std::cmp::Reverse(file.modified));
+		Ok(files)
+	}
+
+	/// Starts appending every raw message from the printer to `path`, one JSON
+	/// record per line, for reproducing status problems with `replay_capture`.
+	pub async fn start_capture(&self, printer_id: &str, path: &Path) -> Result<()> {
+		if !self.printer_configs.read().await.contains_key(printer_id) {
+			return Err(anyhow!("Printer {printer_id} not found"));
+		}
+		self.captures.start(printer_id, path)
+	}
+
+	pub fn stop_capture(&self, printer_id: &str) -> Result<()> {
+		if !self.captures.stop(printer_id) {
+			return Err(anyhow!("Printer {printer_id} is not being captured"));
+		}
+		Ok(())
+	}
+
+	/// Feeds a file written by `start_capture` through the same handling as
+	/// live messages, into `printer_id` or else the printer whose serial the
+	/// captured topics name. Returns the number of messages replayed.
+	pub async fn replay_capture(&self, path: &Path, printer_id: Option<&str>) -> Result<usize> {
+		let records = Captures::read(path)?;
+		let config = {
+			let configs = self.printer_configs.read().await;
+			match printer_id {
+				Some(printer_id) => configs
+					.get(printer_id)
+					.cloned()
+					.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?,
+				None => {
+					let serial = records
+						.iter()
+						.find_map(|record| record.topic.strip_prefix("device/")?.split('/').next())
+						.ok_or_else(|| anyhow!("{} has no printer messages", path.display()))?;
+					configs
+						.values()
+						.find(|config| same_serial(&config.serial, serial))
+						.cloned()
+						.ok_or_else(|| anyhow!("No printer with serial {serial} to replay into"))?
+				}
+			}
+		};
+
+		// Captured reports may come from another printer's serial
+		let report_topic = config.report_topic();
+		for record in &records {
+			let topic = if record.topic.ends_with("/report") {
+				&report_topic
+			} else {
+				&record.topic
+			};
+			for data in Self::parse_payload(&config.name, &record.payload_bytes()) {
+				Self::handle_printer_message(
+					&self.printer_states,
+					&self.printer_mqtt_states,
+					&self.command_responses,
+					&self.temperature_waiters,
+					&self.events,
+					&config,
+					topic,
+					data,
+				)
+				.await;
+			}
+		}
+
+		info!(
+			"Replayed {} message(s) from {} into {}",
+			records.len(),
+			path.display(),
+			config.name
+		);
+		Ok(records.len())
+	}
+
+	/// Removes every printer that has been offline for longer than
+	/// `older_than_secs`, returning the ids of those removed. One that can't
+	/// be removed is logged and skipped.
+	pub async fn prune_offline_printers(&self, older_than_secs: u64) -> Result<Vec<String>> {
+		// Too long ago to represent means nothing can have been offline that long
+		let Some(cutoff) = i64::try_from(older_than_secs)
+			.ok()
+			.and_then(chrono::Duration::try_seconds)
+			.and_then(|age| Utc::now().checked_sub_signed(age))
+		else {
+			return Ok(Vec::new());
+		};
+		let stale: Vec<String> = {
+			let states = self.printer_states.read().await;
+			states
+				.values()
+				.filter(|printer| printer.is_offline_since(cutoff))
+				.map(|printer| printer.id.clone())
+				.collect()
+		};
+
+		let mut removed = Vec::with_capacity(stale.len());
+		for printer_id in stale {
+			// One that fails to go shouldn't keep the rest around
+			match self.remove_printer(&printer_id).await {
+				Ok(()) => removed.push(printer_id),
+				Err(e) => warn!("Failed to prune offline printer {printer_id}: {e}"),
+			}
+		}
+
+		info!("Pruned {} offline printer(s)", removed.len());
+		Ok(removed)
+	}
+
+	pub async fn get_service_summary(&self) -> ServiceSummary {
+		let states = self.printer_states.read().await;
+		ServiceSummary::from_printers(states.values().map(|printer| printer.as_ref()))
+	}
+
+	/// Like `get_all_printers`, but only the printers matching `filter`.
+	pub async fn query_printers(&self, filter: &PrinterFilter) -> Vec<Printer> {
+		self
+			.printer_states
+			.snapshot()
+			.values()
+			.filter(|printer| filter.matches(printer))
+			.map(|printer| printer.as_ref().clone())
+			.collect()
+	}
+
+	pub async fn get_all_printers(&self) -> Vec<Printer> {
+		self
+			.printer_states
+			.snapshot()
+			.values()
+			.map(|printer| printer.as_ref().clone())
+			.collect()
+	}
+
+	/// Printers changed since `version`, for frontends that poll instead of
+	/// listening for events, along with the version to ask for next time.
+	/// Version 0 returns every printer. Removed printers aren't reported;
+	/// `printer-removed` announces those.
+	pub async fn get_printers_since(&self, version: u64) -> (u64, Vec<Printer>) {
+		let (version, printers) = self.printer_states.since(version);
+		(
+			version,
+			printers
+				.iter()
+				.map(|printer| printer.as_ref().clone())
+				.collect(),
+		)
+	}
+
+	/// Progress for the printer's current report and which field it came from.
+	pub async fn get_print_progress_estimate(&self, printer_id: &str) -> Result<ProgressEstimate> {
+		if !self.printer_states.read().await.contains_key(printer_id) {
+			return Err(anyhow!("Printer {printer_id} not found"));
+		}
+
+		let mqtt_states = self.printer_mqtt_states.read().await;
+		let print_data = mqtt_states
+			.get(printer_id)
+			.and_then(|state| state.get("print"))
+			.cloned()
+			.unwrap_or_default();
+		Ok(estimate_progress(&print_data))
+	}
+
+	/// Returns the accumulated MQTT state for a printer, exactly as merged
+	/// from its reports. Empty if no message has been received yet.
+	pub async fn get_raw_state(&self, printer_id: &str) -> Result<serde_json::Value> {
+		if !self.printer_states.read().await.contains_key(printer_id) {
+			return Err(anyhow!("Printer {printer_id} not found"));
+		}
+
+		let mqtt_states = self.printer_mqtt_states.read().await;
+		Ok(
+			mqtt_states
+				.get(printer_id)
+				.cloned()
+				.unwrap_or_else(|| serde_json::Value::Object(serde_json::Map::new())),
+		)
+	}
+
+	pub async fn get_connection_metrics(&self, printer_id: &str) -> Result<ConnectionMetrics> {
+		if !self.printer_states.read().await.contains_key(printer_id) {
+			return Err(anyhow!("Printer {printer_id} not found"));
+		}
+
+		let metrics = self.connection_metrics.read().await;
+		Ok(metrics.get(printer_id).cloned().unwrap_or_default())
+	}
+
+	/// Bundles the config, raw and parsed state, connection metrics and
+	/// recent commands for one printer into a JSON bug report.
+	pub async fn export_debug_bundle(&self, printer_id: &str) -> Result<String> {
+		let config = self
+			.printer_configs
+			.read()
+			.await
+			.get(printer_id)
+			.cloned()
+			.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+		let printer = self
+			.printer_states
+			.read()
+			.await
+			.get(printer_id)
+			.map(|printer| printer.as_ref().clone())
+			.ok_or_else(|| anyhow!("Printer {printer_id} not found"))?;
+
+		let command_history = match self.events.command_history() {
+			Some(history) => history
+				.recent(printer_id, DEBUG_BUNDLE_COMMAND_LIMIT)
+				.await
+				.unwrap_or_else(|e| {
+					warn!("Failed to read command history for debug bundle of {printer_id}: {e}");
+					Vec::new()
+				}),
+			None => Vec::new(),
+		};
+
+		let access_code = config.access_code.clone();
+		let bundle = DebugBundle {
+			app_version: env!("CARGO_PKG_VERSION").to_string(),
+			created_at: Utc::now(),
+			raw_state: self.get_raw_state(printer_id).await?,
+			metrics: self.get_connection_metrics(printer_id).await?,
+			config,
+			printer,
+			command_history,
+		};
+		bundle.into_redacted_json(&access_code)
+	}
+
+	pub async fn get_diagnostics(&self) -> Diagnostics {
+		let mut printer_diagnostics: Vec<PrinterDiagnostics> = {
+			let states = self.printer_states.read().await;
+			let metrics = self.connection_metrics.read().await;
+			states
+				.values()
+				.map(|printer| PrinterDiagnostics {
+					id: printer.id.clone(),
+					name: printer.name.clone(),
+					model: printer.model.clone(),
+					firmware_version: printer.firmware_version.clone(),
+					connection_state: printer.connection_state,
+					metrics: metrics.get(&printer.id).cloned().unwrap_or_default(),
+				})
+				.collect()
+		};
+		printer_diagnostics.sort_by(|a, b| a.name.cmp(&b.name));
+
+		let running_connection_tasks = self
+			.connection_tasks
+			.read()
+			.await
+			.values()
+			.filter(|task| !task.handle.inner().is_finished())
+			.count();
+
+		Diagnostics {
+			app_version: env!("CARGO_PKG_VERSION").to_string(),
+			tls_provider: CRYPTO_PROVIDER_NAME.to_string(),
+			printers: printer_diagnostics.len(),
+			connections: self.printer_connections.read().await.len(),
+			running_connection_tasks,
+			pending_acknowledgments: self.command_responses.receiver_count(),
+			cached_thumbnails: self.thumbnail_cache.read().await.len(),
+			printer_diagnostics,
+		}
+	}
+
+	/// Hands over the states saved by the previous session; each is applied
+	/// when its printer is added.
+	pub async fn restore_last_known(&self, states: Vec<PrinterState>) {
+		let mut last_known = self.last_known_states.write().await;
+		for state in states {
+			last_known.insert(state.printer_id.clone(), state);
+		}
+	}
+
+	pub async fn remove_printer(&self, printer_id: &str) -> Result<()> {
+		// Nothing left to send them to
+		self.command_queues.cancel(printer_id);
+
+		// Remove from states
+		{
+			let mut states = self.printer_states.write().await;
+			states.remove(printer_id);
+		}
+
+		// Remove from MQTT states
+		{
+			let mut mqtt_states = self.printer_mqtt_states.write().await;
+			mqtt_states.remove(printer_id);
+		}
+
+		// Remove from configs
+		{
+			let mut configs = self.printer_configs.write().await;
+			configs.remove(printer_id);
+		}
+
+		// Stop the MQTT task and remove from connection pool
+		self.stop_connection(printer_id).await;
+
+		{
+			let mut metrics = self.connection_metrics.write().await;
+			metrics.remove(printer_id);
+		}
+		self.thumbnail_cache.write().await.remove(printer_id);
+		self.last_refresh.write().await.remove(printer_id);
+		self.captures.stop(printer_id);
+		// Dropping the senders fails any `set_temperature_and_wait` still waiting
+		self.temperature_waiters.write().await.remove(printer_id);
+
+		if let Some(store) = self.events.state_store() {
+			if let Err(e) = store.delete(printer_id).await {
+				error!("Failed to delete saved state for printer {printer_id}: {e}");
+			}
+		}
+
+		// Emit removal to frontend
+		if let Err(e) = self.events.emit("printer-removed", printer_id) {
+			error!("Failed to emit printer removal: {e}");
+		}
+
+		info!("Removed printer: {printer_id}");
+		Ok(())
+	}
+
+	#[allow(clippy::too_many_arguments)]
 	fn determine_status_from_indicators(
 		name: &str,
 		has_active_job: bool,
@@ -957,3 +5853,3009 @@ impl MqttService {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_broker::{RecordedEvents, TestBroker};
+
+	fn test_printer() -> Printer {
+		Printer::new(&test_config())
+	}
+
+	fn test_config() -> PrinterConfig {
+		PrinterConfig {
+			id: "test-printer".to_string(),
+			name: "Test Printer".to_string(),
+			model: "P1P".to_string(),
+			ip: "192.168.1.50".to_string(),
+			access_code: "12345678".to_string(),
+			serial: "01S00A000000000".to_string(),
+			port: None,
+			tls_mode: TlsMode::InsecureSkipVerify,
+			poll_interval_secs: None,
+			keep_alive_secs: None,
+			max_reconnect_attempts: None,
+			extra_topics: Vec::new(),
+			connection_mode: ConnectionMode::Mqtt,
+			username: None,
+			notifications_enabled: true,
+			temperature_thresholds: TemperatureThresholds::default(),
+			tags: Vec::new(),
+			location: None,
+		}
+	}
+
+	fn fixture(json: &str) -> serde_json::Value {
+		serde_json::from_str(json).expect("fixture should be valid JSON")
+	}
+
+	struct ReportCase {
+		name: &'static str,
+		payload: &'static str,
+		status: PrinterStatus,
+		progress: Option<f64>,
+		temperatures: (f64, f64, f64),
+	}
+
+	#[test]
+	fn test_apply_message_recorded_reports() {
+		let cases = [
+			ReportCase {
+				name: "P1P idle",
+				payload: include_str!("../tests/fixtures/p1p_idle.json"),
+				status: PrinterStatus::Idle,
+				progress: None,
+				temperatures: (26.1, 24.5, 5.0),
+			},
+			ReportCase {
+				name: "P1P printing",
+				payload: include_str!("../tests/fixtures/p1p_printing.json"),
+				status: PrinterStatus::Printing,
+				progress: Some(42.0),
+				temperatures: (219.8, 55.0, 5.0),
+			},
+			ReportCase {
+				name: "X1C printing",
+				payload: include_str!("../tests/fixtures/x1c_printing.json"),
+				status: PrinterStatus::Printing,
+				progress: Some(87.0),
+				temperatures: (249.6, 100.1, 41.0),
+			},
+			ReportCase {
+				name: "X1C paused",
+				payload: include_str!("../tests/fixtures/x1c_paused.json"),
+				status: PrinterStatus::Paused,
+				progress: Some(63.0),
+				temperatures: (248.9, 99.8, 40.0),
+			},
+			ReportCase {
+				name: "P1P filament runout",
+				payload: include_str!("../tests/fixtures/p1p_filament_runout.json"),
+				status: PrinterStatus::Error,
+				progress: Some(31.0),
+				temperatures: (218.0, 60.0, 5.0),
+			},
+		];
+
+		for case in cases {
+			let mut printer = test_printer();
+			let change = MqttService::apply_message(&mut printer, &fixture(case.payload));
+
+			assert_eq!(printer.status, case.status, "{}: status", case.name);
+			assert_eq!(change.current, case.status, "{}: status change", case.name);
+			assert_eq!(
+				printer.print.as_ref().map(|job| job.progress),
+				case.progress,
+				"{}: progress",
+				case.name
+			);
+			let temperatures = &printer.temperatures;
+			assert_eq!(
+				(temperatures.nozzle, temperatures.bed, temperatures.chamber),
+				case.temperatures,
+				"{}: temperatures",
+				case.name
+			);
+		}
+	}
+
+	#[test]
+	fn test_apply_message_reports_status_change() {
+		let mut printer = test_printer();
+		printer.status = PrinterStatus::Idle;
+
+		let change = MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/p1p_printing.json")),
+		);
+		assert_eq!(change.previous, PrinterStatus::Idle);
+		assert_eq!(change.current, PrinterStatus::Printing);
+		assert!(change.changed());
+
+		let change = MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/p1p_printing.json")),
+		);
+		assert!(!change.changed());
+	}
+
+	#[test]
+	fn test_apply_message_populates_print_job() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/p1p_printing.json")),
+		);
+
+		let job = printer.print.expect("printing report should produce a job");
+		assert_eq!(job.file_name, "benchy");
+		assert_eq!(job.layer_current, 84);
+		assert_eq!(job.layer_total, 200);
+		assert_eq!(job.time_remaining, 73 * 60);
+		assert_eq!(job.speed_level, Some(2));
+		assert_eq!(job.speed_magnitude, Some(100));
+		// The P1P capture predates filament usage reporting
+		assert_eq!(job.filament_weight, None);
+		assert_eq!(job.filament_length, None);
+	}
+
+	#[test]
+	fn test_final_minute_does_not_end_print() {
+		let mut printer = test_printer();
+		printer.status = PrinterStatus::Printing;
+
+		// No gcode_state, so status falls back to the other indicators
+		let change = MqttService::apply_message(
+			&mut printer,
+			&serde_json::json!({ "print": {
+				"mc_percent": 98,
+				"mc_remaining_time": 0,
+				"layer_num": 0,
+				"nozzle_temper": 220.0,
+				"bed_temper": 60.0,
+				"fan_gear": 0
+			} }),
+		);
+
+		assert!(!change.changed());
+		assert_eq!(printer.status, PrinterStatus::Printing);
+		let job = printer.print.expect("job should still be reported");
+		assert_eq!(job.time_remaining, SUB_MINUTE_REMAINING_SECS);
+		assert_eq!(job.progress, 98.0);
+	}
+
+	#[test]
+	fn test_preparing_until_layers_advance() {
+		let report = |gcode_state: &str, stg_cur: i64, layer_num: i64| {
+			serde_json::json!({ "print": {
+				"gcode_state": gcode_state,
+				"stg_cur": stg_cur,
+				"layer_num": layer_num,
+				"mc_percent": 0,
+				"mc_remaining_time": 45,
+				"subtask_name": "benchy"
+			} })
+		};
+
+		let mut printer = test_printer();
+		printer.status = PrinterStatus::Idle;
+		for (gcode_state, stg_cur) in [
+			("SLICING", 0),
+			("PREPARE", 0),
+			("RUNNING", 2),
+			("RUNNING", 1),
+		] {
+			let change = MqttService::apply_message(&mut printer, &report(gcode_state, stg_cur, 0));
+			assert_eq!(
+				printer.status,
+				PrinterStatus::Preparing,
+				"{gcode_state} at stage {stg_cur}"
+			);
+			assert_eq!(change.previous != change.current, gcode_state == "SLICING");
+		}
+		assert!(printer.print_start.is_some());
+
+		// The first layer starts the print proper
+		MqttService::apply_message(&mut printer, &report("RUNNING", 0, 1));
+		assert_eq!(printer.status, PrinterStatus::Printing);
+
+		// A preparation stage mid-print doesn't send it back
+		MqttService::apply_message(&mut printer, &report("RUNNING", 7, 0));
+		assert_eq!(printer.status, PrinterStatus::Printing);
+
+		// print_real means material is going down whatever the stage says
+		let mut printer = test_printer();
+		let mut started = report("PREPARE", 2, 0);
+		started["print"]["print_real"] = serde_json::json!(1);
+		MqttService::apply_message(&mut printer, &started);
+		assert_eq!(printer.status, PrinterStatus::Printing);
+	}
+
+	#[test]
+	fn test_post_print_cooldown_stays_printing() {
+		let mut printer = test_printer();
+		let mut state = MqttService::merge_report(
+			serde_json::json!({}),
+			fixture(include_str!("../tests/fixtures/p1p_printing.json")),
+		);
+		MqttService::apply_message(&mut printer, &state);
+		assert_eq!(printer.status, PrinterStatus::Printing);
+
+		let sequence = fixture(include_str!("../tests/fixtures/p1p_end_of_print.json"));
+		let mut statuses = Vec::new();
+		let mut sub_stages = Vec::new();
+		for report in sequence.as_array().unwrap() {
+			state = MqttService::merge_report(state, report.clone());
+			MqttService::apply_message(&mut printer, &state);
+			statuses.push(printer.status.clone());
+			sub_stages.push(printer.print.as_ref().and_then(|job| job.sub_stage));
+		}
+
+		// 100% and FINISH, but the printer is still cooling down until mc_print_stage drops to 1
+		assert_eq!(
+			statuses,
+			vec![
+				PrinterStatus::Printing,
+				PrinterStatus::Printing,
+				PrinterStatus::Idle
+			]
+		);
+		assert_eq!(sub_stages, vec![Some(0), Some(2), Some(0)]);
+	}
+
+	#[test]
+	fn test_layer_change_only_on_increment() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/p1p_printing.json")),
+		);
+		let job = printer.print.clone().expect("printing fixture has a job");
+
+		// First report after connecting: nothing to compare against
+		assert_eq!(
+			LayerChangeEvent::between(&printer.id, None, Some(&job)),
+			None
+		);
+		assert_eq!(
+			LayerChangeEvent::between(&printer.id, Some(job.layer_current), Some(&job)),
+			None
+		);
+		assert_eq!(
+			LayerChangeEvent::between(&printer.id, Some(job.layer_current - 1), Some(&job)),
+			Some(LayerChangeEvent {
+				printer_id: printer.id.clone(),
+				layer_current: job.layer_current,
+				layer_total: job.layer_total,
+			})
+		);
+		assert_eq!(LayerChangeEvent::between(&printer.id, Some(3), None), None);
+	}
+
+	#[test]
+	fn test_detection_follows_xcam_hms() {
+		let mut printer = Printer::new(&PrinterConfig {
+			model: "X1C".to_string(),
+			..test_config()
+		});
+		let mut report = fixture(include_str!("../tests/fixtures/p1p_printing.json"));
+		report["print"]["xcam"] = serde_json::json!({ "first_layer_inspector": true });
+		MqttService::apply_message(&mut printer, &report);
+		assert_eq!(printer.detection, Some(DetectionStatus::FirstLayerOk));
+		assert_eq!(DetectionAlert::between(None, &printer), None);
+
+		// `0C00_0300_0003_0008`
+		report["print"]["hms"] = serde_json::json!([{ "attr": 0x0C00_0300, "code": 0x0003_0008 }]);
+		MqttService::apply_message(&mut printer, &report);
+		assert_eq!(printer.detection, Some(DetectionStatus::SpaghettiDetected));
+		let alert = DetectionAlert::between(Some(DetectionStatus::FirstLayerOk), &printer)
+			.expect("spaghetti should raise an alert");
+		assert_eq!(alert.printer_name, printer.name);
+		// Not again while it's still reported
+		assert_eq!(
+			DetectionAlert::between(Some(DetectionStatus::SpaghettiDetected), &printer),
+			None
+		);
+
+		assert_eq!(printer.ai_detection_enabled, None);
+		report["print"]["xcam"]["spaghetti_detector"] = serde_json::json!(true);
+		MqttService::apply_message(&mut printer, &report);
+		assert_eq!(printer.ai_detection_enabled, Some(true));
+
+		// The P1P can't detect anything, whatever it reports
+		let mut p1p = test_printer();
+		MqttService::apply_message(&mut p1p, &report);
+		assert_eq!(p1p.detection, None);
+		assert_eq!(p1p.ai_detection_enabled, None);
+	}
+
+	#[test]
+	fn test_temperature_alert_on_rising_edge() {
+		let mut printer = Printer::new(&PrinterConfig {
+			temperature_thresholds: TemperatureThresholds {
+				max_nozzle: Some(240.0),
+				max_chamber: Some(50.0),
+				..TemperatureThresholds::default()
+			},
+			..test_config()
+		});
+		let cold = printer.temperatures.clone();
+		printer.temperatures.nozzle = 240.0;
+		printer.temperatures.bed = 120.0;
+		assert_eq!(TemperatureAlert::between(&cold, &printer), Vec::new());
+
+		let at_threshold = printer.temperatures.clone();
+		printer.temperatures.nozzle = 241.5;
+		printer.temperatures.chamber = 55.0;
+		let alerts = TemperatureAlert::between(&at_threshold, &printer);
+		let sensors: Vec<_> = alerts.iter().map(|alert| alert.sensor).collect();
+		assert_eq!(
+			sensors,
+			vec![TemperatureSensor::Nozzle, TemperatureSensor::Chamber]
+		);
+		assert_eq!(alerts[0].reading, 241.5);
+		assert_eq!(alerts[0].threshold, 240.0);
+
+		// Not again while it stays above
+		let hot = printer.temperatures.clone();
+		printer.temperatures.nozzle = 245.0;
+		assert_eq!(TemperatureAlert::between(&hot, &printer), Vec::new());
+	}
+
+	#[test]
+	fn test_reported_links_affect_online() {
+		let mut printer = test_printer();
+		printer.online = true;
+		let mut report = fixture(include_str!("../tests/fixtures/x1c_printing.json"));
+		report["print"]["online"] = serde_json::json!({ "ahb": true, "rfid": true });
+		MqttService::apply_message(&mut printer, &report);
+		assert!(printer.online);
+		assert_eq!(
+			printer.device_links,
+			Some(DeviceLinks {
+				ahb: Some(true),
+				rfid: Some(true)
+			})
+		);
+
+		report["print"]["online"]["ahb"] = serde_json::json!(false);
+		MqttService::apply_message(&mut printer, &report);
+		assert!(!printer.online);
+
+		// Leaving the field out isn't the same as reporting it down
+		report["print"]["online"] = serde_json::json!({ "rfid": true });
+		MqttService::apply_message(&mut printer, &report);
+		assert!(printer.online);
+
+		// Without an AMS, the hub being absent is normal
+		let mut report = fixture(include_str!("../tests/fixtures/p1p_printing.json"));
+		report["print"]["online"] = serde_json::json!({ "ahb": false, "rfid": false });
+		MqttService::apply_message(&mut printer, &report);
+		assert!(printer.ams.is_empty());
+		assert!(printer.online);
+	}
+
+	#[test]
+	fn test_error_cleared_only_on_falling_edge() {
+		let mut printer = test_printer();
+		let runout = fixture(include_str!("../tests/fixtures/p1p_filament_runout.json"));
+		MqttService::apply_message(&mut printer, &runout);
+		let error = printer
+			.error
+			.clone()
+			.expect("runout should produce an error");
+
+		assert_eq!(
+			ErrorClearedEvent::between(&printer.id, None, Some(&error)),
+			None
+		);
+		assert_eq!(
+			ErrorClearedEvent::between(&printer.id, Some(&error), Some(&error)),
+			None
+		);
+		assert_eq!(ErrorClearedEvent::between(&printer.id, None, None), None);
+
+		let mut recovered = runout.clone();
+		recovered["print"]["print_error"] = serde_json::json!(0);
+		recovered["print"]["mc_print_error_code"] = serde_json::json!(0);
+		recovered["print"]["gcode_state"] = serde_json::json!("RUNNING");
+		MqttService::apply_message(&mut printer, &recovered);
+		assert_eq!(
+			ErrorClearedEvent::between(&printer.id, Some(&error), printer.error.as_ref()),
+			Some(ErrorClearedEvent {
+				printer_id: printer.id.clone(),
+				print_error: error.print_error,
+				error_code: error.error_code,
+			})
+		);
+	}
+
+	#[test]
+	fn test_apply_message_parses_filament_usage() {
+		let mut report = fixture(include_str!("../tests/fixtures/x1c_printing.json"));
+		report["print"]["weight"] = serde_json::json!("41.27");
+		report["print"]["length"] = serde_json::json!(13840.5);
+
+		let mut printer = test_printer();
+		MqttService::apply_message(&mut printer, &report);
+
+		let job = printer.print.expect("printing report should produce a job");
+		assert_eq!(job.filament_weight, Some(41.27));
+		assert_eq!(job.filament_length, Some(13840.5));
+		assert_eq!(
+			job.gcode_file.as_deref(),
+			Some("/sdcard/enclosure_bracket_abs.gcode.3mf")
+		);
+	}
+
+	#[test]
+	fn test_notifications_fire_on_transitions() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/p1p_printing.json")),
+		);
+		assert_eq!(
+			PrinterNotification::between(&PrinterStatus::Printing, None, &printer),
+			None
+		);
+
+		let runout = fixture(include_str!("../tests/fixtures/p1p_filament_runout.json"));
+		MqttService::apply_message(&mut printer, &runout);
+		let notification = PrinterNotification::between(&PrinterStatus::Printing, None, &printer)
+			.expect("runout should notify");
+		assert_eq!(notification.event, "filament-runout");
+		assert_eq!(
+			notification.message.as_deref(),
+			Some("Filament runout detected")
+		);
+		// Same error in the next report
+		assert_eq!(
+			PrinterNotification::between(&PrinterStatus::Error, printer.error.as_ref(), &printer),
+			None
+		);
+
+		let mut other = runout.clone();
+		other["print"]["stg_cur"] = serde_json::json!(0);
+		other["print"]["mc_print_error_code"] = serde_json::json!(1204);
+		let previous_error = printer.error.clone();
+		MqttService::apply_message(&mut printer, &other);
+		assert_eq!(
+			PrinterNotification::between(&PrinterStatus::Error, previous_error.as_ref(), &printer)
+				.map(|notification| notification.event),
+			Some("printer-error")
+		);
+
+		let mut finished = test_printer();
+		MqttService::apply_message(
+			&mut finished,
+			&serde_json::json!({ "print": { "gcode_state": "FINISH", "subtask_name": "benchy" } }),
+		);
+		let notification = PrinterNotification::between(&PrinterStatus::Printing, None, &finished)
+			.expect("finishing should notify");
+		assert_eq!(notification.event, "print-complete");
+		assert_eq!(
+			PrinterNotification::between(&PrinterStatus::Idle, None, &finished),
+			None
+		);
+	}
+
+	#[test]
+	fn test_notifications_enabled_defaults_to_true() {
+		let mut config = serde_json::to_value(test_config()).unwrap();
+		config
+			.as_object_mut()
+			.unwrap()
+			.remove("notifications_enabled");
+		let config: PrinterConfig = serde_json::from_value(config).unwrap();
+		assert!(config.notifications_enabled);
+		assert!(Printer::new(&config).notifications_enabled);
+	}
+
+	#[test]
+	fn test_parse_payload_recovers_what_it_can() {
+		let report = fixture(include_str!("../tests/fixtures/p1p_printing.json"));
+		let text = report.to_string();
+
+		assert_eq!(
+			MqttService::parse_payload("p", text.as_bytes()),
+			vec![report.clone()]
+		);
+
+		// Two objects in one message, as some firmware sends
+		let info = serde_json::json!({ "info": { "command": "get_version" } });
+		let concatenated = format!("{text}{info}");
+		assert_eq!(
+			MqttService::parse_payload("p", concatenated.as_bytes()),
+			vec![report.clone(), info]
+		);
+
+		// The complete object survives a truncated one after it
+		let cut = format!("{text}{}", &text[..text.len() / 2]);
+		assert_eq!(
+			MqttService::parse_payload("p", cut.as_bytes()),
+			vec![report.clone()]
+		);
+	}
+
+	#[test]
+	fn test_truncated_payload_leaves_state_intact() {
+		let report = fixture(include_str!("../tests/fixtures/p1p_printing.json"));
+		let mut accumulated = MqttService::merge_report(serde_json::json!({}), report.clone());
+		let mut printer = test_printer();
+		MqttService::apply_message(&mut printer, &accumulated);
+		let state_before = accumulated.clone();
+		let printer_before = serde_json::to_value(&printer).unwrap();
+
+		let text = report.to_string();
+		let truncated = &text.as_bytes()[..text.len() - 20];
+		for data in MqttService::parse_payload("p", truncated) {
+			accumulated = MqttService::merge_report(accumulated, data);
+		}
+		MqttService::apply_message(&mut printer, &accumulated);
+
+		assert_eq!(accumulated, state_before);
+		assert_eq!(serde_json::to_value(&printer).unwrap(), printer_before);
+	}
+
+	#[test]
+	fn test_heater_gcode() {
+		assert_eq!(
+			Heater::parse("nozzle").unwrap().gcode(220, 300).unwrap(),
+			"M104 S220"
+		);
+		assert_eq!(
+			Heater::parse("bed")
+				.unwrap()
+				.gcode(0, MAX_BED_TEMP)
+				.unwrap(),
+			"M140 S0"
+		);
+		assert!(Heater::Nozzle.gcode(320, 300).is_err());
+		assert!(Heater::Bed.gcode(-5, MAX_BED_TEMP).is_err());
+		assert!(Heater::parse("chamber").is_err());
+	}
+
+	#[tokio::test]
+	async fn test_set_temperature_and_wait_fails_fast() {
+		let service = MqttService::with_events(Arc::new(RecordedEvents::default()));
+		let printer = Printer {
+			connection_state: ConnectionState::Unreachable,
+			..test_printer()
+		};
+		let printer_id = printer.id.clone();
+		service
+			.printer_states
+			.write()
+			.await
+			.insert(printer_id.clone(), Arc::new(printer));
+
+		let started = Instant::now();
+		assert!(service
+			.set_temperature_and_wait("unknown", "nozzle", 200, 60)
+			.await
+			.is_err());
+		assert!(service
+			.set_temperature_and_wait(&printer_id, "nozzle", 900, 60)
+			.await
+			.is_err());
+		assert!(service
+			.set_temperature_and_wait(&printer_id, "bed", 0, 60)
+			.await
+			.is_err());
+		assert!(service.temperature_waiters.read().await.is_empty());
+
+		// Not connected, so the setpoint can't go out
+		assert!(service
+			.set_temperature_and_wait(&printer_id, "nozzle", 200, 60)
+			.await
+			.is_err());
+		assert!(started.elapsed() < Duration::from_secs(5));
+	}
+
+	#[tokio::test]
+	async fn test_temperature_waiters_resolve_within_tolerance() {
+		let waiters = RwLock::new(HashMap::new());
+		let (nozzle, mut nozzle_reached) = oneshot::channel();
+		let (bed, mut bed_reached) = oneshot::channel();
+		let (abandoned, _) = oneshot::channel();
+		waiters.write().await.insert(
+			"p1".to_string(),
+			vec![
+				TemperatureWaiter {
+					heater: Heater::Nozzle,
+					target: 220,
+					reached: nozzle,
+				},
+				TemperatureWaiter {
+					heater: Heater::Bed,
+					target: 60,
+					reached: bed,
+				},
+				TemperatureWaiter {
+					heater: Heater::Bed,
+					target: 60,
+					reached: abandoned,
+				},
+			],
+		);
+		let mut temperatures = PrinterTemperatures {
+			nozzle: 150.0,
+			bed: 57.4,
+			chamber: 0.0,
+			nozzle_target: 220.0,
+			bed_target: 60.0,
+			nozzle_state: HeatingState::Heating,
+			bed_state: HeatingState::Heating,
+		};
+
+		// Another printer's report changes nothing
+		MqttService::resolve_temperature_waiters(&waiters, "p2", &temperatures).await;
+		assert_eq!(waiters.read().await["p1"].len(), 3);
+
+		MqttService::resolve_temperature_waiters(&waiters, "p1", &temperatures).await;
+		assert!(bed_reached.try_recv().is_ok());
+		assert!(nozzle_reached.try_recv().is_err());
+		assert_eq!(waiters.read().await["p1"].len(), 1);
+
+		temperatures.nozzle = 222.6;
+		MqttService::resolve_temperature_waiters(&waiters, "p1", &temperatures).await;
+		assert!(nozzle_reached.try_recv().is_ok());
+		assert!(waiters.read().await.is_empty());
+	}
+
+	#[test]
+	fn test_estimate_progress_prefers_mc_percent() {
+		let estimate = estimate_progress(&serde_json::json!({
+			"mc_percent": 42, "layer_num": 10, "total_layer_num": 200
+		}));
+		assert_eq!(estimate.percent, 42.0);
+		assert_eq!(estimate.source, ProgressSource::McPercent);
+	}
+
+	#[test]
+	fn test_estimate_progress_falls_back_to_layers() {
+		let estimate = estimate_progress(&serde_json::json!({
+			"mc_percent": 0, "layer_num": 50, "total_layer_num": 200
+		}));
+		assert_eq!(estimate.percent, 25.0);
+		assert_eq!(estimate.source, ProgressSource::Layers);
+
+		// Missing `mc_percent` counts as 0
+		let estimate = estimate_progress(&serde_json::json!({ "layer_num": 1, "total_layer_num": 4 }));
+		assert_eq!(estimate.source, ProgressSource::Layers);
+	}
+
+	#[test]
+	fn test_estimate_progress_without_data() {
+		for print_data in [
+			serde_json::json!({}),
+			serde_json::json!({ "mc_percent": 0, "layer_num": 0, "total_layer_num": 200 }),
+			// Out of range, and not 0 either, so the layers aren't trusted over it
+			serde_json::json!({ "mc_percent": 250, "layer_num": 50, "total_layer_num": 200 }),
+		] {
+			let estimate = estimate_progress(&print_data);
+			assert_eq!(estimate.percent, 0.0, "{print_data}");
+			assert_eq!(estimate.source, ProgressSource::Unavailable, "{print_data}");
+		}
+	}
+
+	#[test]
+	fn test_apply_message_parses_speed_magnitude() {
+		let mut report = fixture(include_str!("../tests/fixtures/x1c_printing.json"));
+		let mut printer = test_printer();
+		MqttService::apply_message(&mut printer, &report);
+		assert_eq!(printer.print.as_ref().unwrap().speed_magnitude, Some(124));
+
+		report["print"]["spd_mag"] = serde_json::json!(1000);
+		MqttService::apply_message(&mut printer, &report);
+		assert_eq!(printer.print.unwrap().speed_magnitude, Some(200));
+	}
+
+	#[test]
+	fn test_apply_message_parses_fan_speeds() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/x1c_printing.json")),
+		);
+
+		let job = printer.print.expect("printing report should produce a job");
+		assert_eq!(
+			job.fan_speeds,
+			Some(FanSpeeds {
+				part: 67,
+				aux: 33,
+				chamber: 53,
+			})
+		);
+		// The raw fan_gear is still exposed for the status heuristics
+		assert_eq!(job.fan_speed, Some(36095));
+	}
+
+	#[test]
+	fn test_fan_speeds_from_print_data() {
+		let speeds = FanSpeeds::from_print_data(&serde_json::json!({
+			"cooling_fan_speed": "15",
+			"big_fan1_speed": 0,
+			"big_fan2_speed": "99"
+		}));
+		assert_eq!(
+			speeds,
+			Some(FanSpeeds {
+				part: 100,
+				aux: 0,
+				chamber: 100,
+			})
+		);
+
+		assert_eq!(
+			FanSpeeds::from_print_data(&serde_json::json!({ "fan_gear": 15 })),
+			None
+		);
+	}
+
+	#[test]
+	fn test_fan_speed_gcode() {
+		assert_eq!(FanSpeeds::gcode("part", 100).unwrap(), "M106 P1 S255");
+		assert_eq!(FanSpeeds::gcode("aux", 50).unwrap(), "M106 P2 S128");
+		assert_eq!(FanSpeeds::gcode("chamber", 0).unwrap(), "M106 P3 S0");
+		assert_eq!(FanSpeeds::gcode("part", 250).unwrap(), "M106 P1 S255");
+		assert!(FanSpeeds::gcode("hotend", 50).is_err());
+	}
+
+	#[test]
+	fn test_build_command_payload() {
+		let payload = MqttService::build_command_payload(&PrintCommand::new("pause"), "42").unwrap();
+		assert_eq!(
+			payload,
+			serde_json::json!({ "print": { "command": "pause", "sequence_id": "42" } })
+		);
+
+		let payload = MqttService::build_command_payload(
+			&PrintCommand::with_param("gcode_line", serde_json::json!("M106 P1 S255")),
+			"42",
+		)
+		.unwrap();
+		assert_eq!(
+			payload,
+			serde_json::json!({
+				"print": { "command": "gcode_line", "param": "M106 P1 S255\n", "sequence_id": "42" }
+			})
+		);
+
+		let payload = MqttService::build_command_payload(
+			&PrintCommand::with_param("print_speed", serde_json::json!("3")),
+			"42",
+		)
+		.unwrap();
+		assert_eq!(
+			payload,
+			serde_json::json!({
+				"print": { "command": "print_speed", "param": "3", "sequence_id": "42" }
+			})
+		);
+
+		let payload = MqttService::build_command_payload(
+			&PrintCommand::with_param(
+				"ams_change_filament",
+				serde_json::json!({ "target": 5, "curr_temp": 30, "tar_temp": 220 }),
+			),
+			"42",
+		)
+		.unwrap();
+		assert_eq!(
+			payload,
+			serde_json::json!({
+				"print": {
+					"command": "ams_change_filament",
+					"target": 5,
+					"curr_temp": 30,
+					"tar_temp": 220,
+					"sequence_id": "42"
+				}
+			})
+		);
+
+		assert!(MqttService::build_command_payload(&PrintCommand::new("gcode_line"), "42").is_err());
+		assert!(
+			MqttService::build_command_payload(&PrintCommand::new("ams_change_filament"), "42").is_err()
+		);
+
+		let payload = MqttService::build_command_payload(
+			&PrintCommand::with_param("skip_objects", serde_json::json!([3, 7])),
+			"42",
+		)
+		.unwrap();
+		assert_eq!(
+			payload,
+			serde_json::json!({
+				"print": { "command": "skip_objects", "obj_list": [3, 7], "sequence_id": "42" }
+			})
+		);
+		assert!(MqttService::build_command_payload(
+			&PrintCommand::with_param("skip_objects", serde_json::json!([])),
+			"42"
+		)
+		.is_err());
+
+		let payload = MqttService::build_command_payload(&PrintCommand::new("pushall"), "42").unwrap();
+		assert_eq!(
+			payload,
+			serde_json::json!({ "pushing": { "command": "pushall", "sequence_id": "42" } })
+		);
+
+		let payload =
+			MqttService::build_command_payload(&PrintCommand::new("get_version"), "42").unwrap();
+		assert_eq!(
+			payload,
+			serde_json::json!({ "info": { "command": "get_version", "sequence_id": "42" } })
+		);
+		let payload = MqttService::build_command_payload(
+			&PrintCommand::with_param("ipcam_timelapse", serde_json::json!("enable")),
+			"42",
+		)
+		.unwrap();
+		assert_eq!(
+			payload,
+			serde_json::json!({
+				"camera": { "command": "ipcam_timelapse", "control": "enable", "sequence_id": "42" }
+			})
+		);
+		let payload = MqttService::build_command_payload(
+			&PrintCommand::with_param("xcam_control_set", serde_json::json!({ "control": false })),
+			"42",
+		)
+		.unwrap();
+		assert_eq!(
+			payload,
+			serde_json::json!({
+				"xcam": {
+					"command": "xcam_control_set",
+					"module_name": "spaghetti_detector",
+					"control": false,
+					"print_halt": false,
+					"sequence_id": "42"
+				}
+			})
+		);
+		assert!(
+			MqttService::build_command_payload(&PrintCommand::new("xcam_control_set"), "42").is_err()
+		);
+
+		assert!(MqttService::build_command_payload(&PrintCommand::new("explode"), "42").is_err());
+	}
+
+	#[test]
+	fn test_apply_message_sets_error_details() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/p1p_filament_runout.json")),
+		);
+
+		let error = printer
+			.error
+			.expect("runout report should produce an error");
+		assert_eq!(error.error_code, 1203);
+		assert_eq!(error.message, "Filament runout detected");
+		assert_eq!(error.gcode_state, "PAUSE");
+		assert_eq!(printer.hms.len(), 1);
+		assert_eq!(printer.hms[0].id, "0700_0100_0002_0002");
+	}
+
+	#[test]
+	fn test_apply_message_uses_hms_description_for_error() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&serde_json::json!({ "print": {
+				"gcode_state": "FAILED",
+				"print_error": 50348033,
+				"hms": [ { "attr": 50331904, "code": 65537 } ]
+			} }),
+		);
+
+		let error = printer.error.expect("print_error should produce an error");
+		assert_eq!(
+			error.message,
+			"The heatbed temperature is abnormal; the heater may be short-circuited"
+		);
+	}
+
+	#[test]
+	fn test_acknowledged_error_stays_hidden_until_it_changes() {
+		let runout = fixture(include_str!("../tests/fixtures/p1p_filament_runout.json"));
+		let mut printer = test_printer();
+		MqttService::apply_message(&mut printer, &runout);
+		let error = printer
+			.error
+			.clone()
+			.expect("runout should produce an error");
+
+		// What `clear_error` does before re-applying the accumulated state
+		printer.acknowledged_error = Some((error.print_error, error.error_code));
+		MqttService::apply_message(&mut printer, &runout);
+		assert!(printer.error.is_none());
+		assert_eq!(printer.status, PrinterStatus::Paused);
+
+		// A different error is not suppressed
+		let mut other = runout.clone();
+		other["print"]["mc_print_error_code"] = serde_json::json!(1204);
+		MqttService::apply_message(&mut printer, &other);
+		assert_eq!(printer.error.map(|e| e.error_code), Some(1204));
+		assert_eq!(printer.status, PrinterStatus::Error);
+		assert_eq!(printer.acknowledged_error, None);
+	}
+
+	#[test]
+	fn test_service_summary_counts() {
+		let mut printing = test_printer();
+		MqttService::apply_message(
+			&mut printing,
+			&fixture(include_str!("../tests/fixtures/p1p_printing.json")),
+		);
+		let mut runout = test_printer();
+		MqttService::apply_message(
+			&mut runout,
+			&fixture(include_str!("../tests/fixtures/p1p_filament_runout.json")),
+		);
+		let connecting = test_printer();
+
+		let summary = ServiceSummary::from_printers([&printing, &runout, &connecting]);
+		assert_eq!(
+			summary,
+			ServiceSummary {
+				total: 3,
+				printing: 1,
+				error: 1,
+				connecting: 1,
+				with_errors: 1,
+				with_hms_errors: 1,
+				..ServiceSummary::default()
+			}
+		);
+	}
+
+	#[test]
+	fn test_is_offline_since() {
+		let now = Utc::now();
+		let cutoff = now - chrono::Duration::hours(24);
+		let mut printer = test_printer();
+		printer.status = PrinterStatus::Offline;
+		printer.offline_since = Some(now - chrono::Duration::days(3));
+		assert!(printer.is_offline_since(cutoff));
+
+		// Only just went offline
+		printer.offline_since = Some(now);
+		assert!(!printer.is_offline_since(cutoff));
+
+		// Gave up reconnecting
+		printer.offline_since = Some(now - chrono::Duration::days(3));
+		printer.status = PrinterStatus::Connecting;
+		assert!(!printer.is_offline_since(cutoff));
+		printer.connection_state = ConnectionState::Unreachable;
+		assert!(printer.is_offline_since(cutoff));
+
+		// Switched off on purpose
+		printer.status = PrinterStatus::Disabled;
+		assert!(!printer.is_offline_since(cutoff));
+	}
+
+	#[tokio::test]
+	async fn test_prune_counts_from_first_failed_attempt() {
+		// Nothing listens on a port we just let go of
+		let port = std::net::TcpListener::bind("127.0.0.1:0")
+			.unwrap()
+			.local_addr()
+			.unwrap()
+			.port();
+		let config = PrinterConfig {
+			ip: "127.0.0.1".to_string(),
+			port: Some(port),
+			max_reconnect_attempts: Some(1),
+			..test_config()
+		};
+		let service = MqttService::with_events(Arc::new(RecordedEvents::default()));
+		service.add_printer(config.clone(), false).await.unwrap();
+
+		let printer_with = |done: fn(&Printer) -> bool| {
+			let service = service.clone();
+			async move {
+				loop {
+					let printer = service.get_all_printers().await.remove(0);
+					if done(&printer) {
+						return printer;
+					}
+					tokio::time::sleep(Duration::from_millis(50)).await;
+				}
+			}
+		};
+		let deadline = RECONNECT_DELAY + Duration::from_secs(5);
+		let first_failure = tokio::time::timeout(deadline, printer_with(|p| p.offline_since.is_some()))
+			.await
+			.expect("the first connection attempt never failed");
+		let gave_up = tokio::time::timeout(
+			deadline,
+			printer_with(|p| p.connection_state == ConnectionState::Unreachable),
+		)
+		.await
+		.expect("the connection task never gave up");
+
+		// The retry refreshed `last_update` but the printer has been offline since the first failure
+		assert_eq!(gave_up.offline_since, first_failure.offline_since);
+		assert!(gave_up.last_update > first_failure.offline_since.unwrap());
+		assert!(service
+			.prune_offline_printers(u64::MAX)
+			.await
+			.unwrap()
+			.is_empty());
+		let offline_for = RECONNECT_DELAY.as_secs() - 1;
+		assert_eq!(
+			service.prune_offline_printers(offline_for).await.unwrap(),
+			vec![config.id.clone()]
+		);
+		assert!(service.get_all_printers().await.is_empty());
+	}
+
+	#[test]
+	fn test_text_on_color() {
+		assert_eq!(FilamentInfo::text_on_color("FFFFFFFF"), "#000000");
+		assert_eq!(FilamentInfo::text_on_color("000000FF"), "#ffffff");
+		assert_eq!(FilamentInfo::text_on_color("#F4EE2A"), "#000000");
+		assert_eq!(FilamentInfo::text_on_color("0A2989FF"), "#ffffff");
+		assert_eq!(FilamentInfo::text_on_color("FF0000"), "#000000");
+		assert_eq!(FilamentInfo::text_on_color("8E1B1BFF"), "#ffffff");
+		// Empty tray, and garbage
+		assert_eq!(FilamentInfo::text_on_color("00000000"), "#000000");
+		assert_eq!(FilamentInfo::text_on_color(""), "#000000");
+		assert_eq!(FilamentInfo::text_on_color("GGGGGG"), "#000000");
+		assert_eq!(FilamentInfo::text_on_color("ÿÿÿ"), "#000000");
+	}
+
+	#[test]
+	fn test_debug_bundle_redacts_access_code() {
+		let config = test_config();
+		let mut printer = Printer::new(&config);
+		printer.name = format!("Printer {}", config.access_code);
+		let bundle = DebugBundle {
+			app_version: "0.0.0".to_string(),
+			created_at: Utc::now(),
+			raw_state: serde_json::json!({
+				"print": { "subtask_name": format!("job-{}", config.access_code) },
+				"log": [config.access_code.clone()]
+			}),
+			config: config.clone(),
+			printer,
+			metrics: ConnectionMetrics::default(),
+			command_history: Vec::new(),
+		};
+
+		let json = bundle.into_redacted_json(&config.access_code).unwrap();
+		assert!(!json.contains(&config.access_code));
+		let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+		assert_eq!(parsed["config"]["access_code"], "");
+		assert_eq!(parsed["config"]["serial"], config.serial);
+		assert_eq!(
+			parsed["raw_state"]["print"]["subtask_name"],
+			"job-[redacted]"
+		);
+	}
+
+	#[test]
+	fn test_printer_filter() {
+		let mut printer = Printer::new(&PrinterConfig {
+			tags: vec!["PETG station".to_string(), "garage".to_string()],
+			..test_config()
+		});
+		printer.status = PrinterStatus::Printing;
+
+		assert!(PrinterFilter::default().matches(&printer));
+		let filter = PrinterFilter {
+			status: Some(PrinterStatus::Printing),
+			name: Some(" test ".to_string()),
+			tag: Some("petg station".to_string()),
+		};
+		assert!(filter.matches(&printer));
+		assert!(!PrinterFilter {
+			status: Some(PrinterStatus::Idle),
+			..filter.clone()
+		}
+		.matches(&printer));
+		assert!(!PrinterFilter {
+			name: Some("voron".to_string()),
+			..filter.clone()
+		}
+		.matches(&printer));
+		// Tags match whole, not by substring
+		assert!(!PrinterFilter {
+			tag: Some("PETG".to_string()),
+			..filter
+		}
+		.matches(&printer));
+	}
+
+	#[test]
+	fn test_restore_last_known_state() {
+		let mut printing = test_printer();
+		MqttService::apply_message(
+			&mut printing,
+			&fixture(include_str!("../tests/fixtures/p1p_printing.json")),
+		);
+		let stored = printing.to_stored_state();
+		assert_eq!(stored.status, "printing");
+
+		let mut restored = test_printer();
+		restored.restore_last_known(&stored);
+		assert_eq!(restored.status, PrinterStatus::Printing);
+		assert_eq!(restored.connection_state, ConnectionState::Stale);
+		assert!(!restored.online);
+		assert_eq!(restored.temperatures.nozzle, printing.temperatures.nozzle);
+		let job = restored.print.as_ref().unwrap();
+		assert_eq!(job.file_name, "benchy");
+		assert_eq!(job.layer_current, 84);
+		assert_eq!(
+			restored.last_update.timestamp(),
+			printing.last_update.timestamp()
+		);
+
+		// A state with an unknown status is ignored
+		let mut untouched = test_printer();
+		untouched.restore_last_known(&PrinterState {
+			status: "exploded".to_string(),
+			..stored
+		});
+		assert_eq!(untouched.connection_state, ConnectionState::Connecting);
+	}
+
+	#[tokio::test]
+	async fn test_duplicate_add_keeps_last_known_state() {
+		let service = MqttService::with_events(Arc::new(RecordedEvents::default()));
+		let first = PrinterConfig {
+			ip: "127.0.0.1".to_string(),
+			port: Some(1),
+			..test_config()
+		};
+		let second = PrinterConfig {
+			id: "second-printer".to_string(),
+			..first.clone()
+		};
+		service
+			.restore_last_known(vec![PrinterState {
+				printer_id: second.id.clone(),
+				..test_printer().to_stored_state()
+			}])
+			.await;
+
+		service.add_printer(first, false).await.unwrap();
+		assert!(service.add_printer(second.clone(), false).await.is_err());
+		assert!(service
+			.last_known_states
+			.read()
+			.await
+			.contains_key(&second.id));
+
+		let second = PrinterConfig {
+			serial: "01S00A000000001".to_string(),
+			..second
+		};
+		service.add_printer(second, false).await.unwrap();
+		assert!(service.last_known_states.read().await.is_empty());
+		service.shutdown().await;
+	}
+
+	#[test]
+	fn test_z_offset_gcode() {
+		assert_eq!(MqttService::z_offset_gcode(0.02).unwrap(), "M290 Z0.020");
+		assert_eq!(MqttService::z_offset_gcode(-0.04).unwrap(), "M290 Z-0.040");
+		assert_eq!(MqttService::z_offset_gcode(3.0).unwrap(), "M290 Z0.500");
+		assert_eq!(MqttService::z_offset_gcode(-3.0).unwrap(), "M290 Z-0.500");
+		assert!(MqttService::z_offset_gcode(0.0).is_err());
+		assert!(MqttService::z_offset_gcode(f64::NAN).is_err());
+	}
+
+	#[test]
+	fn test_command_response_from_report() {
+		let response = CommandResponse::from_report(
+			"p1",
+			&serde_json::json!({ "print": {
+				"command": "resume",
+				"sequence_id": "1700000000000",
+				"result": "failed",
+				"reason": "printer is not paused"
+			} }),
+		)
+		.expect("command echo should parse");
+		assert_eq!(response.sequence_id, "1700000000000");
+		assert!(response.is_failure());
+		assert_eq!(response.reason.as_deref(), Some("printer is not paused"));
+
+		// Status pushes carry a sequence_id but answer nothing
+		assert!(CommandResponse::from_report(
+			"p1",
+			&fixture(include_str!("../tests/fixtures/p1p_printing.json"))
+		)
+		.is_none());
+	}
+
+	/// Adds `config` to a service recording its events and lets the real
+	/// connection task run against `broker` until it has handled `reports`
+	/// reports and sent its status request.
+	async fn play_reports(broker: &TestBroker, config: &PrinterConfig, reports: usize) -> Printer {
+		let events = Arc::new(RecordedEvents::default());
+		let service = MqttService::with_events(events.clone());
+
+		let played = async {
+			service.add_printer(config.clone(), true).await.unwrap();
+			// One update when added and one on connecting, then one per report
+			events.wait_for("printer-update", reports + 2).await;
+			while broker.log().published.is_empty() {
+				tokio::time::sleep(Duration::from_millis(50)).await;
+			}
+		};
+		tokio::time::timeout(Duration::from_secs(10), played)
+			.await
+			.expect("timed out waiting for the test broker");
+
+		let printer = service.get_all_printers().await.remove(0);
+		service.shutdown().await;
+		printer
+	}
+
+	#[tokio::test]
+	async fn test_command_sent_before_connack_waits_for_connection() {
+		let config = PrinterConfig {
+			ip: "127.0.0.1".to_string(),
+			..test_config()
+		};
+		let broker = TestBroker::start(&config.report_topic(), Vec::new())
+			.await
+			.unwrap();
+		let config = PrinterConfig {
+			port: Some(broker.port),
+			..config
+		};
+
+		let service = MqttService::with_events(Arc::new(RecordedEvents::default()));
+		service.add_printer(config.clone(), false).await.unwrap();
+
+		// The user pauses right after adding the printer, before it has connected
+		let sent = tokio::time::timeout(
+			Duration::from_secs(10),
+			service.send_command_confirmed(&config.id, PrintCommand::new("pause")),
+		)
+		.await
+		.expect("timed out waiting for the printer to connect");
+		sent.unwrap();
+
+		let reached_broker = async {
+			loop {
+				let pause = broker
+					.log()
+					.published
+					.iter()
+					.find(|(_, payload)| payload["print"]["command"] == "pause")
+					.cloned();
+				if let Some(pause) = pause {
+					return pause;
+				}
+				tokio::time::sleep(Duration::from_millis(50)).await;
+			}
+		};
+		let (topic, _) = tokio::time::timeout(Duration::from_secs(5), reached_broker)
+			.await
+			.expect("timed out waiting for the command to reach the broker");
+		assert_eq!(topic, format!("device/{}/request", config.serial));
+		service.shutdown().await;
+	}
+
+	#[tokio::test]
+	async fn test_command_fails_if_printer_never_connects() {
+		let printer_states = PrinterStates::new();
+		let printer_connections = RwLock::new(HashMap::new());
+		let connection_ready = Notify::new();
+		let mut printer = test_printer();
+		printer_states
+			.write()
+			.await
+			.insert(printer.id.clone(), Arc::new(printer.clone()));
+
+		let error = MqttService::connected_client(
+			&printer_states,
+			&printer_connections,
+			&connection_ready,
+			&printer.id,
+			Duration::from_millis(50),
+		)
+		.await
+		.unwrap_err();
+		assert!(error.to_string().contains("didn't connect"), "{error}");
+
+		// No point waiting on a printer that rejected the access code
+		printer.connection_state = ConnectionState::AuthFailed;
+		printer_states
+			.write()
+			.await
+			.insert(printer.id.clone(), Arc::new(printer.clone()));
+		let started = Instant::now();
+		assert!(MqttService::connected_client(
+			&printer_states,
+			&printer_connections,
+			&connection_ready,
+			&printer.id,
+			Duration::from_secs(5),
+		)
+		.await
+		.is_err());
+		assert!(started.elapsed() < Duration::from_secs(1));
+	}
+
+	#[tokio::test]
+	async fn test_send_command_to_all_reports_failed_publishes() {
+		let service = MqttService::with_events(Arc::new(RecordedEvents::default()));
+		let options = MqttOptions::new("pulseprint_test", "127.0.0.1", 8883);
+		// Never polled, but alive, so the publish is accepted
+		let (connected, _event_loop) = AsyncClient::new(options.clone(), 10);
+		// Its event loop is gone, so publishing fails
+		let (disconnected, _) = AsyncClient::new(options, 10);
+
+		for (id, client) in [("p1", connected), ("p2", disconnected)] {
+			let printer = Printer {
+				id: id.to_string(),
+				serial: format!("SERIAL{id}"),
+				status: PrinterStatus::Printing,
+				..test_printer()
+			};
+			service
+				.printer_states
+				.write()
+				.await
+				.insert(id.to_string(), Arc::new(printer));
+			service
+				.printer_connections
+				.write()
+				.await
+				.insert(id.to_string(), client);
+		}
+
+		let results = service
+			.send_command_to_all("pause", &[PrinterStatus::Printing])
+			.await;
+		assert_eq!(results.len(), 2);
+		assert_eq!(results[0].printer_id, "p1");
+		assert!(results[0].success, "{:?}", results[0].error);
+		assert_eq!(results[1].printer_id, "p2");
+		assert!(!results[1].success);
+		assert!(results[1].error.is_some());
+	}
+
+	/// Checks every field in `expected` against `actual`, recursing into objects.
+	fn assert_fields(expected: &serde_json::Value, actual: &serde_json::Value, path: &str) {
+		match expected.as_object() {
+			Some(fields) => {
+				for (key, value) in fields {
+					assert_fields(value, &actual[key], &format!("{path}.{key}"));
+				}
+			}
+			None => assert_eq!(actual, expected, "{path}"),
+		}
+	}
+
+	/// Plays each scenario in `tests/fixtures/scenarios` through a local broker.
+	/// A scenario lists the reports to publish, either inline or as the name of
+	/// a fixture, and the `Printer` fields expected afterwards.
+	#[tokio::test]
+	async fn test_broker_scenarios() {
+		let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+		let mut scenarios: Vec<PathBuf> = std::fs::read_dir(fixtures.join("scenarios"))
+			.unwrap()
+			.map(|entry| entry.unwrap().path())
+			.filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+			.collect();
+		scenarios.sort();
+		assert!(!scenarios.is_empty());
+
+		for path in scenarios {
+			let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+			let scenario = fixture(&std::fs::read_to_string(&path).unwrap());
+			let reports: Vec<serde_json::Value> = scenario["reports"]
+				.as_array()
+				.unwrap_or_else(|| panic!("{name}: no reports"))
+				.iter()
+				.map(|report| match report.as_str() {
+					Some(file) => fixture(&std::fs::read_to_string(fixtures.join(file)).unwrap()),
+					None => report.clone(),
+				})
+				.collect();
+
+			let config = PrinterConfig {
+				ip: "127.0.0.1".to_string(),
+				..test_config()
+			};
+			let broker = TestBroker::start(&config.report_topic(), reports.clone())
+				.await
+				.unwrap();
+			let config = PrinterConfig {
+				port: Some(broker.port),
+				..config
+			};
+			let printer = play_reports(&broker, &config, reports.len()).await;
+
+			{
+				let log = broker.log();
+				assert_eq!(
+					log.logins,
+					vec![(
+						PrinterConfig::DEFAULT_USERNAME.to_string(),
+						config.access_code.clone()
+					)],
+					"{name}: login"
+				);
+				assert!(
+					log.subscriptions.contains(&config.report_topic()),
+					"{name}: subscriptions"
+				);
+				assert_eq!(
+					log.published[0].0,
+					format!("device/{}/request", config.serial),
+					"{name}: status request"
+				);
+				assert_eq!(log.published[0].1["print"]["command"], "get_status");
+			}
+			assert_fields(
+				&scenario["expect"],
+				&serde_json::to_value(&printer).unwrap(),
+				&name,
+			);
+		}
+	}
+
+	#[test]
+	fn test_mirror_changes_compare_snapshots() {
+		let printer = Arc::new(test_printer());
+		let snapshot: PrinterMap = HashMap::from([(printer.id.clone(), Arc::clone(&printer))]);
+
+		let (changed, removed) = MirrorConfig::changes(&snapshot, &HashMap::new());
+		assert_eq!(changed.len(), 1);
+		assert!(removed.is_empty());
+
+		let (changed, removed) = MirrorConfig::changes(&snapshot, &snapshot);
+		assert!(changed.is_empty() && removed.is_empty());
+
+		let (changed, removed) = MirrorConfig::changes(&HashMap::new(), &snapshot);
+		assert!(changed.is_empty());
+		assert_eq!(removed, vec![printer.id.clone()]);
+	}
+
+	#[tokio::test]
+	async fn test_mirror_publishes_printer_state() {
+		let broker = TestBroker::start("unused", Vec::new()).await.unwrap();
+		let mirror = MirrorConfig {
+			host: "127.0.0.1".to_string(),
+			port: broker.port,
+			username: None,
+			password: None,
+			tls: Some(TlsMode::InsecureSkipVerify),
+		};
+		let states = PrinterStates::new();
+		let printer = test_printer();
+		let topic = MirrorConfig::state_topic(&printer.id);
+		states
+			.write()
+			.await
+			.insert(printer.id.clone(), Arc::new(printer));
+
+		let cancel = CancellationToken::new();
+		let task = tokio::spawn(MqttService::run_mirror(
+			mirror.host.clone(),
+			mirror.mqtt_options().unwrap(),
+			states.snapshot.subscribe(),
+			cancel.clone(),
+		));
+		let published = async {
+			loop {
+				if let Some((_, state)) = broker.log().published.iter().find(|(t, _)| *t == topic) {
+					return state.clone();
+				}
+				tokio::time::sleep(Duration::from_millis(20)).await;
+			}
+		};
+		let state = tokio::time::timeout(Duration::from_secs(10), published)
+			.await
+			.unwrap_or_else(|_| panic!("mirror never published: {:?}", broker.log()));
+		cancel.cancel();
+		task.await.unwrap();
+
+		assert_eq!(state["name"], "Test Printer");
+		assert!(state.get("access_code").is_none());
+	}
+
+	#[tokio::test]
+	async fn test_printer_states_snapshot_follows_writes() {
+		let states = PrinterStates::new();
+		assert!(states.snapshot().is_empty());
+
+		{
+			let mut printers = states.write().await;
+			printers.insert("p1".to_string(), Arc::new(test_printer()));
+			// Not published until the write lock is released
+			assert!(states.snapshot().is_empty());
+		}
+		let before = states.snapshot();
+		assert_eq!(before.len(), 1);
+
+		{
+			let mut printers = states.write().await;
+			let printer = Arc::make_mut(printers.get_mut("p1").unwrap());
+			printer.temperatures.nozzle = 215.0;
+		}
+		assert_eq!(states.snapshot()["p1"].temperatures.nozzle, 215.0);
+		// Earlier snapshots are unaffected
+		assert_eq!(before["p1"].temperatures.nozzle, 0.0);
+	}
+
+	#[tokio::test]
+	async fn test_printer_states_since_returns_changed_printers() {
+		let states = PrinterStates::new();
+		let count = |version| {
+			let (next, printers) = states.since(version);
+			(next, printers.len())
+		};
+		assert_eq!(count(0), (0, 0));
+
+		{
+			let mut printers = states.write().await;
+			printers.insert("p1".to_string(), Arc::new(test_printer()));
+			printers.insert("p2".to_string(), Arc::new(test_printer()));
+		}
+		assert_eq!(count(0), (1, 2));
+		assert_eq!(count(1), (1, 0));
+
+		{
+			let mut printers = states.write().await;
+			Arc::make_mut(printers.get_mut("p2").unwrap())
+				.temperatures
+				.nozzle = 215.0;
+		}
+		let (next, printers) = states.since(1);
+		assert_eq!(next, 2);
+		assert_eq!(printers.len(), 1);
+		assert_eq!(printers[0].temperatures.nozzle, 215.0);
+
+		// A write that changes nothing doesn't bump the version
+		drop(states.write().await);
+		assert_eq!(count(2), (2, 0));
+
+		states.write().await.remove("p1");
+		assert_eq!(count(0), (2, 1));
+	}
+
+	/// Checks that `get_all_printers`-style bulk reads from the snapshot are no
+	/// slower than under the read lock while ten printers report as fast as
+	/// they can. Run with `cargo test bench_bulk_read -- --ignored`.
+	#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+	#[ignore]
+	async fn bench_bulk_read_under_load() {
+		const READS: u32 = 2000;
+		let states = Arc::new(PrinterStates::new());
+		{
+			let mut printers = states.write().await;
+			for i in 0..10 {
+				let mut config = test_config();
+				config.id = format!("p{i}");
+				printers.insert(config.id.clone(), Arc::new(Printer::new(&config)));
+			}
+		}
+
+		let stop = CancellationToken::new();
+		for i in 0..10 {
+			let (states, stop) = (Arc::clone(&states), stop.clone());
+			tokio::spawn(async move {
+				let report = fixture(include_str!("../tests/fixtures/p1p_printing.json"));
+				while !stop.is_cancelled() {
+					let mut printers = states.write().await;
+					MqttService::apply_message(
+						Arc::make_mut(printers.get_mut(&format!("p{i}")).unwrap()),
+						&report,
+					);
+					drop(printers);
+					tokio::task::yield_now().await;
+				}
+			});
+		}
+
+		let started = Instant::now();
+		for _ in 0..READS {
+			let printers = states.read().await;
+			let all: Vec<Printer> = printers.values().map(|p| p.as_ref().clone()).collect();
+			assert_eq!(all.len(), 10);
+		}
+		let locked = started.elapsed() / READS;
+
+		let started = Instant::now();
+		for _ in 0..READS {
+			let all: Vec<Printer> = states
+				.snapshot()
+				.values()
+				.map(|p| p.as_ref().clone())
+				.collect();
+			assert_eq!(all.len(), 10);
+		}
+		let snapshot = started.elapsed() / READS;
+		stop.cancel();
+
+		assert!(
+			snapshot <= locked,
+			"snapshot reads took {snapshot:?} each, read-locked ones {locked:?}"
+		);
+	}
+
+	#[tokio::test]
+	async fn test_slow_printer_does_not_block_others() {
+		let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+		let log = Arc::clone(&sent);
+		let queues = CommandQueues::new(move |printer_id: String, command: PrintCommand| {
+			let log = Arc::clone(&log);
+			async move {
+				// Stands in for a publish stuck on a slow TLS connection
+				if printer_id == "slow" && command.action == "pause" {
+					tokio::time::sleep(Duration::from_millis(500)).await;
+				}
+				log
+					.lock()
+					.unwrap()
+					.push(format!("{printer_id}:{}", command.action));
+				Ok(command.action)
+			}
+		});
+
+		let queue = |printer_id: &str, action: &str| {
+			let (reply, outcome) = oneshot::channel();
+			queues.push(QueuedCommand {
+				printer_id: printer_id.to_string(),
+				command: PrintCommand::new(action),
+				reply: Some(reply),
+			});
+			outcome
+		};
+		let slow_pause = queue("slow", "pause");
+		let slow_stop = queue("slow", "stop");
+		let fast = queue("fast", "pause");
+
+		let result = tokio::time::timeout(Duration::from_millis(200), fast)
+			.await
+			.expect("fast printer's command should not wait behind the slow one");
+		assert_eq!(result.unwrap().unwrap(), "pause");
+
+		slow_pause.await.unwrap().unwrap();
+		slow_stop.await.unwrap().unwrap();
+		// Per-printer order is kept
+		assert_eq!(
+			*sent.lock().unwrap(),
+			vec!["fast:pause", "slow:pause", "slow:stop"]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_pending_commands_can_be_cancelled() {
+		let queues = CommandQueues::new(|_printer_id: String, command: PrintCommand| async move {
+			// A slow printer, so the commands behind this one pile up
+			tokio::time::sleep(Duration::from_millis(300)).await;
+			Ok(command.action)
+		});
+		let queue = |action: &str| {
+			let (reply, outcome) = oneshot::channel();
+			queues.push(QueuedCommand {
+				printer_id: "p1".to_string(),
+				command: PrintCommand::new(action),
+				reply: Some(reply),
+			});
+			outcome
+		};
+
+		let pause = queue("pause");
+		// Wait for the worker to pick up `pause`; it's no longer pending then
+		while !queues.pending("p1").is_empty() {
+			tokio::time::sleep(Duration::from_millis(5)).await;
+		}
+		let stop = queue("stop");
+		let resume = queue("resume");
+		assert_eq!(queues.pending("p1"), vec!["stop", "resume"]);
+		assert!(queues.pending("p2").is_empty());
+
+		assert_eq!(queues.cancel("p1"), 2);
+		assert!(queues.pending("p1").is_empty());
+		assert!(stop
+			.await
+			.unwrap()
+			.unwrap_err()
+			.to_string()
+			.contains("cancelled"));
+		assert!(resume.await.unwrap().is_err());
+		// The command already being sent isn't affected
+		assert_eq!(pause.await.unwrap().unwrap(), "pause");
+		assert_eq!(queues.cancel("p1"), 0);
+	}
+
+	#[test]
+	fn test_validate_accepts_valid_config() {
+		assert_eq!(test_config().validate(), Ok(()));
+
+		let config = PrinterConfig {
+			ip: "bambu-p1p".to_string(),
+			access_code: "a1B2c3D4".to_string(),
+			..test_config()
+		};
+		assert_eq!(config.validate(), Ok(()));
+	}
+
+	#[test]
+	fn test_validate_accepts_ipv6_and_mdns_hosts() {
+		for (ip, host) in [
+			("fe80::1c2b:3aff:fe4d:5e6f", "fe80::1c2b:3aff:fe4d:5e6f"),
+			("[2001:db8::42]", "2001:db8::42"),
+			(" ::1 ", "::1"),
+			("bambu-x1c.local", "bambu-x1c.local"),
+			("bambu-x1c.local.", "bambu-x1c.local."),
+		] {
+			let config = PrinterConfig {
+				ip: ip.to_string(),
+				..test_config()
+			};
+			assert_eq!(config.validate(), Ok(()), "{ip}");
+			assert_eq!(config.host(), host);
+		}
+
+		for ip in [
+			"[bambu-x1c.local]",
+			"[192.168.1.20]",
+			"fe80::1%en0",
+			"2001:db8::g",
+		] {
+			let config = PrinterConfig {
+				ip: ip.to_string(),
+				..test_config()
+			};
+			assert_eq!(config.validate().unwrap_err().field, "ip", "{ip}");
+		}
+	}
+
+	#[test]
+	fn test_fresh_client_id_differs_per_attempt() {
+		let config = test_config();
+		let first = MqttService::fresh_client_id(&config);
+		let second = MqttService::fresh_client_id(&config);
+		assert!(first.starts_with(&format!("pulseprint_desktop_{}_", config.id)));
+		assert_ne!(first, second);
+	}
+
+	#[tokio::test]
+	async fn test_ipv6_host_reaches_rumqttc_unbracketed() {
+		let config = PrinterConfig {
+			ip: "[::1]".to_string(),
+			..test_config()
+		};
+		let options = MqttService::mqtt_options(&config, "client").unwrap();
+		assert_eq!(options.broker_address(), ("::1".to_string(), 8883));
+
+		// rumqttc connects by looking up "{host}:{port}", which has to resolve for a bare IPv6 literal
+		let addrs: Vec<_> = tokio::net::lookup_host("::1:8883").await.unwrap().collect();
+		assert_eq!(addrs, vec!["[::1]:8883".parse().unwrap()]);
+
+		// ...and the TLS server name is taken from the same string
+		assert!(ServerName::try_from("::1").is_ok());
+	}
+
+	#[test]
+	fn test_validate_rejects_bad_fields() {
+		let cases = [
+			(
+				PrinterConfig {
+					ip: "192.168.1.".to_string(),
+					..test_config()
+				},
+				"ip",
+			),
+			(
+				PrinterConfig {
+					ip: "192.168.1.300".to_string(),
+					..test_config()
+				},
+				"ip",
+			),
+			(
+				PrinterConfig {
+					ip: String::new(),
+					..test_config()
+				},
+				"ip",
+			),
+			(
+				PrinterConfig {
+					ip: "printer_1".to_string(),
+					..test_config()
+				},
+				"ip",
+			),
+			(
+				PrinterConfig {
+					access_code: "1234567".to_string(),
+					..test_config()
+				},
+				"access_code",
+			),
+			(
+				PrinterConfig {
+					access_code: "1234 678".to_string(),
+					..test_config()
+				},
+				"access_code",
+			),
+			(
+				PrinterConfig {
+					serial: "  ".to_string(),
+					..test_config()
+				},
+				"serial",
+			),
+			(
+				PrinterConfig {
+					port: Some(0),
+					..test_config()
+				},
+				"port",
+			),
+			(
+				PrinterConfig {
+					poll_interval_secs: Some(0),
+					..test_config()
+				},
+				"poll_interval_secs",
+			),
+			(
+				PrinterConfig {
+					keep_alive_secs: Some(0),
+					..test_config()
+				},
+				"keep_alive_secs",
+			),
+			(
+				PrinterConfig {
+					keep_alive_secs: Some(70_000),
+					..test_config()
+				},
+				"keep_alive_secs",
+			),
+			(
+				PrinterConfig {
+					extra_topics: vec![" ".to_string()],
+					..test_config()
+				},
+				"extra_topics",
+			),
+			(
+				PrinterConfig {
+					temperature_thresholds: TemperatureThresholds {
+						max_chamber: Some(0.0),
+						..TemperatureThresholds::default()
+					},
+					..test_config()
+				},
+				"temperature_thresholds",
+			),
+			(
+				PrinterConfig {
+					tags: vec!["garage".to_string(), "".to_string()],
+					..test_config()
+				},
+				"tags",
+			),
+		];
+
+		for (config, field) in cases {
+			let error = config.validate().expect_err(field);
+			assert_eq!(error.field, field);
+			assert!(error.to_string().starts_with(&format!("{field}: ")));
+		}
+	}
+
+	#[test]
+	fn test_export_configs_redacts_access_codes() {
+		let configs = vec![
+			PrinterConfig {
+				id: "b".to_string(),
+				name: "Workshop".to_string(),
+				..test_config()
+			},
+			test_config(),
+		];
+
+		let exported = MqttService::export_configs(configs.clone(), false).unwrap();
+		let parsed: Vec<PrinterConfig> = serde_json::from_str(&exported).unwrap();
+		assert_eq!(parsed.len(), 2);
+		assert_eq!(parsed[0].name, "Test Printer");
+		assert!(parsed.iter().all(|config| config.access_code.is_empty()));
+		// A redacted entry can't be imported as-is
+		assert_eq!(parsed[0].validate().unwrap_err().field, "access_code");
+
+		let exported = MqttService::export_configs(configs, true).unwrap();
+		let parsed: Vec<PrinterConfig> = serde_json::from_str(&exported).unwrap();
+		assert!(parsed.iter().all(|config| config.validate().is_ok()));
+	}
+
+	#[test]
+	fn test_capabilities_for_model() {
+		let x1e = PrinterCapabilities::for_model("X1E");
+		assert!(x1e.has_chamber_fan && x1e.has_chamber_temp);
+		assert_eq!(x1e.max_nozzle_temp, 320);
+
+		assert!(x1e.has_spaghetti_detection && x1e.has_first_layer_inspection);
+
+		let p1s = PrinterCapabilities::for_model("P1S");
+		assert!(p1s.has_chamber_fan && !p1s.has_chamber_temp);
+		assert!(p1s.has_spaghetti_detection && !p1s.has_first_layer_inspection);
+		assert!(!PrinterCapabilities::for_model("P1P").has_spaghetti_detection);
+
+		for model in ["P1P", "A1", "A1-mini", "a1 mini"] {
+			let capabilities = PrinterCapabilities::for_model(model);
+			assert!(!capabilities.has_chamber_fan, "{model}");
+			assert!(capabilities.has_ams, "{model}");
+		}
+
+		assert!(PrinterCapabilities::for_model("").has_chamber_fan);
+		assert_eq!(
+			Printer::new(&test_config()).capabilities,
+			PrinterCapabilities::for_model("P1P")
+		);
+	}
+
+	#[test]
+	fn test_config_port_defaults_when_absent() {
+		let config: PrinterConfig = serde_json::from_value(serde_json::json!({
+			"id": "test-printer",
+			"name": "Test Printer",
+			"model": "P1P",
+			"ip": "192.168.1.50",
+			"access_code": "12345678",
+			"serial": "01S00A000000000"
+		}))
+		.expect("config without a port should deserialize");
+		assert_eq!(config.port, None);
+		assert_eq!(config.mqtt_port(), 8883);
+		assert_eq!(config.poll_interval_secs, None);
+		assert_eq!(config.keep_alive(), Duration::from_secs(60));
+		assert_eq!(config.max_reconnect_attempts, None);
+		assert_eq!(config.username(), "bblp");
+
+		let config = PrinterConfig {
+			port: Some(1883),
+			username: Some("bridge".to_string()),
+			..test_config()
+		};
+		assert_eq!(config.mqtt_port(), 1883);
+		assert_eq!(config.username(), "bridge");
+		assert!(PrinterConfig {
+			username: Some(" ".to_string()),
+			..test_config()
+		}
+		.validate()
+		.is_err());
+	}
+
+	#[test]
+	fn test_tls_mode_defaults_to_insecure() {
+		let config: PrinterConfig = serde_json::from_value(serde_json::json!({
+			"id": "test-printer",
+			"name": "Test Printer",
+			"model": "P1P",
+			"ip": "192.168.1.50",
+			"access_code": "12345678",
+			"serial": "01S00A000000000"
+		}))
+		.unwrap();
+		assert_eq!(config.tls_mode, TlsMode::InsecureSkipVerify);
+
+		let config: PrinterConfig = serde_json::from_value(serde_json::json!({
+			"id": "test-printer",
+			"name": "Test Printer",
+			"model": "P1P",
+			"ip": "192.168.1.50",
+			"access_code": "12345678",
+			"serial": "01S00A000000000",
+			"tls_mode": { "verify_with_ca": "/etc/pulseprint/ca.pem" }
+		}))
+		.unwrap();
+		assert_eq!(
+			config.tls_mode,
+			TlsMode::VerifyWithCa(PathBuf::from("/etc/pulseprint/ca.pem"))
+		);
+	}
+
+	#[test]
+	fn test_build_tls_config_with_ca() {
+		let ca_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/test_ca.pem");
+		assert!(MqttService::build_tls_config(&TlsMode::InsecureSkipVerify).is_ok());
+		assert!(MqttService::build_tls_config(&TlsMode::VerifyWithCa(ca_path)).is_ok());
+	}
+
+	#[test]
+	fn test_insecure_verifier_uses_config_provider() {
+		assert!(MqttService::build_tls_config(&TlsMode::InsecureSkipVerify).is_ok());
+
+		let provider = crypto_provider();
+		assert!(Arc::ptr_eq(&provider, &crypto_provider()));
+
+		let schemes = InsecureVerifier::new(provider.clone()).supported_verify_schemes();
+		assert!(!schemes.is_empty());
+		assert_eq!(
+			schemes,
+			provider
+				.signature_verification_algorithms
+				.supported_schemes()
+		);
+	}
+
+	#[test]
+	fn test_build_tls_config_rejects_bad_ca() {
+		let missing = TlsMode::VerifyWithCa(PathBuf::from("/nonexistent/ca.pem"));
+		assert!(MqttService::build_tls_config(&missing).is_err());
+
+		let mut empty = tempfile::NamedTempFile::new().unwrap();
+		std::io::Write::write_all(&mut empty, b"not a certificate\n").unwrap();
+		let empty = TlsMode::VerifyWithCa(empty.path().to_path_buf());
+		let error = MqttService::build_tls_config(&empty).unwrap_err();
+		assert!(error.to_string().starts_with("No certificates found"));
+	}
+
+	#[test]
+	fn test_parse_wifi_signal() {
+		assert_eq!(parse_wifi_signal(&serde_json::json!("-55dBm")), Some(-55));
+		assert_eq!(
+			parse_wifi_signal(&serde_json::json!(" -61 dBm ")),
+			Some(-61)
+		);
+		assert_eq!(parse_wifi_signal(&serde_json::json!(-70)), Some(-70));
+		assert_eq!(parse_wifi_signal(&serde_json::json!("")), None);
+		assert_eq!(parse_wifi_signal(&serde_json::json!("weak")), None);
+		assert_eq!(parse_wifi_signal(&serde_json::json!(null)), None);
+	}
+
+	#[test]
+	fn test_apply_message_parses_wifi_signal() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/x1c_printing.json")),
+		);
+		assert_eq!(printer.wifi_signal, Some(-45));
+
+		MqttService::apply_message(&mut printer, &serde_json::json!({ "print": {} }));
+		assert_eq!(printer.wifi_signal, None);
+	}
+
+	#[test]
+	fn test_heating_state_from_temps() {
+		let cases = [
+			(25.0, 0.0, HeatingState::Off),
+			(180.0, 0.0, HeatingState::Off),
+			(120.0, 220.0, HeatingState::Heating),
+			(217.9, 220.0, HeatingState::Heating),
+			(218.0, 220.0, HeatingState::Stable),
+			(222.0, 220.0, HeatingState::Stable),
+			(222.1, 220.0, HeatingState::Cooling),
+		];
+
+		for (current, target, expected) in cases {
+			assert_eq!(
+				HeatingState::from_temps(current, target),
+				expected,
+				"{current} -> {target}"
+			);
+		}
+	}
+
+	#[test]
+	fn test_apply_message_sets_heating_state() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/p1p_printing.json")),
+		);
+		assert_eq!(printer.temperatures.nozzle_target, 220.0);
+		assert_eq!(printer.temperatures.nozzle_state, HeatingState::Stable);
+		assert_eq!(printer.temperatures.bed_state, HeatingState::Stable);
+
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/p1p_idle.json")),
+		);
+		assert_eq!(printer.temperatures.nozzle_state, HeatingState::Off);
+		assert_eq!(printer.temperatures.bed_state, HeatingState::Off);
+	}
+
+	#[test]
+	fn test_apply_message_parses_ams_units() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/x1c_printing.json")),
+		);
+		assert_eq!(printer.ams.len(), 1);
+		let unit = &printer.ams[0];
+		assert_eq!(unit.humidity, Some(4));
+		assert_eq!(unit.temperature, Some(27.4));
+		assert_eq!(unit.trays.len(), 2);
+		assert_eq!(unit.trays[1].filament_type.as_deref(), Some("PETG"));
+		assert_eq!(unit.trays[1].remaining, Some(12));
+		assert_eq!(printer.active_tray, Some(0));
+		assert!(!printer.external_spool_active);
+
+		// Chained units, one without a temperature sensor and with an empty slot
+		MqttService::apply_message(
+			&mut printer,
+			&serde_json::json!({ "print": { "ams": { "ams": [
+				{ "id": "0", "humidity": "2", "temp": "24.1", "tray": [ { "id": "0", "tray_type": "PLA" } ] },
+				{ "id": "1", "humidity": "5", "temp": "0.0", "tray": [ { "id": "0" }, { "id": "1", "tray_type": "" } ] }
+			], "tray_now": "255" } } }),
+		);
+		assert_eq!(printer.ams.len(), 2);
+		assert_eq!(printer.active_tray, None);
+		assert!(printer.external_spool_active);
+		assert_eq!(printer.ams[1].humidity, Some(5));
+		assert_eq!(printer.ams[1].temperature, None);
+		assert!(printer.ams[1]
+			.trays
+			.iter()
+			.all(|tray| tray.filament_type.is_none()));
+
+		// No AMS attached
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/p1p_idle.json")),
+		);
+		assert!(printer.ams.is_empty());
+	}
+
+	#[test]
+	fn test_filament_slots_span_units_and_external_spool() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/x1c_two_ams.json")),
+		);
+		let slots = &printer.filament_slots;
+		assert_eq!(
+			slots.iter().map(|slot| slot.index).collect::<Vec<_>>(),
+			[0, 1, 2, 3, 4, 5, 6, 7, FilamentSlot::EXTERNAL_SPOOL_INDEX]
+		);
+
+		let active = slots
+			.iter()
+			.find(|slot| Some(slot.index) == printer.active_tray)
+			.expect("active tray is in the list");
+		assert_eq!((active.ams_id, active.tray_id), (Some(1), 1));
+		assert_eq!(active.filament_type.as_deref(), Some("PLA-S"));
+		assert_eq!(slots[2].filament_type, None);
+
+		let external = slots.last().unwrap();
+		assert_eq!(external.ams_id, None);
+		assert_eq!(external.filament_type.as_deref(), Some("TPU"));
+		// Non-RFID spools report -1
+		assert_eq!(external.remaining, None);
+
+		// Nothing to list without an AMS or external spool report
+		MqttService::apply_message(&mut printer, &serde_json::json!({ "print": {} }));
+		assert!(printer.filament_slots.is_empty());
+	}
+
+	#[test]
+	fn test_check_filament_change() {
+		let mut printer = test_printer();
+		printer.status = PrinterStatus::Idle;
+		MqttService::apply_message(
+			&mut printer,
+			&serde_json::json!({ "print": { "ams": { "ams": [
+				{ "id": "0", "tray": [ { "id": "0" }, { "id": "1" } ] },
+				{ "id": "1", "tray": [ { "id": "2" } ] }
+			] } } }),
+		);
+
+		assert!(MqttService::check_filament_change(&printer, Some(1)).is_ok());
+		assert!(MqttService::check_filament_change(&printer, Some(6)).is_ok());
+		assert!(MqttService::check_filament_change(&printer, Some(2)).is_err());
+		assert!(MqttService::check_filament_change(&printer, None).is_ok());
+
+		printer.status = PrinterStatus::Printing;
+		assert!(MqttService::check_filament_change(&printer, Some(1)).is_err());
+		assert!(MqttService::check_filament_change(&printer, None).is_err());
+
+		printer.status = PrinterStatus::Idle;
+		printer.ams.clear();
+		assert!(MqttService::check_filament_change(&printer, Some(0)).is_err());
+	}
+
+	#[test]
+	fn test_calibration_command() {
+		let options = CalibrationOptions {
+			bed_level: true,
+			vibration: true,
+			motor_noise: false,
+		};
+		assert_eq!(options.bitmask(), 0b0110);
+		assert_eq!(CalibrationOptions::default().bitmask(), 0);
+
+		let payload = MqttService::build_command_payload(
+			&PrintCommand::with_param(
+				"calibration",
+				serde_json::json!({ "option": options.bitmask() }),
+			),
+			"7",
+		)
+		.unwrap();
+		assert_eq!(
+			payload,
+			serde_json::json!({
+				"print": { "command": "calibration", "option": 6, "sequence_id": "7" }
+			})
+		);
+	}
+
+	#[tokio::test]
+	async fn test_validate_command_agrees_with_sending() {
+		let service = MqttService::with_events(Arc::new(RecordedEvents::default()));
+		let printer = Printer {
+			status: PrinterStatus::Paused,
+			..test_printer()
+		};
+		let printer_id = printer.id.clone();
+		let options = MqttOptions::new("pulseprint_test", "127.0.0.1", 8883);
+		let (client, _event_loop) = AsyncClient::new(options, 10);
+		service
+			.printer_connections
+			.write()
+			.await
+			.insert(printer_id.clone(), client);
+		service
+			.printer_states
+			.write()
+			.await
+			.insert(printer_id.clone(), Arc::new(printer));
+
+		let skip = PrintCommand::with_param("skip_objects", serde_json::json!([3]));
+		assert!(service.validate_command(&printer_id, &skip).await.is_err());
+		assert!(service.skip_objects(&printer_id, vec![3]).await.is_err());
+
+		// A P1P has no spaghetti detection
+		let detection =
+			PrintCommand::with_param("xcam_control_set", serde_json::json!({ "control": true }));
+		assert!(service
+			.validate_command(&printer_id, &detection)
+			.await
+			.is_err());
+		assert!(service.set_ai_detection(&printer_id, true).await.is_err());
+
+		// No AMS reported
+		let load = PrintCommand::with_param(
+			"ams_change_filament",
+			serde_json::json!({ "target": 1, "curr_temp": 25, "tar_temp": 220 }),
+		);
+		assert!(service.validate_command(&printer_id, &load).await.is_err());
+		assert!(service.change_filament(&printer_id, 1, 220).await.is_err());
+
+		MqttService::update_printer_status(
+			&service.printer_states,
+			&service.events,
+			&printer_id,
+			|printer| {
+				printer.status = PrinterStatus::Printing;
+			},
+		)
+		.await;
+		assert!(service.validate_command(&printer_id, &skip).await.is_ok());
+		assert!(service.skip_objects(&printer_id, vec![3]).await.is_ok());
+	}
+
+	#[test]
+	fn test_command_allowed_statuses() {
+		let allowed = |action: &str, status: PrinterStatus| {
+			PrintCommand::new(action)
+				.allowed_statuses()
+				.map_or(true, |statuses| statuses.contains(&status))
+		};
+		assert!(allowed("pause", PrinterStatus::Printing));
+		assert!(!allowed("pause", PrinterStatus::Paused));
+		assert!(allowed("resume", PrinterStatus::Error));
+		assert!(!allowed("resume", PrinterStatus::Idle));
+		assert!(allowed("stop", PrinterStatus::Paused));
+		assert!(allowed("stop", PrinterStatus::Preparing));
+		assert!(!allowed("pause", PrinterStatus::Preparing));
+		assert!(!allowed("stop", PrinterStatus::Idle));
+		assert!(!allowed("project_file", PrinterStatus::Printing));
+		assert!(!allowed("skip_objects", PrinterStatus::Paused));
+		// Not tied to a job
+		assert!(allowed("get_status", PrinterStatus::Offline));
+	}
+
+	#[test]
+	fn test_start_print_command() {
+		let options = StartPrintOptions {
+			plate: Some(2),
+			bed_leveling: true,
+			use_ams: true,
+			..Default::default()
+		};
+		let payload = MqttService::build_command_payload(
+			&PrintCommand::with_param(
+				"project_file",
+				serde_json::json!({ "file": "/cache/benchy.gcode.3mf", "options": options }),
+			),
+			"7",
+		)
+		.unwrap();
+		let print = &payload["print"];
+		assert_eq!(print["command"], "project_file");
+		assert_eq!(print["param"], "Metadata/plate_2.gcode");
+		assert_eq!(print["url"], "file:///sdcard/cache/benchy.gcode.3mf");
+		assert_eq!(print["subtask_name"], "benchy");
+		assert_eq!(print["bed_levelling"], true);
+		assert_eq!(print["flow_cali"], false);
+		assert_eq!(print["use_ams"], true);
+		assert_eq!(print["sequence_id"], "7");
+
+		// Options are optional, the file isn't
+		let payload = MqttService::build_command_payload(
+			&PrintCommand::with_param("project_file", serde_json::json!({ "file": "cube.3mf" })),
+			"8",
+		)
+		.unwrap();
+		assert_eq!(payload["print"]["param"], "Metadata/plate_1.gcode");
+		assert_eq!(payload["print"]["subtask_name"], "cube");
+		assert!(MqttService::build_command_payload(&PrintCommand::new("project_file"), "9").is_err());
+	}
+
+	#[test]
+	fn test_calibration_stage_from_report() {
+		let mut printer = test_printer();
+		let mut data = serde_json::json!({
+			"print": { "gcode_state": "IDLE", "stg_cur": 1 }
+		});
+		MqttService::apply_message(&mut printer, &data);
+		assert_eq!(
+			printer.calibration_stage.as_deref(),
+			Some("Auto bed leveling")
+		);
+
+		// Motor noise calibration runs in two steps
+		for (stage, name) in [(25, "Motor noise calibration"), (31, "Motor noise check")] {
+			data["print"]["stg_cur"] = serde_json::json!(stage);
+			MqttService::apply_message(&mut printer, &data);
+			assert_eq!(printer.calibration_stage.as_deref(), Some(name));
+		}
+
+		data["print"]["stg_cur"] = serde_json::json!(0);
+		MqttService::apply_message(&mut printer, &data);
+		assert_eq!(printer.calibration_stage, None);
+	}
+
+	#[test]
+	fn test_print_start_resets_for_new_job() {
+		let mut printer = test_printer();
+		let mut report = serde_json::json!({
+			"print": {
+				"gcode_state": "RUNNING",
+				"subtask_name": "benchy",
+				"mc_percent": 40,
+				"mc_remaining_time": 20,
+				"layer_num": 60,
+				"total_layer_num": 150
+			}
+		});
+		MqttService::apply_message(&mut printer, &report);
+		let started = printer.print_start.expect("start recorded when printing");
+		assert_eq!(printer.print.as_ref().unwrap().elapsed_seconds, Some(0));
+
+		// Same job progressing keeps its start time
+		let earlier = started - chrono::Duration::minutes(30);
+		printer.print_start = Some(earlier);
+		report["print"]["layer_num"] = serde_json::json!(61);
+		MqttService::apply_message(&mut printer, &report);
+		assert_eq!(printer.print_start, Some(earlier));
+		assert!(printer.print.as_ref().unwrap().elapsed_seconds.unwrap() >= 30 * 60);
+
+		// Reprinting the same file starts over from layer 1
+		report["print"]["layer_num"] = serde_json::json!(1);
+		MqttService::apply_message(&mut printer, &report);
+		assert!(printer.print_start.unwrap() > earlier);
+
+		// A different job starts over too
+		printer.print_start = Some(earlier);
+		report["print"]["layer_num"] = serde_json::json!(2);
+		report["print"]["subtask_name"] = serde_json::json!("cable_clips");
+		MqttService::apply_message(&mut printer, &report);
+		assert!(printer.print_start.unwrap() > earlier);
+
+		let idle = serde_json::json!({
+			"print": { "gcode_state": "FINISH", "mc_percent": 100, "mc_remaining_time": 0 }
+		});
+		MqttService::apply_message(&mut printer, &idle);
+		assert_eq!(printer.status, PrinterStatus::Idle);
+		assert_eq!(printer.print_start, None);
+	}
+
+	#[test]
+	fn test_mock_reports_drive_status() {
+		let options = MockOptions {
+			duration_secs: 100,
+			..Default::default()
+		};
+		let mut printer = test_printer();
+
+		MqttService::apply_message(&mut printer, &options.report(Duration::ZERO));
+		assert_eq!(printer.status, PrinterStatus::Preparing);
+
+		MqttService::apply_message(&mut printer, &options.report(Duration::from_secs(50)));
+		assert_eq!(printer.status, PrinterStatus::Printing);
+		let job = printer.print.as_ref().unwrap();
+		assert_eq!(job.file_name, crate::mock::JOB_NAME);
+		assert_eq!(job.layer_current, 89);
+
+		MqttService::apply_message(&mut printer, &options.report(Duration::from_secs(100)));
+		assert_eq!(printer.status, PrinterStatus::Idle);
+	}
+
+	#[test]
+	fn test_pause_reason() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/x1c_paused.json")),
+		);
+		assert_eq!(printer.status, PrinterStatus::Paused);
+		assert_eq!(
+			printer.print.as_ref().unwrap().pause_reason.as_deref(),
+			Some("user_requested")
+		);
+
+		// A runout shows as an error until dismissed, then as a paused print
+		let runout = fixture(include_str!("../tests/fixtures/p1p_filament_runout.json"));
+		let mut printer = test_printer();
+		MqttService::apply_message(&mut printer, &runout);
+		assert_eq!(printer.status, PrinterStatus::Error);
+		assert_eq!(printer.print.as_ref().unwrap().pause_reason, None);
+		printer.acknowledged_error = Some((117473287, 1203));
+		MqttService::apply_message(&mut printer, &runout);
+		assert_eq!(printer.status, PrinterStatus::Paused);
+		assert_eq!(
+			printer.print.as_ref().unwrap().pause_reason.as_deref(),
+			Some("filament_runout")
+		);
+
+		let mut printing = fixture(include_str!("../tests/fixtures/p1p_printing.json"));
+		printing["print"]["stg_cur"] = serde_json::json!(16);
+		MqttService::apply_message(&mut printer, &printing);
+		assert_eq!(printer.status, PrinterStatus::Printing);
+		assert_eq!(printer.print.as_ref().unwrap().pause_reason, None);
+	}
+
+	#[test]
+	fn test_status_jitter_is_bounded_and_stable() {
+		let ids: Vec<String> = (0..20).map(|i| format!("printer-{i}")).collect();
+		let delays: Vec<Duration> = ids
+			.iter()
+			.map(|id| MqttService::status_jitter(id))
+			.collect();
+
+		assert!(delays.iter().all(|delay| *delay < MAX_STATUS_JITTER));
+		assert_eq!(MqttService::status_jitter("printer-3"), delays[3]);
+		// Not everyone lands in the same slot
+		assert!(delays.iter().any(|delay| *delay != delays[0]));
+	}
+
+	#[test]
+	fn test_camera_status() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/x1c_printing.json")),
+		);
+		assert_eq!(
+			printer.camera,
+			Some(CameraStatus {
+				recording: true,
+				timelapse: false,
+				resolution: Some("1080p".to_string()),
+			})
+		);
+
+		let no_camera = serde_json::json!({ "ipcam": { "ipcam_dev": "0" } });
+		assert_eq!(CameraStatus::from_print_data(&no_camera), None);
+		assert_eq!(CameraStatus::from_print_data(&serde_json::json!({})), None);
+	}
+
+	#[test]
+	fn test_storage_info_from_print_data() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/p1p_idle.json")),
+		);
+		assert_eq!(
+			printer.storage,
+			Some(StorageInfo {
+				present: true,
+				free_mb: None
+			})
+		);
+
+		let print_data = serde_json::json!({ "sdcard": true, "storage": { "free": 20480 } });
+		assert_eq!(
+			StorageInfo::from_print_data(&print_data).and_then(|s| s.free_mb),
+			Some(20480)
+		);
+		assert_eq!(StorageInfo::from_print_data(&serde_json::json!({})), None);
+	}
+
+	#[test]
+	fn test_apply_message_parses_nozzle_details() {
+		let mut printer = test_printer();
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/x1c_printing.json")),
+		);
+		assert_eq!(printer.nozzle_diameter, Some(0.4));
+		assert_eq!(printer.nozzle_type.as_deref(), Some("hardened_steel"));
+
+		// Older firmware omits the nozzle fields entirely
+		MqttService::apply_message(
+			&mut printer,
+			&fixture(include_str!("../tests/fixtures/x1c_paused.json")),
+		);
+		assert_eq!(printer.nozzle_diameter, None);
+		assert_eq!(printer.nozzle_type, None);
+	}
+
+	#[test]
+	fn test_apply_message_parses_bed_type() {
+		let mut printer = test_printer();
+		let mut report = fixture(include_str!("../tests/fixtures/x1c_printing.json"));
+		report["print"]["bed_type"] = serde_json::json!("textured_plate");
+		MqttService::apply_message(&mut printer, &report);
+		assert_eq!(printer.bed_type.as_deref(), Some("Textured PEI"));
+
+		assert_eq!(bed_type_name("pc").as_deref(), Some("Cool Plate"));
+		assert_eq!(
+			bed_type_name("supertack_plate").as_deref(),
+			Some("supertack_plate")
+		);
+		assert_eq!(bed_type_name("auto"), None);
+		assert_eq!(bed_type_name(""), None);
+	}
+
+	#[test]
+	fn test_same_serial_ignores_case_and_whitespace() {
+		assert!(same_serial("01S00A000000000", "01S00A000000000"));
+		assert!(same_serial(" 01s00a000000000\t", "01S00A000000000"));
+		assert!(!same_serial("01S00A000000000", "01S00A000000001"));
+	}
+
+	#[test]
+	fn test_deep_merge_nested_objects() {
+		let base = serde_json::json!({
+			"print": { "nozzle_temper": 210.0, "bed_temper": 60.0, "ipcam": { "timelapse": "enable" } }
+		});
+		let update = serde_json::json!({
+			"print": { "nozzle_temper": 215.5, "ipcam": { "resolution": "1080p" } }
+		});
+
+		let merged = MqttService::deep_merge(base, update);
+		assert_eq!(merged["print"]["nozzle_temper"], 215.5);
+		assert_eq!(merged["print"]["bed_temper"], 60.0);
+		assert_eq!(merged["print"]["ipcam"]["timelapse"], "enable");
+		assert_eq!(merged["print"]["ipcam"]["resolution"], "1080p");
+	}
+
+	#[test]
+	fn test_deep_merge_preserves_subtask_name() {
+		let base = serde_json::json!({ "print": { "subtask_name": "benchy" } });
+
+		let merged = MqttService::deep_merge(
+			base.clone(),
+			serde_json::json!({ "print": { "subtask_name": "" } }),
+		);
+		assert_eq!(merged["print"]["subtask_name"], "benchy");
+
+		let merged = MqttService::deep_merge(
+			base,
+			serde_json::json!({ "print": { "subtask_name": "calibration_cube" } }),
+		);
+		assert_eq!(merged["print"]["subtask_name"], "calibration_cube");
+
+		let merged = MqttService::deep_merge(
+			serde_json::json!({ "print": { "gcode_file": "/sdcard/benchy.gcode.3mf" } }),
+			serde_json::json!({ "print": { "gcode_file": "" } }),
+		);
+		assert_eq!(merged["print"]["gcode_file"], "/sdcard/benchy.gcode.3mf");
+	}
+
+	#[test]
+	fn test_deep_merge_preserves_in_progress_percent() {
+		let merged = MqttService::deep_merge(
+			serde_json::json!({ "print": { "mc_percent": 42 } }),
+			serde_json::json!({ "print": { "mc_percent": 0 } }),
+		);
+		assert_eq!(merged["print"]["mc_percent"], 42);
+
+		// A finished print may be reset to zero for the next job
+		let merged = MqttService::deep_merge(
+			serde_json::json!({ "print": { "mc_percent": 100 } }),
+			serde_json::json!({ "print": { "mc_percent": 0 } }),
+		);
+		assert_eq!(merged["print"]["mc_percent"], 0);
+	}
+
+	#[test]
+	fn test_deep_merge_preserves_remaining_time() {
+		let merged = MqttService::deep_merge(
+			serde_json::json!({ "print": { "mc_remaining_time": 73 } }),
+			serde_json::json!({ "print": { "mc_remaining_time": 0 } }),
+		);
+		assert_eq!(merged["print"]["mc_remaining_time"], 73);
+
+		let merged = MqttService::deep_merge(
+			serde_json::json!({ "print": { "mc_remaining_time": 73 } }),
+			serde_json::json!({ "print": { "mc_remaining_time": 72 } }),
+		);
+		assert_eq!(merged["print"]["mc_remaining_time"], 72);
+	}
+
+	#[test]
+	fn test_deep_merge_keyed_arrays_replace_by_id() {
+		let base = serde_json::json!({
+			"print": { "ams": { "ams": [
+				{ "id": "0", "humidity": "4", "tray": [
+					{ "id": "0", "tray_type": "PLA", "remain": 80 },
+					{ "id": "1", "tray_type": "PETG", "remain": 50 }
+				] },
+				{ "id": "1", "humidity": "3", "tray": [] }
+			] } }
+		});
+		let update = serde_json::json!({
+			"print": { "ams": { "ams": [
+				{ "id": "0", "humidity": "4", "tray": [
+					{ "id": "0", "tray_type": "PLA", "remain": 80 },
+					{ "id": "1", "tray_type": "PETG", "remain": 45 }
+				] }
+			] } }
+		});
+
+		let merged = MqttService::deep_merge(base, update);
+		let units = merged["print"]["ams"]["ams"].as_array().unwrap();
+		assert_eq!(units.len(), 2, "units missing from the update are kept");
+		assert_eq!(units[0]["tray"][1]["tray_type"], "PETG");
+		assert_eq!(units[0]["tray"][1]["remain"], 45);
+		assert_eq!(units[1]["humidity"], "3");
+	}
+
+	#[test]
+	fn test_deep_merge_empties_pulled_tray() {
+		let base = serde_json::json!([
+			{ "id": "2", "tray_type": "PLA", "tray_color": "FF0000FF", "remain": 60 }
+		]);
+		// A pulled spool leaves only the slot's id
+		let merged = MqttService::deep_merge(base, serde_json::json!([{ "id": "2" }]));
+		assert_eq!(merged, serde_json::json!([{ "id": "2" }]));
+		assert!(merged[0].get("tray_type").is_none());
+	}
+
+	#[test]
+	fn test_full_report_drops_detached_ams_units() {
+		let two_units = serde_json::json!({ "print": { "msg": 0, "ams": { "ams": [
+			{ "id": "0", "tray": [ { "id": "0", "tray_type": "PLA" } ] },
+			{ "id": "1", "tray": [ { "id": "0", "tray_type": "PETG" } ] }
+		] } } });
+		let state = MqttService::merge_report(serde_json::json!({}), two_units);
+
+		// A delta leaves the other unit alone
+		let delta = serde_json::json!({ "print": { "msg": 1, "ams": { "ams": [
+			{ "id": "0", "tray": [ { "id": "0", "tray_type": "ABS" } ] }
+		] } } });
+		let state = MqttService::merge_report(state, delta);
+		assert_eq!(state["print"]["ams"]["ams"].as_array().unwrap().len(), 2);
+
+		let one_unit = serde_json::json!({ "print": { "msg": 0, "ams": { "ams": [
+			{ "id": "0", "tray": [ { "id": "0", "tray_type": "ABS" } ] }
+		] } } });
+		let state = MqttService::merge_report(state, one_unit);
+		let units = state["print"]["ams"]["ams"].as_array().unwrap();
+		assert_eq!(units.len(), 1);
+		assert_eq!(units[0]["id"], "0");
+	}
+
+	#[test]
+	fn test_deep_merge_keyed_arrays_append_new_ids() {
+		let merged = MqttService::deep_merge(
+			serde_json::json!([{ "id": "0", "humidity": "4" }]),
+			serde_json::json!([{ "id": "1", "humidity": "2" }]),
+		);
+		assert_eq!(
+			merged,
+			serde_json::json!([{ "id": "0", "humidity": "4" }, { "id": "1", "humidity": "2" }])
+		);
+	}
+
+	#[test]
+	fn test_deep_merge_unkeyed_arrays_replace() {
+		let base = serde_json::json!({
+			"print": { "hms": [ { "attr": 117440768, "code": 131074 } ] }
+		});
+
+		let merged = MqttService::deep_merge(
+			base.clone(),
+			serde_json::json!({ "print": { "hms": [ { "attr": 50335744, "code": 65543 } ] } }),
+		);
+		assert_eq!(
+			merged["print"]["hms"],
+			serde_json::json!([ { "attr": 50335744, "code": 65543 } ])
+		);
+
+		// An empty list means the errors cleared
+		let merged = MqttService::deep_merge(base, serde_json::json!({ "print": { "hms": [] } }));
+		assert_eq!(merged["print"]["hms"], serde_json::json!([]));
+	}
+
+	#[test]
+	fn test_merge_report_drops_unused_keys() {
+		let merged = MqttService::merge_report(
+			serde_json::json!({}),
+			serde_json::json!({
+				"print": {
+					"gcode_state": "RUNNING",
+					"mc_percent": 12,
+					"upgrade_state": { "status": "IDLE", "new_version_state": 2 },
+					"lights_report": [{ "node": "chamber_light", "mode": "on" }]
+				},
+				"info": { "command": "get_version" },
+				"system": { "command": "ledctrl" }
+			}),
+		);
+
+		assert_eq!(
+			merged,
+			serde_json::json!({
+				"print": { "gcode_state": "RUNNING", "mc_percent": 12 },
+				"info": { "command": "get_version" }
+			})
+		);
+	}
+
+	#[test]
+	fn test_subscription_topics() {
+		let config = PrinterConfig {
+			extra_topics: vec![
+				"device/{serial}/report".to_string(),
+				"device/{serial}/ams".to_string(),
+			],
+			..test_config()
+		};
+
+		assert_eq!(
+			config.subscription_topics(),
+			[
+				"device/01S00A000000000/report",
+				"device/01S00A000000000/report/info",
+				"device/01S00A000000000/ams",
+			]
+		);
+	}
+
+	#[test]
+	fn test_info_topic_does_not_clobber_print_state() {
+		let config = test_config();
+		let state = MqttService::merge_report(
+			serde_json::json!({}),
+			MqttService::scope_to_topic(
+				&config,
+				"device/01S00A000000000/report",
+				serde_json::json!({ "print": { "gcode_state": "RUNNING", "mc_percent": 40 } }),
+			),
+		);
+
+		let state = MqttService::merge_report(
+			state,
+			MqttService::scope_to_topic(
+				&config,
+				"device/01S00A000000000/report/info",
+				serde_json::json!({
+					"print": { "gcode_state": "IDLE" },
+					"info": { "command": "get_version", "module": [] }
+				}),
+			),
+		);
+
+		assert_eq!(state["print"]["gcode_state"], "RUNNING");
+		assert_eq!(state["print"]["mc_percent"], 40);
+		assert_eq!(state["info"]["command"], "get_version");
+	}
+
+	#[test]
+	fn test_merge_report_keeps_everything_status_detection_reads() {
+		let reports = [
+			include_str!("../tests/fixtures/p1p_idle.json"),
+			include_str!("../tests/fixtures/p1p_printing.json"),
+			include_str!("../tests/fixtures/x1c_printing.json"),
+			include_str!("../tests/fixtures/x1c_paused.json"),
+			include_str!("../tests/fixtures/p1p_filament_runout.json"),
+		];
+
+		for report in reports {
+			let report = fixture(report);
+			let pruned = MqttService::merge_report(serde_json::json!({}), report.clone());
+
+			let mut from_raw = test_printer();
+			let mut from_pruned = from_raw.clone();
+			MqttService::apply_message(&mut from_raw, &report);
+			MqttService::apply_message(&mut from_pruned, &pruned);
+
+			// The job clock reads the wall clock, so only compare whether it started
+			for printer in [&mut from_raw, &mut from_pruned] {
+				printer.print_start = printer.print_start.map(|_| DateTime::<Utc>::MIN_UTC);
+				if let Some(job) = printer.print.as_mut() {
+					job.elapsed_seconds = job.elapsed_seconds.map(|_| 0);
+				}
+			}
+
+			assert_eq!(
+				serde_json::to_value(&from_pruned).unwrap(),
+				serde_json::to_value(&from_raw).unwrap()
+			);
+		}
+	}
+
+	#[test]
+	fn test_connection_error_events_are_rate_limited() {
+		let start = Instant::now();
+		assert!(MqttService::should_emit_connection_error(
+			&None,
+			"I/O: refused",
+			start
+		));
+
+		let last = Some((start, "I/O: refused".to_string()));
+		assert!(!MqttService::should_emit_connection_error(
+			&last,
+			"I/O: refused",
+			start + Duration::from_secs(5)
+		));
+		assert!(MqttService::should_emit_connection_error(
+			&last,
+			"Network timeout",
+			start + Duration::from_secs(5)
+		));
+		assert!(MqttService::should_emit_connection_error(
+			&last,
+			"I/O: refused",
+			start + CONNECTION_ERROR_EVENT_INTERVAL
+		));
+	}
+
+	#[test]
+	fn test_connection_outcome() {
+		let mut printer = Printer::new(&test_config());
+		assert!(MqttService::connection_outcome("p1", Some(&printer)).is_none());
+		printer.connection_state = ConnectionState::Stale;
+		assert!(MqttService::connection_outcome("p1", Some(&printer)).is_none());
+
+		printer.connection_state = ConnectionState::Connected;
+		assert!(MqttService::connection_outcome("p1", Some(&printer))
+			.unwrap()
+			.is_ok());
+
+		for state in [
+			ConnectionState::Failed,
+			ConnectionState::AuthFailed,
+			ConnectionState::Unreachable,
+		] {
+			printer.connection_state = state;
+			assert!(MqttService::connection_outcome("p1", Some(&printer))
+				.unwrap()
+				.is_err());
+		}
+		assert!(MqttService::connection_outcome("p1", None)
+			.unwrap()
+			.is_err());
+	}
+
+	#[test]
+	fn test_connection_state_after_error() {
+		let refused =
+			rumqttc::ConnectionError::ConnectionRefused(rumqttc::ConnectReturnCode::BadUserNamePassword);
+		assert_eq!(
+			ConnectionState::after_error(&refused, false),
+			ConnectionState::AuthFailed
+		);
+
+		let dropped = rumqttc::ConnectionError::NetworkTimeout;
+		assert_eq!(
+			ConnectionState::after_error(&dropped, true),
+			ConnectionState::Reconnecting
+		);
+		assert_eq!(
+			ConnectionState::after_error(&dropped, false),
+			ConnectionState::Failed
+		);
+	}
+
+	#[test]
+	fn test_connection_state_serializes_as_legacy_strings() {
+		let cases = [
+			(ConnectionState::Connecting, "connecting"),
+			(ConnectionState::Connected, "connected"),
+			(ConnectionState::Failed, "failed"),
+			(ConnectionState::AuthFailed, "auth_failed"),
+			(ConnectionState::Disabled, "paused"),
+			(ConnectionState::Unreachable, "unreachable"),
+		];
+		for (state, expected) in cases {
+			assert_eq!(serde_json::to_value(state).unwrap(), expected);
+			assert_eq!(
+				serde_json::from_value::<ConnectionState>(expected.into()).unwrap(),
+				state
+			);
+		}
+	}
+
+	#[test]
+	fn test_connection_error_kind() {
+		assert_eq!(
+			MqttService::connection_error_kind(&rumqttc::ConnectionError::NetworkTimeout),
+			"NetworkTimeout"
+		);
+		let io = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+		assert_eq!(
+			MqttService::connection_error_kind(&rumqttc::ConnectionError::Io(io)),
+			"Io"
+		);
+	}
+
+	#[test]
+	fn test_apply_message_parses_firmware_version() {
+		let mut printer = test_printer();
+		let state = MqttService::merge_report(
+			serde_json::json!({}),
+			serde_json::json!({ "info": {
+				"command": "get_version",
+				"module": [
+					{ "name": "mc", "sw_ver": "00.00.28.55", "hw_ver": "MC07" },
+					{ "name": "ota", "sw_ver": "01.07.00.00", "product_name": "X1 Carbon" }
+				]
+			} }),
+		);
+		MqttService::apply_message(&mut printer, &state);
+		assert_eq!(printer.firmware_version.as_deref(), Some("01.07.00.00"));
+		assert_eq!(printer.product_name.as_deref(), Some("X1 Carbon"));
+
+		// Later reports keep the version in the accumulated state
+		let state = MqttService::merge_report(
+			state,
+			fixture(include_str!("../tests/fixtures/x1c_printing.json")),
+		);
+		MqttService::apply_message(&mut printer, &state);
+		assert_eq!(printer.firmware_version.as_deref(), Some("01.07.00.00"));
+
+		// And the parsed value survives even a state without version info
+		MqttService::apply_message(&mut printer, &serde_json::json!({ "print": {} }));
+		assert_eq!(printer.firmware_version.as_deref(), Some("01.07.00.00"));
+	}
+
+	#[test]
+	fn test_apply_message_without_print_object_is_noop() {
+		let mut printer = test_printer();
+		printer.status = PrinterStatus::Idle;
+
+		let change = MqttService::apply_message(&mut printer, &serde_json::json!({ "info": {} }));
+		assert!(!change.changed());
+		assert_eq!(printer.status, PrinterStatus::Idle);
+		assert!(printer.print.is_none());
+	}
+}
@@ -0,0 +1,263 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+
+/// Typed view of the accumulated MQTT state for one printer.
+///
+/// Reports are merged as raw JSON first, since each message only carries the
+/// keys that changed, and the merged result is read through this once per
+/// message. Every field is optional and lenient: a value of the wrong type
+/// reads as missing instead of failing the whole report, as firmware versions
+/// disagree on whether numbers are sent as numbers or strings.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct BambuReport {
+	#[serde(deserialize_with = "lenient")]
+	pub print: Option<PrintReport>,
+	#[serde(deserialize_with = "lenient")]
+	pub info: Option<InfoReport>,
+}
+
+impl BambuReport {
+	pub fn from_value(accumulated: &serde_json::Value) -> Self {
+		Self::deserialize(accumulated).unwrap_or_default()
+	}
+}
+
+/// The `print` object: status, temperatures and job progress.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct PrintReport {
+	#[serde(deserialize_with = "lenient_f64")]
+	pub nozzle_temper: Option<f64>,
+	#[serde(deserialize_with = "lenient_f64")]
+	pub bed_temper: Option<f64>,
+	#[serde(deserialize_with = "lenient_f64")]
+	pub chamber_temper: Option<f64>,
+	#[serde(deserialize_with = "lenient_f64")]
+	pub nozzle_target_temper: Option<f64>,
+	#[serde(deserialize_with = "lenient_f64")]
+	pub bed_target_temper: Option<f64>,
+	#[serde(deserialize_with = "wifi_signal")]
+	pub wifi_signal: Option<i32>,
+	#[serde(deserialize_with = "lenient_f64")]
+	pub nozzle_diameter: Option<f64>,
+	#[serde(deserialize_with = "lenient")]
+	pub nozzle_type: Option<String>,
+	#[serde(deserialize_with = "lenient")]
+	pub bed_type: Option<String>,
+	#[serde(deserialize_with = "lenient")]
+	pub ams: Option<AmsReport>,
+	#[serde(deserialize_with = "lenient")]
+	pub xcam: Option<XcamReport>,
+	#[serde(deserialize_with = "lenient")]
+	pub online: Option<OnlineReport>,
+	#[serde(deserialize_with = "lenient")]
+	pub gcode_state: Option<String>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub print_real: Option<i64>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub mc_remaining_time: Option<i64>,
+	#[serde(deserialize_with = "lenient_f64")]
+	pub mc_percent: Option<f64>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub mc_print_stage: Option<i64>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub mc_print_sub_stage: Option<i64>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub mc_print_error_code: Option<i64>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub layer_num: Option<i64>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub total_layer_num: Option<i64>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub stg_cur: Option<i64>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub print_error: Option<i64>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub fan_gear: Option<i64>,
+	#[serde(deserialize_with = "lenient")]
+	pub subtask_name: Option<String>,
+	#[serde(deserialize_with = "lenient")]
+	pub gcode_file: Option<String>,
+	#[serde(deserialize_with = "lenient")]
+	pub print_type: Option<String>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub spd_lvl: Option<i64>,
+	#[serde(deserialize_with = "lenient_i64")]
+	pub spd_mag: Option<i64>,
+	#[serde(deserialize_with = "lenient")]
+	pub lifecycle: Option<String>,
+	#[serde(deserialize_with = "lenient_f64")]
+	pub weight: Option<f64>,
+	#[serde(deserialize_with = "lenient_f64")]
+	pub length: Option<f64>,
+}
+
+/// The parts of `print.ams` read outside the per-unit parsing.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct AmsReport {
+	#[serde(deserialize_with = "lenient_i64")]
+	pub tray_now: Option<i64>,
+}
+
+/// `print.xcam`: camera-based detection settings.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct XcamReport {
+	#[serde(deserialize_with = "lenient")]
+	pub spaghetti_detector: Option<bool>,
+}
+
+/// `print.online`: the printer's own view of its internal links.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct OnlineReport {
+	#[serde(deserialize_with = "lenient")]
+	pub ahb: Option<bool>,
+	#[serde(deserialize_with = "lenient")]
+	pub rfid: Option<bool>,
+}
+
+/// The `info` object, answered to `get_version`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct InfoReport {
+	#[serde(deserialize_with = "lenient_list")]
+	pub module: Vec<ModuleReport>,
+}
+
+/// One hardware or firmware module from `info.module`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct ModuleReport {
+	#[serde(deserialize_with = "lenient")]
+	pub name: Option<String>,
+	#[serde(deserialize_with = "lenient")]
+	pub sw_ver: Option<String>,
+	#[serde(deserialize_with = "lenient")]
+	pub product_name: Option<String>,
+}
+
+/// Reads an integer that the printer may send either as a JSON number or as
+/// a numeric string (e.g. `"cooling_fan_speed": "15"`).
+pub(crate) fn value_as_i64(value: &serde_json::Value) -> Option<i64> {
+	value
+		.as_i64()
+		.or_else(|| value.as_str().and_then(|s| s.trim().parse().ok()))
+}
+
+/// Reads a float that the printer may send either as a JSON number or as a
+/// numeric string (e.g. `"nozzle_diameter": "0.4"`).
+pub(crate) fn value_as_f64(value: &serde_json::Value) -> Option<f64> {
+	value
+		.as_f64()
+		.or_else(|| value.as_str().and_then(|s| s.trim().parse().ok()))
+}
+
+/// Parses a Wi-Fi signal strength such as `"-55dBm"` into dBm.
+pub(crate) fn parse_wifi_signal(value: &serde_json::Value) -> Option<i32> {
+	if let Some(dbm) = value.as_i64() {
+		return i32::try_from(dbm).ok();
+	}
+
+	let text = value.as_str()?.trim();
+	let number = text
+		.strip_suffix("dBm")
+		.or_else(|| text.strip_suffix("dbm"))
+		.unwrap_or(text);
+	number.trim().parse().ok()
+}
+
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+	D: Deserializer<'de>,
+	T: DeserializeOwned,
+{
+	let value = serde_json::Value::deserialize(deserializer)?;
+	Ok(serde_json::from_value(value).ok())
+}
+
+fn lenient_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+	D: Deserializer<'de>,
+	T: DeserializeOwned,
+{
+	let value = serde_json::Value::deserialize(deserializer)?;
+	let items = match value {
+		serde_json::Value::Array(items) => items,
+		_ => return Ok(Vec::new()),
+	};
+	Ok(
+		items
+			.into_iter()
+			.filter_map(|item| serde_json::from_value(item).ok())
+			.collect(),
+	)
+}
+
+fn lenient_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+	serde_json::Value::deserialize(deserializer).map(|value| value_as_i64(&value))
+}
+
+fn lenient_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+	serde_json::Value::deserialize(deserializer).map(|value| value_as_f64(&value))
+}
+
+fn wifi_signal<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i32>, D::Error> {
+	serde_json::Value::deserialize(deserializer).map(|value| parse_wifi_signal(&value))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_report_reads_fixture() {
+		let fixture = include_str!("../tests/fixtures/p1p_printing.json");
+		let report = BambuReport::from_value(&serde_json::from_str(fixture).unwrap());
+		let print = report.print.expect("fixture has a print object");
+
+		assert_eq!(print.gcode_state.as_deref(), Some("RUNNING"));
+		assert!(print.mc_percent.is_some());
+		assert!(print.nozzle_temper.is_some());
+	}
+
+	#[test]
+	fn test_report_is_lenient_about_types() {
+		let report = BambuReport::from_value(&serde_json::json!({
+			"print": {
+				"nozzle_diameter": "0.4",
+				"layer_num": "12",
+				"mc_percent": "not a number",
+				"gcode_state": 3,
+				"wifi_signal": "-55dBm",
+				"ams": "broken",
+				"xcam": { "spaghetti_detector": true }
+			},
+			"info": { "module": [{ "name": "ota", "sw_ver": "01.07.00.00" }, 7] }
+		}));
+		let print = report.print.unwrap();
+
+		assert_eq!(print.nozzle_diameter, Some(0.4));
+		assert_eq!(print.layer_num, Some(12));
+		assert_eq!(print.mc_percent, None);
+		assert_eq!(print.gcode_state, None);
+		assert_eq!(print.wifi_signal, Some(-55));
+		assert!(print.ams.is_none());
+		assert_eq!(print.xcam.unwrap().spaghetti_detector, Some(true));
+		// A malformed entry is skipped rather than dropping the whole list
+		let modules = report.info.unwrap().module;
+		assert_eq!(modules.len(), 1);
+		assert_eq!(modules[0].sw_ver.as_deref(), Some("01.07.00.00"));
+	}
+
+	#[test]
+	fn test_report_without_print() {
+		let report = BambuReport::from_value(&serde_json::json!({ "info": {} }));
+		assert!(report.print.is_none());
+		assert!(BambuReport::from_value(&serde_json::json!("garbage"))
+			.print
+			.is_none());
+	}
+}
@@ -0,0 +1,219 @@
+//! A minimal MQTT 3.1.1 broker over TLS that stands in for a printer in tests.
+//!
+//! It accepts any client and acknowledges every subscription. Once a client
+//! subscribes to the report topic, it publishes the canned reports there.
+//! Everything the client sends is recorded so tests can assert on it.
+//!
+//! It is hand-rolled rather than an embedded `rumqttd`: that would be a whole
+//! second MQTT stack in the dev dependencies for the handful of packets a
+//! printer connection uses, and the tests need to see each login and publish
+//! exactly as the client sent it, which is simplest with the packets in hand.
+//!
+//! `RecordedEvents` lets the real `MqttService` run against it without a
+//! Tauri app.
+
+use crate::database::{CommandHistory, PrinterStateStore};
+use crate::mqtt::EventSink;
+use anyhow::{anyhow, Result};
+use bytes::BytesMut;
+use rumqttc::mqttbytes::{self, v4};
+use rumqttc::{ConnAck, ConnectReturnCode, Packet, PingResp, Publish, QoS, SubAck};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+
+const MAX_PACKET_SIZE: usize = 1024 * 1024;
+
+/// What clients sent, in order of arrival.
+#[derive(Debug, Default)]
+pub struct BrokerLog {
+	/// `(username, password)` of each CONNECT
+	pub logins: Vec<(String, String)>,
+	pub subscriptions: Vec<String>,
+	/// `(topic, payload)` of each PUBLISH; payloads that aren't JSON are `Null`
+	pub published: Vec<(String, serde_json::Value)>,
+}
+
+pub struct TestBroker {
+	pub port: u16,
+	log: Arc<Mutex<BrokerLog>>,
+	task: JoinHandle<()>,
+}
+
+impl TestBroker {
+	/// Starts a broker on a free local port that publishes `reports` on
+	/// `report_topic` to every client that subscribes to it.
+	pub async fn start(report_topic: &str, reports: Vec<serde_json::Value>) -> Result<Self> {
+		let acceptor = TlsAcceptor::from(Arc::new(server_config()?));
+		let listener = TcpListener::bind("127.0.0.1:0").await?;
+		let port = listener.local_addr()?.port();
+		let log = Arc::new(Mutex::new(BrokerLog::default()));
+
+		let (report_topic, session_log) = (report_topic.to_string(), Arc::clone(&log));
+		let task = tokio::spawn(async move {
+			// One client at a time is all the tests need; a reconnect gets the reports again
+			while let Ok((stream, _)) = listener.accept().await {
+				let Ok(stream) = acceptor.accept(stream).await else {
+					continue;
+				};
+				if let Err(e) = serve(stream, &report_topic, &reports, &session_log).await {
+					log::debug!("Test broker session ended: {e}");
+				}
+			}
+		});
+
+		Ok(Self { port, log, task })
+	}
+
+	pub fn log(&self) -> MutexGuard<'_, BrokerLog> {
+		self.log.lock().unwrap()
+	}
+}
+
+impl Drop for TestBroker {
+	fn drop(&mut self) {
+		self.task.abort();
+	}
+}
+
+/// An `EventSink` that keeps every event instead of sending it to a frontend.
+#[derive(Default)]
+pub struct RecordedEvents {
+	events: Mutex<Vec<(String, serde_json::Value)>>,
+	emitted: Notify,
+}
+
+impl RecordedEvents {
+	/// Payloads of the `event`s emitted so far, oldest first.
+	pub fn named(&self, event: &str) -> Vec<serde_json::Value> {
+		self
+			.events
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|(name, _)| name == event)
+			.map(|(_, payload)| payload.clone())
+			.collect()
+	}
+
+	/// Waits until `event` has been emitted at least `count` times.
+	pub async fn wait_for(&self, event: &str, count: usize) {
+		loop {
+			// Created before checking, so an emit in between still wakes us
+			let emitted = self.emitted.notified();
+			if self.named(event).len() >= count {
+				return;
+			}
+			emitted.await;
+		}
+	}
+}
+
+impl EventSink for RecordedEvents {
+	fn emit_event(&self, event: &str, payload: serde_json::Value) -> Result<()> {
+		self
+			.events
+			.lock()
+			.unwrap()
+			.push((event.to_string(), payload));
+		self.emitted.notify_waiters();
+		Ok(())
+	}
+
+	fn state_store(&self) -> Option<PrinterStateStore> {
+		None
+	}
+
+	fn command_history(&self) -> Option<CommandHistory> {
+		None
+	}
+}
+
+/// A throwaway self-signed certificate, like the ones printers present.
+fn server_config() -> Result<rustls::ServerConfig> {
+	let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+	let chain = vec![CertificateDer::from(cert.serialize_der()?)];
+	let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.serialize_private_key_der()));
+
+	let provider = Arc::new(rustls::crypto::ring::default_provider());
+	Ok(
+		rustls::ServerConfig::builder_with_provider(provider)
+			.with_safe_default_protocol_versions()?
+			.with_no_client_auth()
+			.with_single_cert(chain, key)?,
+	)
+}
+
+async fn serve<S>(
+	mut stream: S,
+	report_topic: &str,
+	reports: &[serde_json::Value],
+	log: &Mutex<BrokerLog>,
+) -> Result<()>
+where
+	S: AsyncRead + AsyncWrite + Unpin,
+{
+	let mut buffer = BytesMut::new();
+	loop {
+		let packet = match v4::read(&mut buffer, MAX_PACKET_SIZE) {
+			Ok(packet) => packet,
+			Err(mqttbytes::Error::InsufficientBytes(_)) => {
+				if stream.read_buf(&mut buffer).await? == 0 {
+					return Ok(());
+				}
+				continue;
+			}
+			Err(e) => return Err(anyhow!("Malformed packet from client: {e}")),
+		};
+
+		let mut reply = BytesMut::new();
+		match packet {
+			Packet::Connect(connect) => {
+				let login = connect
+					.login
+					.map(|login| (login.username, login.password))
+					.unwrap_or_default();
+				log.lock().unwrap().logins.push(login);
+				ConnAck::new(ConnectReturnCode::Success, false).write(&mut reply)?;
+			}
+			Packet::Subscribe(subscribe) => {
+				let codes = subscribe
+					.filters
+					.iter()
+					.map(|filter| v4::SubscribeReasonCode::Success(filter.qos))
+					.collect();
+				SubAck::new(subscribe.pkid, codes).write(&mut reply)?;
+
+				let wants_reports = subscribe
+					.filters
+					.iter()
+					.any(|filter| filter.path == report_topic);
+				log
+					.lock()
+					.unwrap()
+					.subscriptions
+					.extend(subscribe.filters.into_iter().map(|filter| filter.path));
+				if wants_reports {
+					for report in reports {
+						Publish::new(report_topic, QoS::AtMostOnce, serde_json::to_vec(report)?)
+							.write(&mut reply)?;
+					}
+				}
+			}
+			Packet::Publish(publish) => {
+				let payload = serde_json::from_slice(&publish.payload).unwrap_or_default();
+				log.lock().unwrap().published.push((publish.topic, payload));
+			}
+			Packet::PingReq => {
+				PingResp.write(&mut reply)?;
+			}
+			Packet::Disconnect => return Ok(()),
+			_ => {}
+		}
+		stream.write_all(&reply).await?;
+	}
+}
@@ -0,0 +1,234 @@
+use anyhow::{anyhow, Result};
+use flate2::read::DeflateDecoder;
+use std::io::Read;
+
+/// Paths where the printer may keep the `.3mf` for the current job.
+///
+/// `gcode_file` points straight at the project when it was started from the
+/// SD card. Jobs sent from Bambu Studio report the G-code inside the project
+/// instead (`/data/Metadata/plate_1.gcode`), and the project itself is stored
+/// under the job name, either in `/cache` or at the root.
+pub fn candidate_paths(gcode_file: &str, subtask_name: &str) -> Vec<String> {
+	let mut paths = Vec::new();
+	if gcode_file.ends_with(".3mf") {
+		// The FTP root is the SD card itself
+		let path = gcode_file.strip_prefix("/sdcard").unwrap_or(gcode_file);
+		paths.push(format!("/{}", path.trim_start_matches('/')));
+	}
+
+	let name = subtask_name.trim();
+	if !name.is_empty() {
+		for dir in ["/cache", ""] {
+			for extension in [".gcode.3mf", ".3mf"] {
+				let path = format!("{dir}/{name}{extension}");
+				if !paths.contains(&path) {
+					paths.push(path);
+				}
+			}
+		}
+	}
+
+	paths
+}
+
+/// Path of the plate preview inside the project, e.g. `Metadata/plate_2.png`
+/// when `gcode_file` is `/data/Metadata/plate_2.gcode`. Defaults to plate 1.
+pub fn plate_image_path(gcode_file: &str) -> String {
+	let plate = gcode_file
+		.rsplit('/')
+		.next()
+		.and_then(|name| name.strip_prefix("plate_"))
+		.and_then(|name| name.strip_suffix(".gcode"))
+		.and_then(|number| number.parse::<u32>().ok())
+		.unwrap_or(1);
+	format!("Metadata/plate_{plate}.png")
+}
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+
+/// Reads one file out of a `.3mf` (a zip archive). Only what slicers write is
+/// supported: stored or deflated entries, no zip64 and no encryption.
+pub fn extract_file(archive: &[u8], name: &str) -> Result<Vec<u8>> {
+	let eocd = (0..=archive.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_LEN))
+		.rev()
+		.find(|&offset| read_u32(archive, offset) == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+		.ok_or_else(|| anyhow!("Not a zip archive"))?;
+	let entries = read_u16(archive, eocd + 10).ok_or_else(truncated)?;
+	let mut offset = read_u32(archive, eocd + 16).ok_or_else(truncated)? as usize;
+
+	for _ in 0..entries {
+		if read_u32(archive, offset) != Some(CENTRAL_HEADER_SIGNATURE) {
+			return Err(anyhow!("Corrupt zip central directory"));
+		}
+		let field = |at: usize| read_u16(archive, offset + at).ok_or_else(truncated);
+		let method = field(10)?;
+		let compressed_len = read_u32(archive, offset + 20).ok_or_else(truncated)? as usize;
+		let len = read_u32(archive, offset + 24).ok_or_else(truncated)? as usize;
+		let name_len = field(28)? as usize;
+		let extra_len = field(30)? as usize;
+		let comment_len = field(32)? as usize;
+		let local_offset = read_u32(archive, offset + 42).ok_or_else(truncated)? as usize;
+		let entry_name = archive
+			.get(offset + 46..offset + 46 + name_len)
+			.ok_or_else(truncated)?;
+
+		if entry_name == name.as_bytes() {
+			return read_entry(archive, local_offset, method, compressed_len, len);
+		}
+		offset += 46 + name_len + extra_len + comment_len;
+	}
+
+	Err(anyhow!("{name} not found in archive"))
+}
+
+fn read_entry(
+	archive: &[u8],
+	offset: usize,
+	method: u16,
+	compressed_len: usize,
+	len: usize,
+) -> Result<Vec<u8>> {
+	if read_u32(archive, offset) != Some(LOCAL_HEADER_SIGNATURE) {
+		return Err(anyhow!("Corrupt zip entry header"));
+	}
+	let name_len = read_u16(archive, offset + 26).ok_or_else(truncated)? as usize;
+	let extra_len = read_u16(archive, offset + 28).ok_or_else(truncated)? as usize;
+	let start = offset + 30 + name_len + extra_len;
+	let data = archive
+		.get(start..start + compressed_len)
+		.ok_or_else(truncated)?;
+
+	match method {
+		0 => Ok(data.to_vec()),
+		8 => {
+			let mut contents = Vec::with_capacity(len);
+			DeflateDecoder::new(data)
+				.read_to_end(&mut contents)
+				.map_err(|e| anyhow!("Failed to inflate zip entry: {e}"))?;
+			Ok(contents)
+		}
+		_ => Err(anyhow!("Unsupported zip compression method {method}")),
+	}
+}
+
+fn truncated() -> anyhow::Error {
+	anyhow!("Truncated zip archive")
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+	let bytes = data.get(offset..offset + 2)?;
+	Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+	let bytes = data.get(offset..offset + 4)?;
+	Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use flate2::write::DeflateEncoder;
+	use flate2::Compression;
+	use std::io::Write;
+
+	/// Builds a zip with one entry per `(name, contents, deflate)`.
+	fn build_zip(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+		let mut archive = Vec::new();
+		let mut central = Vec::new();
+		for (name, contents, deflate) in files {
+			let data = if *deflate {
+				let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+				encoder.write_all(contents).unwrap();
+				encoder.finish().unwrap()
+			} else {
+				contents.to_vec()
+			};
+			let method: u16 = if *deflate { 8 } else { 0 };
+			let local_offset = archive.len() as u32;
+
+			archive.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+			archive.extend([0u8; 4]);
+			archive.extend(method.to_le_bytes());
+			archive.extend([0u8; 8]);
+			archive.extend((data.len() as u32).to_le_bytes());
+			archive.extend((contents.len() as u32).to_le_bytes());
+			archive.extend((name.len() as u16).to_le_bytes());
+			archive.extend(0u16.to_le_bytes());
+			archive.extend(name.as_bytes());
+			archive.extend(&data);
+
+			central.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+			central.extend([0u8; 6]);
+			central.extend(method.to_le_bytes());
+			central.extend([0u8; 8]);
+			central.extend((data.len() as u32).to_le_bytes());
+			central.extend((contents.len() as u32).to_le_bytes());
+			central.extend((name.len() as u16).to_le_bytes());
+			central.extend([0u8; 12]);
+			central.extend(local_offset.to_le_bytes());
+			central.extend(name.as_bytes());
+		}
+
+		let central_offset = archive.len() as u32;
+		archive.extend(&central);
+		archive.extend(END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+		archive.extend([0u8; 4]);
+		archive.extend((files.len() as u16).to_le_bytes());
+		archive.extend((files.len() as u16).to_le_bytes());
+		archive.extend((central.len() as u32).to_le_bytes());
+		archive.extend(central_offset.to_le_bytes());
+		archive.extend(0u16.to_le_bytes());
+		archive
+	}
+
+	#[test]
+	fn test_extract_file() {
+		let png = b"\x89PNG\r\n\x1a\n fake image data fake image data";
+		let archive = build_zip(&[
+			("3D/3dmodel.model", b"<model/>", false),
+			("Metadata/plate_1.png", png, true),
+		]);
+
+		assert_eq!(extract_file(&archive, "Metadata/plate_1.png").unwrap(), png);
+		assert_eq!(
+			extract_file(&archive, "3D/3dmodel.model").unwrap(),
+			b"<model/>"
+		);
+		assert!(extract_file(&archive, "Metadata/plate_2.png").is_err());
+		assert!(extract_file(b"not a zip", "Metadata/plate_1.png").is_err());
+	}
+
+	#[test]
+	fn test_plate_image_path() {
+		assert_eq!(
+			plate_image_path("/data/Metadata/plate_3.gcode"),
+			"Metadata/plate_3.png"
+		);
+		assert_eq!(
+			plate_image_path("/sdcard/benchy.gcode.3mf"),
+			"Metadata/plate_1.png"
+		);
+	}
+
+	#[test]
+	fn test_candidate_paths() {
+		assert_eq!(
+			candidate_paths("/sdcard/enclosure_bracket.gcode.3mf", "enclosure_bracket"),
+			vec![
+				"/enclosure_bracket.gcode.3mf",
+				"/cache/enclosure_bracket.gcode.3mf",
+				"/cache/enclosure_bracket.3mf",
+				"/enclosure_bracket.3mf",
+			]
+		);
+		assert_eq!(
+			candidate_paths("/data/Metadata/plate_1.gcode", "cable_clips")[0],
+			"/cache/cable_clips.gcode.3mf"
+		);
+		assert!(candidate_paths("", "").is_empty());
+	}
+}